@@ -0,0 +1,74 @@
+//! Compares extracting articles with the decoder bounded to the article's own
+//! bzip2 stream against letting it run to the end of the file.
+//!
+//! Uses the dump configured through `WIKI_INDEX_FILE` and `WIKI_ARTICLE_DB`:
+//!
+//! ```sh
+//! cargo bench --bench article_extraction
+//! ```
+
+use std::time::{Duration, Instant};
+
+use wiki_rs::wiki::{
+    article::ArticleDatabase,
+    index::{Index, IndexEntry},
+};
+
+const SAMPLES: usize = 20;
+
+fn measure(article_db: &ArticleDatabase, entries: &[IndexEntry]) -> anyhow::Result<Duration> {
+    let time = Instant::now();
+    for entry in entries {
+        article_db.get_article(entry)?;
+    }
+    Ok(time.elapsed() / entries.len() as u32)
+}
+
+fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    let index_path = std::env::var("WIKI_INDEX_FILE")?;
+    let articles_path = std::env::var("WIKI_ARTICLE_DB")?;
+
+    let index = Index::from_file(&index_path)?;
+    // Without the block cache, so both runs actually decompress every block
+    let article_db = ArticleDatabase::from_file(&articles_path)?.with_cache_budget(0);
+
+    // Spread the samples over the whole file, since the unbounded decoder
+    // is slower the further an article is from the end.
+    let step = (index.size() / SAMPLES).max(1);
+    let bounded: Vec<IndexEntry> = index
+        .entries()
+        .iter()
+        .step_by(step)
+        .take(SAMPLES)
+        .map(|entry| IndexEntry {
+            offset: entry.offset,
+            end_offset: entry.end_offset,
+            page_id: entry.page_id,
+            page_name: entry.page_name.clone(),
+        })
+        .collect();
+    let unbounded: Vec<IndexEntry> = bounded
+        .iter()
+        .map(|entry| IndexEntry {
+            end_offset: None,
+            page_name: entry.page_name.clone(),
+            ..*entry
+        })
+        .collect();
+
+    println!("Extracting {} articles per run", bounded.len());
+
+    let bounded_time = measure(&article_db, &bounded)?;
+    println!("Bounded to stream:   {:.2?} per article", bounded_time);
+
+    let unbounded_time = measure(&article_db, &unbounded)?;
+    println!("Decoded to file end: {:.2?} per article", unbounded_time);
+
+    println!(
+        "Speedup: {:.1}x",
+        unbounded_time.as_secs_f64() / bounded_time.as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+// Bookmarks an article with the button next to its title, and edits,
+// removes and imports bookmarks on the bookmarks page.
+(function () {
+    const toggle = document.querySelector('main[data-visit] .bookmark');
+    if (toggle) {
+        const title = document.querySelector('main[data-visit]').dataset.visit;
+        toggle.addEventListener('click', () => {
+            fetch(`api/bookmarks/toggle?title=${encodeURIComponent(title)}`, { method: 'POST' })
+                .then((response) => response.json())
+                .then(({ bookmarked }) => {
+                    toggle.classList.toggle('bookmarked', bookmarked);
+                    toggle.title = bookmarked ? 'Remove the bookmark' : 'Bookmark this article';
+                    toggle.textContent = bookmarked ? '★ Bookmarked' : '☆ Bookmark';
+                });
+        });
+    }
+
+    // Bookmarks may be of other wikis than the page, so their API calls
+    // are scoped to the wiki of the bookmark
+    const api = (item, action, params) => {
+        const query = new URLSearchParams({ title: item.dataset.title, ...params });
+        const wiki = encodeURIComponent(item.dataset.wiki);
+        return fetch(`/w/${wiki}/api/bookmarks/${action}?${query}`, { method: 'POST' });
+    };
+
+    for (const form of document.querySelectorAll('.bookmark-edit')) {
+        const item = form.closest('li');
+        form.addEventListener('submit', (event) => {
+            event.preventDefault();
+            const params = Object.fromEntries(new FormData(form));
+            api(item, 'update', params).then(() => location.reload());
+        });
+        form.querySelector('.remove').addEventListener('click', () => {
+            api(item, 'remove', {}).then(() => item.remove());
+        });
+    }
+
+    const input = document.querySelector('.import-bookmarks input');
+    if (input) {
+        input.addEventListener('change', () => {
+            const file = input.files[0];
+            if (!file) {
+                return;
+            }
+            fetch('api/bookmarks/import', { method: 'POST', body: file })
+                .then((response) => response.json())
+                .then((result) => {
+                    if (result.error) {
+                        alert(`Failed to import bookmarks: ${result.error}`);
+                    }
+                    location.reload();
+                });
+        });
+    }
+})();
@@ -0,0 +1,54 @@
+// Back and forward buttons in the nav bar, which Alt+Left and Alt+Right
+// press as well. Articles report how far they were scrolled down when they
+// are left to the browsing history, and scroll back there when they are
+// opened with a `#scroll=<position>` fragment, as on startup.
+(function () {
+    const back = document.querySelector('.main-nav .back');
+    const forward = document.querySelector('.main-nav .forward');
+    if (back && forward) {
+        back.addEventListener('click', () => history.back());
+        forward.addEventListener('click', () => history.forward());
+    }
+
+    document.addEventListener('keydown', (event) => {
+        if (!event.altKey || event.ctrlKey || event.metaKey || event.shiftKey) {
+            return;
+        }
+        if (event.key === 'ArrowLeft') {
+            event.preventDefault();
+            history.back();
+        } else if (event.key === 'ArrowRight') {
+            event.preventDefault();
+            history.forward();
+        }
+    });
+
+    const clear = document.querySelector('.clear-history');
+    if (clear) {
+        clear.addEventListener('click', () => {
+            if (confirm('Clear the browsing history?')) {
+                fetch('api/history/clear', { method: 'POST' }).then(() => location.href = 'history');
+            }
+        });
+    }
+
+    const main = document.querySelector('main[data-visit]');
+    const title = main && main.dataset.visit;
+    if (!title) {
+        return;
+    }
+
+    const match = location.hash.match(/^#scroll=(\d+)$/);
+    if (match) {
+        // Images may still change the height of the article until it loads
+        window.addEventListener('load', () => {
+            window.scrollTo(0, Number(match[1]));
+            history.replaceState(null, '', location.pathname + location.search);
+        });
+    }
+
+    window.addEventListener('pagehide', () => {
+        const position = Math.round(window.scrollY);
+        navigator.sendBeacon(`api/history/scroll?title=${encodeURIComponent(title)}&position=${position}`);
+    });
+})();
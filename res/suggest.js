@@ -0,0 +1,93 @@
+// Suggests titles below the search box of the nav bar while typing. The
+// arrow keys select a suggestion, and Enter opens it, or the article whose
+// title matches the query exactly. Otherwise the search form is submitted.
+(function () {
+    const input = document.querySelector('.main-nav input[name="q"]');
+    if (!input) {
+        return;
+    }
+
+    const list = document.createElement('ul');
+    list.className = 'suggestions';
+    list.hidden = true;
+    input.form.appendChild(list);
+
+    let suggestions = [];
+    let selected = -1;
+    let pending = null;
+
+    const normalize = (title) => title.trim().replace(/_/g, ' ').toLowerCase();
+
+    function render() {
+        list.replaceChildren(...suggestions.map((suggestion, i) => {
+            const item = document.createElement('li');
+            item.textContent = suggestion.title;
+            item.classList.toggle('selected', i === selected);
+            // Keeps the focus in the search box, which would hide the list
+            item.addEventListener('mousedown', (event) => event.preventDefault());
+            item.addEventListener('click', () => location.href = suggestion.url);
+            return item;
+        }));
+        list.hidden = suggestions.length === 0;
+    }
+
+    function hide() {
+        suggestions = [];
+        selected = -1;
+        render();
+    }
+
+    input.addEventListener('input', () => {
+        if (pending) {
+            pending.abort();
+        }
+        if (!input.value.trim()) {
+            hide();
+            return;
+        }
+
+        pending = new AbortController();
+        fetch('api/suggest?q=' + encodeURIComponent(input.value), { signal: pending.signal })
+            .then((response) => response.json())
+            .then(([, titles, , urls]) => {
+                suggestions = titles.map((title, i) => ({ title, url: urls[i] }));
+                selected = -1;
+                render();
+            })
+            .catch(() => {});
+    });
+
+    input.addEventListener('keydown', (event) => {
+        switch (event.key) {
+            case 'ArrowDown':
+            case 'ArrowUp': {
+                if (suggestions.length === 0) {
+                    return;
+                }
+                event.preventDefault();
+                // Cycles through the suggestions and the query itself at -1
+                const count = suggestions.length + 1;
+                const step = event.key === 'ArrowDown' ? 1 : count - 1;
+                selected = (selected + 1 + step) % count - 1;
+                render();
+                break;
+            }
+            case 'Enter': {
+                const query = normalize(input.value);
+                const target = selected >= 0
+                    ? suggestions[selected]
+                    : suggestions.find((suggestion) => normalize(suggestion.title) === query);
+                if (target) {
+                    event.preventDefault();
+                    location.href = target.url;
+                }
+                break;
+            }
+            case 'Escape':
+                hide();
+                break;
+        }
+    });
+
+    input.addEventListener('blur', hide);
+})();
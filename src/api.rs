@@ -0,0 +1,288 @@
+use chrono::DateTime;
+use serde::Serialize;
+use urlencoding::encode;
+
+use crate::{
+    bookmarks::Bookmark,
+    history::Visit,
+    renderer::ArticleRenderer,
+    router::{Response, SearchPage},
+    wiki::{
+        article::Article,
+        index::IndexEntry,
+        revision::{Contributor, Revision},
+        siteinfo::SiteInfo,
+    },
+};
+
+// The structs below define the JSON format of the API. They are separate
+// from the wiki types so that refactoring those doesn't change field names
+// that scripts depend on.
+
+#[derive(Serialize)]
+struct ArticleJson<'a> {
+    id: u64,
+    title: &'a str,
+    namespace: i32,
+    redirect: Option<&'a str>,
+    revision: RevisionJson<'a>,
+    wikitext: &'a str,
+    html: &'a str,
+}
+
+#[derive(Serialize)]
+struct RevisionJson<'a> {
+    id: u64,
+    parent_id: Option<u64>,
+    timestamp: String,
+    contributor: ContributorJson<'a>,
+    comment: Option<&'a str>,
+    minor: bool,
+    model: &'a str,
+    format: &'a str,
+    sha1: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ContributorJson<'a> {
+    User { id: u64, name: &'a str },
+    Ip { ip: &'a str },
+    Deleted,
+}
+
+#[derive(Serialize)]
+struct SearchJson<'a> {
+    query: &'a str,
+    /// Number of results on all pages together
+    total: usize,
+    /// Number of results on the pages before this one
+    start: usize,
+    /// Cursor to pass as `cursor` to get the next page, if there is one
+    next: Option<String>,
+    results: Vec<SearchResultJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct SearchResultJson<'a> {
+    wiki: &'a str,
+    title: &'a str,
+    page_id: u64,
+    offset: u64,
+    /// Rank of the result by how well it matches and how important the
+    /// article is, higher is better
+    score: f64,
+    /// The fields below are only set for the first results, whose articles
+    /// are loaded for them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect: Option<&'a str>,
+    /// Heading of the section the query was found in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    section: Option<&'a str>,
+    /// Text around the query, or the start of the article
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<&'a str>,
+}
+
+/// Suggestions in the OpenSearch format that browsers understand: the query,
+/// followed by the titles, their descriptions and the URLs of the articles.
+#[derive(Serialize)]
+struct SuggestionsJson<'a>(&'a str, Vec<&'a str>, Vec<&'a str>, Vec<String>);
+
+#[derive(Serialize)]
+struct SiteInfoJson<'a> {
+    site_name: &'a str,
+    db_name: &'a str,
+    base: &'a str,
+    language: String,
+    generator: &'a str,
+    case: &'a str,
+    article_count: usize,
+    namespaces: Vec<NamespaceJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct NamespaceJson<'a> {
+    key: i32,
+    name: &'a str,
+    case: &'a str,
+}
+
+#[derive(Serialize)]
+struct VisitJson<'a> {
+    wiki: &'a str,
+    title: &'a str,
+    timestamp: String,
+    /// How far the article was scrolled down when it was left, in pixels
+    scroll: u32,
+}
+
+#[derive(Serialize)]
+struct BookmarkToggleJson {
+    bookmarked: bool,
+}
+
+#[derive(Serialize)]
+struct BookmarksImportJson {
+    imported: usize,
+}
+
+#[derive(Serialize)]
+struct ErrorJson<'a> {
+    status: u16,
+    error: &'a str,
+}
+
+impl<'a> From<&'a Revision> for RevisionJson<'a> {
+    fn from(revision: &'a Revision) -> Self {
+        Self {
+            id: revision.id,
+            parent_id: revision.parent_id,
+            timestamp: revision.timestamp.to_rfc3339(),
+            contributor: match &revision.contributor {
+                Contributor::User { id, name } => ContributorJson::User { id: *id, name },
+                Contributor::Ip(ip) => ContributorJson::Ip { ip },
+                Contributor::Deleted => ContributorJson::Deleted,
+            },
+            comment: revision.comment.as_deref(),
+            minor: revision.minor,
+            model: &revision.model,
+            format: &revision.format,
+            sha1: &revision.sha1,
+        }
+    }
+}
+
+/// An article with its HTML, whose links are absolute paths into `wiki` so
+/// that they work wherever the HTML is shown.
+pub fn article_response(article: &Article, wiki: &str) -> Response {
+    let mut renderer = ArticleRenderer::with_base(&format!("/w/{}/", wiki));
+    renderer.render_article_body(article);
+
+    Response::json(
+        200,
+        &ArticleJson {
+            id: article.id,
+            title: &article.title,
+            namespace: article.namespace,
+            redirect: article.redirect.as_deref(),
+            revision: (&article.revision).into(),
+            wikitext: &article.body,
+            html: renderer.html(),
+        },
+    )
+}
+
+pub fn search_response(query: &str, page: &SearchPage) -> Response {
+    let results = page
+        .hits
+        .iter()
+        .map(|hit| {
+            let summary = hit.summary.as_ref();
+            let text_match = summary.and_then(|summary| summary.text_match.as_ref());
+            SearchResultJson {
+                wiki: &hit.wiki.name,
+                title: &hit.result.entry.page_name,
+                page_id: hit.result.entry.page_id,
+                offset: hit.result.entry.offset,
+                score: hit.result.score,
+                description: summary.and_then(|summary| summary.short_description.as_deref()),
+                redirect: summary.and_then(|summary| summary.redirect.as_deref()),
+                section: text_match.and_then(|text_match| text_match.heading.as_deref()),
+                snippet: match (summary, text_match) {
+                    (_, Some(text_match)) => Some(text_match.snippet.as_str()),
+                    (Some(summary), None) if !summary.lead.is_empty() => Some(&summary.lead),
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+
+    Response::json(
+        200,
+        &SearchJson {
+            query,
+            total: page.total,
+            start: page.start,
+            next: page.next.as_ref().map(|cursor| cursor.to_string()),
+            results,
+        },
+    )
+}
+
+pub fn suggest_response(query: &str, wiki: &str, index_entries: &[&IndexEntry]) -> Response {
+    let titles = index_entries
+        .iter()
+        .map(|entry| entry.page_name.as_str())
+        .collect();
+    let descriptions = index_entries.iter().map(|_| "").collect();
+    let urls = index_entries
+        .iter()
+        .map(|entry| format!("/w/{}/article/{}", wiki, encode(&entry.page_name)))
+        .collect();
+
+    Response::json(200, &SuggestionsJson(query, titles, descriptions, urls))
+}
+
+/// Visits of the browsing history, newest first.
+pub fn history_response(visits: &[Visit]) -> Response {
+    let visits: Vec<VisitJson> = visits
+        .iter()
+        .map(|visit| VisitJson {
+            wiki: &visit.wiki,
+            title: &visit.title,
+            timestamp: DateTime::from_timestamp(visit.timestamp, 0)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            scroll: visit.scroll,
+        })
+        .collect();
+
+    Response::json(200, &visits)
+}
+
+/// All bookmarks, which are exported in the same format as they are saved
+/// and imported in rather than a separate one.
+pub fn bookmarks_response(bookmarks: &[Bookmark]) -> Response {
+    Response::json(200, &bookmarks)
+}
+
+pub fn bookmark_toggle_response(bookmarked: bool) -> Response {
+    Response::json(200, &BookmarkToggleJson { bookmarked })
+}
+
+pub fn bookmarks_import_response(imported: usize) -> Response {
+    Response::json(200, &BookmarksImportJson { imported })
+}
+
+pub fn site_info_response(site_info: &SiteInfo, article_count: usize) -> Response {
+    let namespaces = site_info
+        .namespaces
+        .iter()
+        .map(|ns| NamespaceJson {
+            key: ns.key,
+            name: &ns.name,
+            case: ns.case.as_str(),
+        })
+        .collect();
+
+    Response::json(
+        200,
+        &SiteInfoJson {
+            site_name: &site_info.site_name,
+            db_name: &site_info.db_name,
+            base: &site_info.base,
+            language: site_info.language(),
+            generator: &site_info.generator,
+            case: site_info.case.as_str(),
+            article_count,
+            namespaces,
+        },
+    )
+}
+
+pub fn error_response(status: u16, error: &str) -> Response {
+    Response::json(status, &ErrorJson { status, error })
+}
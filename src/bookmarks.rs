@@ -0,0 +1,335 @@
+//! Bookmarked articles, sorted into named reading lists and annotated with
+//! tags and notes. They are saved to a JSON file in the data directory,
+//! which is also the format they are exported and imported in.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::bail;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::storage::{load_json_or_move_aside, save_json_atomically};
+
+/// Name of the bookmarks file in the data directory.
+const BOOKMARKS_FILE: &str = "bookmarks.json";
+
+/// Reading list of bookmarks that weren't put on another one.
+pub const DEFAULT_LIST: &str = "Reading list";
+
+#[derive(Error, Debug)]
+pub enum BookmarksError {
+    #[error("invalid bookmarks to import: {0}")]
+    InvalidImport(String),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Bookmark {
+    /// Name of the dump the article is in
+    pub wiki: String,
+    pub title: String,
+    /// Name of the reading list the bookmark is on
+    #[serde(default = "default_list")]
+    pub list: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: String,
+    /// Unix time the article was bookmarked at, in seconds
+    #[serde(default)]
+    pub added: i64,
+}
+
+pub struct Bookmarks {
+    /// File the bookmarks are saved to, `None` to keep them in memory only
+    path: Option<PathBuf>,
+    /// Bookmarks in the order they were added
+    bookmarks: Mutex<Vec<Bookmark>>,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks from the data directory, of which there are none
+    /// if there is no bookmarks file yet, or if it can't be parsed, see
+    /// [`load_json_or_move_aside`].
+    pub fn load(data_dir: Option<&Path>) -> anyhow::Result<Bookmarks> {
+        let path = data_dir.map(|dir| dir.join(BOOKMARKS_FILE));
+        let bookmarks = match &path {
+            Some(path) => load_json_or_move_aside(path, "bookmarks")?,
+            None => Vec::new(),
+        };
+
+        Ok(Bookmarks {
+            path,
+            bookmarks: Mutex::new(bookmarks),
+        })
+    }
+
+    pub fn is_bookmarked(&self, wiki: &str, title: &str) -> bool {
+        let bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        bookmarks
+            .iter()
+            .any(|bookmark| bookmark.wiki == wiki && bookmark.title == title)
+    }
+
+    /// Bookmarks an article on the default reading list, or removes its
+    /// bookmark if it has one. Returns whether it is bookmarked now.
+    pub fn toggle(&self, wiki: &str, title: &str) -> anyhow::Result<bool> {
+        let mut bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        let bookmarked = match Self::position(&bookmarks, wiki, title) {
+            Some(position) => {
+                bookmarks.remove(position);
+                false
+            }
+            None => {
+                bookmarks.push(Bookmark {
+                    wiki: wiki.to_owned(),
+                    title: title.to_owned(),
+                    list: DEFAULT_LIST.to_owned(),
+                    tags: Vec::new(),
+                    note: String::new(),
+                    added: Utc::now().timestamp(),
+                });
+                true
+            }
+        };
+
+        self.save(&bookmarks)?;
+        Ok(bookmarked)
+    }
+
+    /// Moves the bookmark of an article to a list and replaces its tags and
+    /// note, bookmarking the article first if it isn't yet.
+    pub fn update(
+        &self,
+        wiki: &str,
+        title: &str,
+        list: &str,
+        tags: &[&str],
+        note: &str,
+    ) -> anyhow::Result<()> {
+        let mut bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        let bookmark = Bookmark {
+            wiki: wiki.to_owned(),
+            title: title.to_owned(),
+            list: list.to_owned(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            note: note.trim().to_owned(),
+            added: 0,
+        };
+        Self::insert(&mut bookmarks, bookmark);
+        self.save(&bookmarks)
+    }
+
+    pub fn remove(&self, wiki: &str, title: &str) -> anyhow::Result<()> {
+        let mut bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        if let Some(position) = Self::position(&bookmarks, wiki, title) {
+            bookmarks.remove(position);
+        }
+        self.save(&bookmarks)
+    }
+
+    /// Adds bookmarks exported before, given as a JSON array of them, of
+    /// articles in the dumps named `wikis`. Bookmarks of articles that are
+    /// already bookmarked replace the lists, tags and notes of those.
+    /// Returns the number of bookmarks imported.
+    pub fn import(&self, json: &[u8], wikis: &[&str]) -> anyhow::Result<usize> {
+        let imported: Vec<Bookmark> = match serde_json::from_slice(json) {
+            Ok(imported) => imported,
+            Err(err) => bail!(BookmarksError::InvalidImport(err.to_string())),
+        };
+        if imported
+            .iter()
+            .any(|bookmark| bookmark.wiki.is_empty() || bookmark.title.is_empty())
+        {
+            bail!(BookmarksError::InvalidImport(
+                "bookmarks need a wiki and a title".to_owned()
+            ));
+        }
+        if let Some(bookmark) = imported
+            .iter()
+            .find(|bookmark| !wikis.contains(&bookmark.wiki.as_str()))
+        {
+            bail!(BookmarksError::InvalidImport(format!(
+                "there is no wiki named {:?}",
+                bookmark.wiki
+            )));
+        }
+
+        let mut bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        let count = imported.len();
+        for bookmark in imported {
+            Self::insert(&mut bookmarks, bookmark);
+        }
+        self.save(&bookmarks)?;
+        Ok(count)
+    }
+
+    /// All bookmarks, in the order they were added.
+    pub fn all(&self) -> Vec<Bookmark> {
+        let bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        bookmarks.clone()
+    }
+
+    /// Finds the bookmarks on a list and with a tag, if given, newest first.
+    pub fn filter(&self, list: Option<&str>, tag: Option<&str>) -> Vec<Bookmark> {
+        let bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        bookmarks
+            .iter()
+            .rev()
+            .filter(|bookmark| list.is_none_or(|list| bookmark.list == list))
+            .filter(|bookmark| tag.is_none_or(|tag| bookmark.tags.iter().any(|t| t == tag)))
+            .cloned()
+            .collect()
+    }
+
+    /// Names of the reading lists and of the tags that bookmarks have,
+    /// sorted alphabetically.
+    pub fn lists_and_tags(&self) -> (Vec<String>, Vec<String>) {
+        let bookmarks = self.bookmarks.lock().expect("Bookmarks lock poisoned");
+        let mut lists: Vec<String> = bookmarks.iter().map(|b| b.list.clone()).collect();
+        let mut tags: Vec<String> = bookmarks.iter().flat_map(|b| b.tags.clone()).collect();
+        for names in [&mut lists, &mut tags] {
+            names.sort_unstable();
+            names.dedup();
+        }
+        (lists, tags)
+    }
+
+    fn position(bookmarks: &[Bookmark], wiki: &str, title: &str) -> Option<usize> {
+        bookmarks
+            .iter()
+            .position(|bookmark| bookmark.wiki == wiki && bookmark.title == title)
+    }
+
+    /// Adds a bookmark, or updates the one of the same article while keeping
+    /// the time it was added at.
+    fn insert(bookmarks: &mut Vec<Bookmark>, mut bookmark: Bookmark) {
+        bookmark.list = match bookmark.list.trim() {
+            "" => DEFAULT_LIST.to_owned(),
+            list => list.to_owned(),
+        };
+        let mut tags: Vec<String> = Vec::new();
+        for tag in bookmark.tags.iter().map(|tag| tag.trim()) {
+            if !tag.is_empty() && !tags.iter().any(|known| known == tag) {
+                tags.push(tag.to_owned());
+            }
+        }
+        bookmark.tags = tags;
+        // Imported bookmarks may not say when they were added
+        if bookmark.added == 0 {
+            bookmark.added = Utc::now().timestamp();
+        }
+
+        match Self::position(bookmarks, &bookmark.wiki, &bookmark.title) {
+            Some(position) => {
+                let existing = &mut bookmarks[position];
+                existing.list = bookmark.list;
+                existing.tags = bookmark.tags;
+                existing.note = bookmark.note;
+            }
+            None => bookmarks.push(bookmark),
+        }
+    }
+
+    fn save(&self, bookmarks: &[Bookmark]) -> anyhow::Result<()> {
+        match &self.path {
+            Some(path) => save_json_atomically(path, bookmarks, "bookmarks"),
+            None => Ok(()),
+        }
+    }
+}
+
+fn default_list() -> String {
+    DEFAULT_LIST.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::TempDir;
+
+    fn import_error(bookmarks: &Bookmarks, json: &str) -> String {
+        let err = bookmarks
+            .import(json.as_bytes(), &["en", "de"])
+            .unwrap_err();
+        match err.downcast_ref() {
+            Some(BookmarksError::InvalidImport(reason)) => reason.clone(),
+            None => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn import_adds_and_updates_bookmarks() {
+        let bookmarks = Bookmarks::load(None).unwrap();
+        bookmarks.toggle("en", "Rust").unwrap();
+
+        let json = r#"[
+            {"wiki": "en", "title": "Rust", "list": "Languages", "tags": ["a", " a ", ""]},
+            {"wiki": "de", "title": "Zürich", "note": "Visit"}
+        ]"#;
+        assert_eq!(bookmarks.import(json.as_bytes(), &["en", "de"]).unwrap(), 2);
+
+        let all = bookmarks.all();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].list, "Languages");
+        assert_eq!(all[0].tags, ["a"]);
+        assert_eq!(all[1].list, DEFAULT_LIST);
+        assert_eq!(all[1].note, "Visit");
+        assert!(all[1].added > 0);
+    }
+
+    #[test]
+    fn import_rejects_unknown_wikis() {
+        let bookmarks = Bookmarks::load(None).unwrap();
+        let json = r#"[
+            {"wiki": "en", "title": "Rust"},
+            {"wiki": "<b>fr</b>", "title": "Paris"}
+        ]"#;
+        assert_eq!(
+            import_error(&bookmarks, json),
+            r#"there is no wiki named "<b>fr</b>""#
+        );
+        // Nothing is imported if any of the bookmarks is invalid
+        assert!(bookmarks.all().is_empty());
+    }
+
+    #[test]
+    fn import_rejects_invalid_bookmarks() {
+        let bookmarks = Bookmarks::load(None).unwrap();
+        for json in [
+            r#"[{"wiki": "en", "title": ""}]"#,
+            r#"[{"wiki": "", "title": "Rust"}]"#,
+        ] {
+            assert_eq!(
+                import_error(&bookmarks, json),
+                "bookmarks need a wiki and a title"
+            );
+        }
+        for json in [r#"{"wiki": "en"}"#, r#"[{"title": "Rust"}]"#, "["] {
+            import_error(&bookmarks, json);
+        }
+        assert!(bookmarks.all().is_empty());
+    }
+
+    #[test]
+    fn unparsable_bookmarks_are_moved_aside() {
+        let dir = TempDir::new("bookmarks-unparsable");
+        fs::write(dir.0.join(BOOKMARKS_FILE), "[{]").unwrap();
+
+        let bookmarks = Bookmarks::load(Some(&dir.0)).unwrap();
+        assert!(bookmarks.all().is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.0.join("bookmarks.json.bad")).unwrap(),
+            "[{]"
+        );
+
+        bookmarks.toggle("en", "Rust").unwrap();
+        let bookmarks = Bookmarks::load(Some(&dir.0)).unwrap();
+        assert!(bookmarks.is_bookmarked("en", "Rust"));
+    }
+}
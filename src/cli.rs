@@ -0,0 +1,584 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::bail;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use rayon::{
+    prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    api,
+    config::DumpConfig,
+    library::Wiki,
+    renderer::ArticleRenderer,
+    wiki::{
+        article::{Article, ArticleDatabase, ArticleError},
+        index::{Index, IndexEntry},
+        links::{LinkIndex, PageRecord, CATEGORY_NAMESPACE},
+        ranking::{self, PageLinks, Ranks},
+        revisions::RevisionIndex,
+    },
+};
+
+/// Most titles printed by `search`.
+const MAX_SEARCH_RESULTS: usize = 100;
+
+#[derive(Parser)]
+#[command(version, about = "A high-performance reader for Wikipedia dumps")]
+pub struct Cli {
+    /// Config file to use instead of the one in the default location
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Name of the configured dump to use instead of the default one
+    #[arg(long, global = true, value_name = "NAME")]
+    pub dump: Option<String>,
+
+    /// Serve the pages over HTTP on this address instead of opening a window
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Parses the arguments, exiting with a usage error if they are invalid.
+    pub fn parse_args() -> Cli {
+        let cli = Cli::parse();
+        if cli.serve.is_some() && cli.command.is_some() {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--serve cannot be used with a subcommand",
+                )
+                .exit();
+        }
+        cli
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Query(QueryCommand),
+
+    #[command(flatten)]
+    Build(BuildCommand),
+}
+
+/// Commands that read from a loaded dump.
+#[derive(Subcommand)]
+pub enum QueryCommand {
+    /// Print the titles of articles starting with a prefix
+    Search { prefix: String },
+
+    /// Print the latest revision of an article
+    Get {
+        title: String,
+
+        #[arg(long, value_enum, default_value_t = Format::Wikitext)]
+        format: Format,
+    },
+
+    /// Print statistics about the dump
+    Stats,
+}
+
+/// Commands that build the files a dump is loaded with, and so load only
+/// the parts of it they need.
+#[derive(Subcommand)]
+pub enum BuildCommand {
+    /// Build an index file by scanning the article database
+    #[command(name = "build-index")]
+    Index {
+        /// Path of the index file to write
+        output: String,
+
+        /// Also write a revisions file to this path, to open revisions by
+        /// their ids alone
+        #[arg(long, value_name = "PATH")]
+        revisions: Option<String>,
+    },
+
+    /// Build a ranks file from the links between articles, to rank search
+    /// results by the importance of articles
+    #[command(name = "build-ranks")]
+    Ranks {
+        /// Path of the ranks file to write
+        output: String,
+    },
+
+    /// Build a links file with the categories of pages and the links between
+    /// them, to filter searches by
+    #[command(name = "build-links")]
+    Links {
+        /// Path of the links file to write
+        output: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Wikitext,
+    Html,
+    Text,
+    Json,
+}
+
+/// Runs a command that reads from a loaded dump and prints to stdout.
+pub fn run(command: QueryCommand, wiki: &Wiki) -> anyhow::Result<()> {
+    // Pipes like `| head` may close stdout early, which is not an error
+    match execute(command, wiki) {
+        Err(err)
+            if err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn execute(command: QueryCommand, wiki: &Wiki) -> anyhow::Result<()> {
+    let mut out = io::stdout().lock();
+
+    match command {
+        QueryCommand::Search { prefix } => {
+            for result in wiki.index.search(&prefix, MAX_SEARCH_RESULTS) {
+                writeln!(out, "{}", result.entry.page_name)?;
+            }
+        }
+        QueryCommand::Get { title, format } => {
+            let title = wiki.site_info.normalize_title(&title);
+            let entry = match wiki.index.find_article_exact(&title) {
+                Some(entry) => entry,
+                None => bail!(ArticleError::ArticleNotFound),
+            };
+            let article = wiki.article_db.get_article(entry)?;
+
+            match format {
+                Format::Wikitext => writeln!(out, "{}", article.body)?,
+                Format::Json => out.write_all(&api::article_response(&article, &wiki.name).body)?,
+                Format::Html | Format::Text => {
+                    let mut renderer = ArticleRenderer::new();
+                    renderer.render_article_body(&article);
+                    match format {
+                        Format::Html => writeln!(out, "{}", renderer.html())?,
+                        _ => writeln!(out, "{}", html_to_text(renderer.html()))?,
+                    }
+                }
+            }
+        }
+        QueryCommand::Stats => print_stats(&mut out, wiki)?,
+    }
+
+    Ok(())
+}
+
+/// Runs a command that builds a file for a dump.
+pub fn build(
+    command: BuildCommand,
+    dump: &DumpConfig,
+    cache_budget: Option<usize>,
+) -> anyhow::Result<()> {
+    // The files being built may replace the configured ones, which may not
+    // even exist yet
+    let load_without_built_files = || {
+        let mut dump = dump.clone();
+        dump.ranks = None;
+        dump.pageviews = None;
+        dump.links = None;
+        Wiki::load(&dump, cache_budget)
+    };
+
+    match command {
+        BuildCommand::Index { output, revisions } => {
+            build_index(&Wiki::open_article_db(dump)?, &output, revisions.as_deref())
+        }
+        BuildCommand::Ranks { output } => build_ranks(&load_without_built_files()?, &output),
+        BuildCommand::Links { output } => build_links(&load_without_built_files()?, &output),
+    }
+}
+
+fn print_stats(out: &mut impl Write, wiki: &Wiki) -> anyhow::Result<()> {
+    let site_info = &wiki.site_info;
+
+    let mut namespaces: BTreeMap<i32, usize> = BTreeMap::new();
+    let mut streams = 0;
+    let mut last_offset = None;
+    for entry in wiki.index.entries() {
+        let namespace = site_info.namespace_of(&entry.page_name);
+        *namespaces
+            .entry(namespace.map_or(0, |ns| ns.key))
+            .or_default() += 1;
+
+        if last_offset != Some(entry.offset) {
+            streams += 1;
+            last_offset = Some(entry.offset);
+        }
+    }
+
+    writeln!(out, "Dump:      {}", wiki.name)?;
+    writeln!(
+        out,
+        "Site:      {} ({})",
+        site_info.site_name, site_info.db_name
+    )?;
+    writeln!(out, "Language:  {}", site_info.language())?;
+    writeln!(out, "Generator: {}", site_info.generator)?;
+    writeln!(out, "Pages:     {}", wiki.index.size())?;
+    writeln!(out, "Streams:   {}", streams)?;
+    writeln!(
+        out,
+        "Size:      {:.1} MiB compressed",
+        wiki.article_db.size() as f64 / (1024.0 * 1024.0)
+    )?;
+    writeln!(out, "Pages by namespace:")?;
+    for (key, count) in namespaces {
+        let name = match site_info.namespaces.iter().find(|ns| ns.key == key) {
+            Some(ns) if !ns.name.is_empty() => ns.name.as_str(),
+            _ => "(Main)",
+        };
+        writeln!(out, "  {:>5} {:<24} {}", key, name, count)?;
+    }
+
+    Ok(())
+}
+
+/// Scans every stream of the article database for its pages and writes them
+/// out as an index file, and their revisions as a revisions file if a path
+/// is given for it. Streams are decompressed in parallel.
+fn build_index(
+    article_db: &ArticleDatabase,
+    output: &str,
+    revisions_output: Option<&str>,
+) -> anyhow::Result<()> {
+    let offsets = article_db.stream_offsets();
+    eprintln!("Found {} streams, listing pages ...", offsets.len());
+
+    let streams = offsets
+        .par_iter()
+        .enumerate()
+        .map(|(i, &offset)| {
+            let end_offset = offsets.get(i + 1).copied();
+            Ok((
+                offset,
+                end_offset,
+                article_db.list_pages(offset, end_offset)?,
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut entries = Vec::new();
+    let mut revisions = Vec::new();
+    for (offset, end_offset, pages) in streams {
+        for page in pages {
+            if revisions_output.is_some() {
+                revisions.extend(page.revisions.iter().map(|&revision| (revision, page.id)));
+            }
+            entries.push(IndexEntry {
+                offset,
+                end_offset,
+                page_id: page.id,
+                page_name: page.title,
+            });
+        }
+    }
+
+    let index = Index::from_entries(entries);
+    index.write_to_file(output)?;
+    eprintln!("Wrote {} pages to {}", index.size(), output);
+
+    if let Some(revisions_output) = revisions_output {
+        RevisionIndex::write_to_file(revisions_output, &revisions)?;
+        eprintln!(
+            "Wrote {} revisions to {}",
+            revisions.len(),
+            revisions_output
+        );
+    }
+
+    Ok(())
+}
+
+/// Scans every stream of the article database for the links between
+/// articles and writes out the ranks computed from them. Streams are
+/// decompressed in parallel.
+fn build_ranks(wiki: &Wiki, output: &str) -> anyhow::Result<()> {
+    let entries = wiki.index.entries();
+    let site_info = &wiki.site_info;
+
+    let titles = TitleLookup::new(entries);
+    // Only links to articles in the main namespace count
+    let resolve_link = |target: &str| {
+        if target.starts_with(':') {
+            return None;
+        }
+        let title = site_info.normalize_title(target);
+        match site_info.namespace_of(&title) {
+            Some(ns) if ns.key != 0 => None,
+            _ => titles.position(entries, &title),
+        }
+    };
+
+    let pages = scan_articles(wiki, |article| {
+        if article.namespace != 0 {
+            return None;
+        }
+
+        let page = titles.position(entries, &article.title)?;
+        let redirect = article.redirect.as_deref().and_then(resolve_link);
+        let links = match article.redirect {
+            Some(_) => Vec::new(),
+            None => ranking::extract_links(&article.body)
+                .into_iter()
+                .filter_map(resolve_link)
+                .collect(),
+        };
+        Some((
+            article.id,
+            PageLinks {
+                page,
+                redirect,
+                links,
+            },
+        ))
+    })?;
+
+    let mut articles = Vec::new();
+    let mut page_links = Vec::new();
+    for (page_id, links) in pages {
+        if links.redirect.is_none() {
+            articles.push((links.page, page_id));
+        }
+        page_links.push(links);
+    }
+    eprintln!(
+        "Found {} links between {} articles, computing ranks ...",
+        page_links
+            .iter()
+            .map(|page| page.links.len())
+            .sum::<usize>(),
+        articles.len()
+    );
+
+    Ranks::compute(entries.len(), &page_links, &articles).write_to_file(output)?;
+    eprintln!(
+        "Wrote the ranks of {} articles to {}",
+        articles.len(),
+        output
+    );
+
+    Ok(())
+}
+
+/// Scans every stream of the article database for the categories of pages
+/// and the pages they link to, and writes them out as a links file. Streams
+/// are decompressed in parallel.
+fn build_links(wiki: &Wiki, output: &str) -> anyhow::Result<()> {
+    let entries = wiki.index.entries();
+    let site_info = &wiki.site_info;
+    let titles = TitleLookup::new(entries);
+
+    let records = scan_articles(wiki, |article| {
+        let mut record = PageRecord {
+            page_id: article.id,
+            links: Vec::new(),
+            categories: Vec::new(),
+        };
+
+        for target in ranking::extract_links(&article.body) {
+            // `[[Category:...]]` puts the page into a category, while
+            // `[[:Category:...]]` only links to it
+            let (is_link, target) = match target.strip_prefix(':') {
+                Some(target) => (true, target),
+                None => (false, target),
+            };
+            let title = site_info.normalize_title(target);
+            match site_info.namespace_of(&title) {
+                Some(ns) if ns.key == CATEGORY_NAMESPACE && !is_link => {
+                    let name = site_info.normalize_title(&title[ns.name.len() + 1..]);
+                    record.categories.push(name);
+                }
+                _ => {
+                    if let Some(position) = titles.position(entries, &title) {
+                        record.links.push(entries[position as usize].page_id);
+                    }
+                }
+            }
+        }
+
+        record.links.sort_unstable();
+        record.links.dedup();
+        record.categories.sort_unstable();
+        record.categories.dedup();
+        Some(record)
+    })?;
+
+    eprintln!(
+        "Found {} links and {} category memberships of {} pages",
+        records
+            .iter()
+            .map(|record| record.links.len())
+            .sum::<usize>(),
+        records
+            .iter()
+            .map(|record| record.categories.len())
+            .sum::<usize>(),
+        records.len()
+    );
+    LinkIndex::write_to_file(output, &records)?;
+    eprintln!("Wrote the links of {} pages to {}", records.len(), output);
+
+    Ok(())
+}
+
+/// Loads the latest revision of every page in the article database and
+/// maps it, decompressing the streams in parallel.
+fn scan_articles<T: Send>(
+    wiki: &Wiki,
+    map: impl Fn(Article) -> Option<T> + Sync,
+) -> anyhow::Result<Vec<T>> {
+    let offsets = wiki.article_db.stream_offsets();
+    eprintln!("Found {} streams, listing links ...", offsets.len());
+
+    let streams = offsets
+        .par_iter()
+        .enumerate()
+        .map(|(i, &offset)| {
+            let end_offset = offsets.get(i + 1).copied();
+            let articles = wiki.article_db.list_articles(offset, end_offset)?;
+            Ok(articles.into_iter().filter_map(&map).collect::<Vec<_>>())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(streams.into_iter().flatten().collect())
+}
+
+/// Finds the positions of index entries by their exact titles, which link
+/// targets are resolved to.
+struct TitleLookup {
+    /// Positions of the entries ordered by title
+    by_title: Vec<u32>,
+}
+
+impl TitleLookup {
+    fn new(entries: &[IndexEntry]) -> TitleLookup {
+        let mut by_title: Vec<u32> = (0..entries.len() as u32).collect();
+        by_title.par_sort_unstable_by(|&left, &right| {
+            entries[left as usize]
+                .page_name
+                .cmp(&entries[right as usize].page_name)
+        });
+        TitleLookup { by_title }
+    }
+
+    fn position(&self, entries: &[IndexEntry], title: &str) -> Option<u32> {
+        self.by_title
+            .binary_search_by(|&idx| entries[idx as usize].page_name.as_str().cmp(title))
+            .ok()
+            .map(|idx| self.by_title[idx])
+    }
+}
+
+/// Strips the tags from rendered article HTML, keeping line breaks after
+/// block elements so paragraphs stay apart.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&unescape_html(&rest[..start]));
+
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = rest[start + 1..end].trim_matches('/');
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        if matches!(
+            tag_name,
+            "p" | "br" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "div"
+        ) && !text.ends_with("\n\n")
+        {
+            text.push('\n');
+        }
+
+        rest = &rest[end + 1..];
+    }
+    text.push_str(&unescape_html(rest));
+
+    text.trim().to_owned()
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["wiki-rs"].iter().chain(args)).expect("arguments parse")
+    }
+
+    #[test]
+    fn query_commands_are_told_apart_from_build_commands() {
+        assert!(matches!(
+            parse(&["search", "Rust"]).command,
+            Some(Command::Query(QueryCommand::Search { prefix })) if prefix == "Rust"
+        ));
+        assert!(matches!(
+            parse(&["--dump", "de", "get", "Rust", "--format", "json"]).command,
+            Some(Command::Query(QueryCommand::Get {
+                format: Format::Json,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            parse(&["stats"]).command,
+            Some(Command::Query(QueryCommand::Stats))
+        ));
+        assert!(matches!(
+            parse(&["build-index", "index.txt"]).command,
+            Some(Command::Build(BuildCommand::Index { output, revisions: None }))
+                if output == "index.txt"
+        ));
+        assert!(matches!(
+            parse(&["build-index", "index.txt", "--revisions", "revisions.txt"]).command,
+            Some(Command::Build(BuildCommand::Index { revisions: Some(revisions), .. }))
+                if revisions == "revisions.txt"
+        ));
+        assert!(matches!(
+            parse(&["build-ranks", "ranks.txt"]).command,
+            Some(Command::Build(BuildCommand::Ranks { .. }))
+        ));
+        assert!(matches!(
+            parse(&["build-links", "links.txt"]).command,
+            Some(Command::Build(BuildCommand::Links { .. }))
+        ));
+        assert!(parse(&[]).command.is_none());
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        assert!(Cli::try_parse_from(["wiki-rs", "build"]).is_err());
+        assert!(Cli::try_parse_from(["wiki-rs", "build-index"]).is_err());
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Name of the dump configured through environment variables.
+const ENV_DUMP_NAME: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone)]
+pub struct DumpConfig {
+    pub name: String,
+    pub index: PathBuf,
+    pub articles: PathBuf,
+    /// Directory with the media files that articles embed.
+    pub media: Option<PathBuf>,
+    /// Language code, overriding the one derived from the dump's base URL.
+    pub language: Option<String>,
+    /// Index and article database of a second dump of the same wiki.
+    pub compare: Option<(PathBuf, PathBuf)>,
+    /// Ranks file written by `build-ranks`, to rank search results by.
+    pub ranks: Option<PathBuf>,
+    /// File with the pageviews of articles, to rank search results by.
+    pub pageviews: Option<PathBuf>,
+    /// Links file written by `build-links`, to filter searches by categories
+    /// and links.
+    pub links: Option<PathBuf>,
+    /// Revisions file written by `build-index --revisions`, to open
+    /// revisions by their ids alone.
+    pub revisions: Option<PathBuf>,
+    /// Article shown at the root URL of the dump, the article of the day if
+    /// the config names none
+    pub start_page: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Configured dumps, with the default dump first.
+    pub dumps: Vec<DumpConfig>,
+    /// Memory in bytes for caching decompressed article blocks, per dump.
+    pub cache_budget: Option<usize>,
+    pub theme: Theme,
+    /// Directory for the data of the app, like the browsing history. `None`
+    /// keeps it in memory only.
+    pub data_dir: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("config file {0} does not exist")]
+    FileNotFound(String),
+
+    #[error("invalid config file {0}: {1}")]
+    InvalidFile(String, String),
+
+    #[error("missing required setting `{0}`")]
+    MissingKey(String),
+
+    #[error("invalid value for `{0}`: {1}")]
+    InvalidValue(String, String),
+
+    #[error("no dump named `{0}` is configured")]
+    UnknownDump(String),
+
+    #[error("no dump is configured, create {0} or set WIKI_INDEX_FILE and WIKI_ARTICLE_DB")]
+    NotConfigured(String),
+}
+
+// The structs below mirror the TOML file. Unknown keys are rejected so that
+// typos are reported instead of silently ignored.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_dump: Option<String>,
+    start_page: Option<String>,
+    data_dir: Option<PathBuf>,
+    #[serde(default)]
+    theme: Theme,
+    #[serde(default)]
+    cache: CacheSection,
+    #[serde(default)]
+    dumps: BTreeMap<String, DumpSection>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CacheSection {
+    /// Cache size in MiB.
+    size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DumpSection {
+    index: PathBuf,
+    articles: PathBuf,
+    media: Option<PathBuf>,
+    language: Option<String>,
+    compare_index: Option<PathBuf>,
+    compare_articles: Option<PathBuf>,
+    ranks: Option<PathBuf>,
+    pageviews: Option<PathBuf>,
+    links: Option<PathBuf>,
+    revisions: Option<PathBuf>,
+    start_page: Option<String>,
+}
+
+impl Config {
+    /// Loads the config file at `path`, or from the default location if no
+    /// path is given. Without a config file, the dump is configured through
+    /// the `WIKI_*` environment variables instead.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let path = match path {
+            Some(path) if !path.is_file() => {
+                bail!(ConfigError::FileNotFound(path.display().to_string()))
+            }
+            Some(path) => Some(path.to_owned()),
+            None => Self::default_path().filter(|path| path.is_file()),
+        };
+
+        match path {
+            Some(path) => Self::from_file(&path),
+            None => Self::from_env(),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/wiki-rs/config.toml` on Linux, and the matching
+    /// config directory on other platforms.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("wiki-rs").join("config.toml"))
+    }
+
+    /// `$XDG_DATA_HOME/wiki-rs` on Linux, and the matching data directory
+    /// on other platforms.
+    fn default_data_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("wiki-rs"))
+    }
+
+    fn from_file(path: &Path) -> anyhow::Result<Config> {
+        Self::parse(&fs::read_to_string(path)?, path)
+    }
+
+    /// Parses the text of the config file at `path`.
+    fn parse(text: &str, path: &Path) -> anyhow::Result<Config> {
+        let file: ConfigFile = toml::from_str(text)
+            .map_err(|err| ConfigError::InvalidFile(path.display().to_string(), err.to_string()))?;
+
+        // Relative paths are relative to the config file
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let resolve = |path: PathBuf| base_dir.join(path);
+
+        if let Some(start_page) = &file.start_page {
+            Self::validate_start_page("start_page", start_page)?;
+        }
+
+        let mut dumps = Vec::new();
+        for (name, dump) in file.dumps {
+            let key = |field: &str| format!("dumps.{}.{}", name, field);
+
+            // Names are part of the URLs of the dump's pages
+            let valid_name = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|chr| chr.is_ascii_alphanumeric() || chr == '-' || chr == '_');
+            if !valid_name {
+                bail!(ConfigError::InvalidValue(
+                    format!("dumps.{}", name),
+                    "dump names may only contain letters, digits, `-` and `_`".to_owned()
+                ));
+            }
+
+            if let Some(language) = &dump.language {
+                Self::validate_language(&key("language"), language)?;
+            }
+            let compare = match (dump.compare_index, dump.compare_articles) {
+                (Some(index), Some(articles)) => Some((resolve(index), resolve(articles))),
+                (None, None) => None,
+                (Some(_), None) => bail!(ConfigError::MissingKey(key("compare_articles"))),
+                (None, Some(_)) => bail!(ConfigError::MissingKey(key("compare_index"))),
+            };
+            if let Some(media) = &dump.media {
+                if !resolve(media.clone()).is_dir() {
+                    bail!(ConfigError::InvalidValue(
+                        key("media"),
+                        format!("{} is not a directory", media.display())
+                    ));
+                }
+            }
+
+            let start_page = dump.start_page.or_else(|| file.start_page.clone());
+            if let Some(start_page) = &start_page {
+                Self::validate_start_page(&key("start_page"), start_page)?;
+            }
+
+            dumps.push(DumpConfig {
+                name,
+                index: resolve(dump.index),
+                articles: resolve(dump.articles),
+                media: dump.media.map(resolve),
+                language: dump.language,
+                compare,
+                ranks: dump.ranks.map(resolve),
+                pageviews: dump.pageviews.map(resolve),
+                links: dump.links.map(resolve),
+                revisions: dump.revisions.map(resolve),
+                start_page,
+            });
+        }
+
+        if dumps.is_empty() {
+            bail!(ConfigError::MissingKey("dumps".to_owned()));
+        }
+        let mut config = Config {
+            dumps,
+            cache_budget: file
+                .cache
+                .size
+                .map(|size| Self::cache_budget("cache.size", size))
+                .transpose()?,
+            theme: file.theme,
+            data_dir: file.data_dir.map(resolve).or_else(Self::default_data_dir),
+        };
+        if let Some(default_dump) = &file.default_dump {
+            if config.set_default_dump(default_dump).is_err() {
+                bail!(ConfigError::InvalidValue(
+                    "default_dump".to_owned(),
+                    format!("no dump named `{}` is configured", default_dump)
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn from_env() -> anyhow::Result<Config> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Configures the dump through the variables that `env_var` looks up.
+    fn from_vars(env_var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Config> {
+        let var = |key: &str| env_var(key).map(PathBuf::from);
+
+        if var("WIKI_INDEX_FILE").is_none() && var("WIKI_ARTICLE_DB").is_none() {
+            let path = Self::default_path().unwrap_or_else(|| PathBuf::from("config.toml"));
+            bail!(ConfigError::NotConfigured(path.display().to_string()));
+        }
+
+        let index = var("WIKI_INDEX_FILE")
+            .ok_or_else(|| ConfigError::MissingKey("WIKI_INDEX_FILE".to_owned()))?;
+        let articles = var("WIKI_ARTICLE_DB")
+            .ok_or_else(|| ConfigError::MissingKey("WIKI_ARTICLE_DB".to_owned()))?;
+        let compare = match (
+            var("WIKI_COMPARE_INDEX_FILE"),
+            var("WIKI_COMPARE_ARTICLE_DB"),
+        ) {
+            (Some(index), Some(articles)) => Some((index, articles)),
+            _ => None,
+        };
+
+        let cache_budget = match env_var("WIKI_CACHE_SIZE") {
+            Some(size) => match size.parse::<usize>() {
+                Ok(size) => Some(Self::cache_budget("WIKI_CACHE_SIZE", size)?),
+                Err(err) => bail!(ConfigError::InvalidValue(
+                    "WIKI_CACHE_SIZE".to_owned(),
+                    err.to_string()
+                )),
+            },
+            None => None,
+        };
+
+        Ok(Config {
+            dumps: vec![DumpConfig {
+                name: ENV_DUMP_NAME.to_owned(),
+                index,
+                articles,
+                media: None,
+                language: None,
+                compare,
+                ranks: var("WIKI_RANKS_FILE"),
+                pageviews: var("WIKI_PAGEVIEWS_FILE"),
+                links: var("WIKI_LINKS_FILE"),
+                revisions: var("WIKI_REVISIONS_FILE"),
+                start_page: None,
+            }],
+            cache_budget,
+            theme: Theme::default(),
+            data_dir: var("WIKI_DATA_DIR").or_else(Self::default_data_dir),
+        })
+    }
+
+    /// Converts the cache size in MiB set by `key` into bytes.
+    fn cache_budget(key: &str, size: usize) -> Result<usize, ConfigError> {
+        size.checked_mul(1024 * 1024).ok_or_else(|| {
+            ConfigError::InvalidValue(
+                key.to_owned(),
+                format!("{} MiB is more memory than can be addressed", size),
+            )
+        })
+    }
+
+    fn validate_start_page(key: &str, start_page: &str) -> anyhow::Result<()> {
+        if start_page.trim().is_empty() {
+            bail!(ConfigError::InvalidValue(
+                key.to_owned(),
+                "must not be empty".to_owned()
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_language(key: &str, language: &str) -> anyhow::Result<()> {
+        let valid = !language.is_empty()
+            && language
+                .chars()
+                .all(|chr| chr.is_ascii_lowercase() || chr.is_ascii_digit() || chr == '-');
+        if !valid {
+            bail!(ConfigError::InvalidValue(
+                key.to_owned(),
+                format!("`{}` is not a language code like `en`", language)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Moves the dump with the given name to the front, making it the
+    /// default dump.
+    pub fn set_default_dump(&mut self, name: &str) -> anyhow::Result<()> {
+        match self.dumps.iter().position(|dump| dump.name == name) {
+            Some(idx) => {
+                let dump = self.dumps.remove(idx);
+                self.dumps.insert(0, dump);
+                Ok(())
+            }
+            None => bail!(ConfigError::UnknownDump(name.to_owned())),
+        }
+    }
+
+    pub fn default_dump(&self) -> &DumpConfig {
+        &self.dumps[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env};
+
+    use super::*;
+
+    fn parse(text: &str) -> anyhow::Result<Config> {
+        Config::parse(text, Path::new("/etc/wiki-rs/config.toml"))
+    }
+
+    fn config_error(text: &str) -> ConfigError {
+        let err = parse(text).expect_err("config is rejected");
+        match err.downcast() {
+            Ok(err) => err,
+            Err(err) => panic!("unexpected error {}", err),
+        }
+    }
+
+    fn from_vars(vars: &[(&str, &str)]) -> anyhow::Result<Config> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Config::from_vars(|key| vars.get(key).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn dumps_are_parsed() {
+        let config = parse(&format!(
+            r#"
+            default_dump = "de"
+            start_page = "Main Page"
+            data_dir = "data"
+            theme = "dark"
+
+            [cache]
+            size = 16
+
+            [dumps.en]
+            index = "en/index.txt"
+            articles = "/dumps/en.xml.bz2"
+            language = "en"
+            compare_index = "old/index.txt"
+            compare_articles = "old/articles.xml.bz2"
+            links = "en/links.txt"
+            revisions = "en/revisions.txt"
+
+            [dumps.de]
+            index = "de/index.txt"
+            articles = "de/articles.xml.bz2"
+            media = {:?}
+            start_page = "Hauptseite"
+            "#,
+            env::temp_dir()
+        ))
+        .unwrap();
+
+        let names: Vec<&str> = config.dumps.iter().map(|dump| dump.name.as_str()).collect();
+        assert_eq!(names, ["de", "en"]);
+        assert_eq!(config.cache_budget, Some(16 * 1024 * 1024));
+        assert_eq!(config.theme, Theme::Dark);
+        assert_eq!(config.data_dir, Some(PathBuf::from("/etc/wiki-rs/data")));
+
+        let (de, en) = (&config.dumps[0], &config.dumps[1]);
+        assert_eq!(de.start_page.as_deref(), Some("Hauptseite"));
+        assert_eq!(de.media, Some(env::temp_dir()));
+        assert_eq!(en.index, PathBuf::from("/etc/wiki-rs/en/index.txt"));
+        assert_eq!(en.articles, PathBuf::from("/dumps/en.xml.bz2"));
+        assert_eq!(en.language.as_deref(), Some("en"));
+        assert_eq!(en.start_page.as_deref(), Some("Main Page"));
+        assert_eq!(
+            en.compare,
+            Some((
+                PathBuf::from("/etc/wiki-rs/old/index.txt"),
+                PathBuf::from("/etc/wiki-rs/old/articles.xml.bz2")
+            ))
+        );
+        assert_eq!(en.links, Some(PathBuf::from("/etc/wiki-rs/en/links.txt")));
+        assert_eq!(
+            en.revisions,
+            Some(PathBuf::from("/etc/wiki-rs/en/revisions.txt"))
+        );
+        assert_eq!(en.ranks, None);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for text in [
+            "[dumps.en]\nindex = \"i\"\narticles = \"a\"\nindx = \"typo\"",
+            "theme = \"light\"\nthem = \"dark\"\n[dumps.en]\nindex = \"i\"\narticles = \"a\"",
+            "[cache]\nsize_mb = 1\n[dumps.en]\nindex = \"i\"\narticles = \"a\"",
+        ] {
+            assert!(
+                matches!(config_error(text), ConfigError::InvalidFile(_, _)),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_dump_names_are_rejected() {
+        for name in ["\"en wiki\"", "\"en/wiki\"", "\"ü\"", "\"\""] {
+            let text = format!("[dumps.{}]\nindex = \"i\"\narticles = \"a\"", name);
+            assert!(
+                matches!(config_error(&text), ConfigError::InvalidValue(..)),
+                "{}",
+                name
+            );
+        }
+        assert!(parse("[dumps.en-wiki_2]\nindex = \"i\"\narticles = \"a\"").is_ok());
+    }
+
+    #[test]
+    fn invalid_languages_are_rejected() {
+        for language in ["", "EN", "en_US", "de ch"] {
+            let text = format!(
+                "[dumps.en]\nindex = \"i\"\narticles = \"a\"\nlanguage = {:?}",
+                language
+            );
+            assert!(
+                matches!(config_error(&text), ConfigError::InvalidValue(key, _) if key == "dumps.en.language"),
+                "{:?}",
+                language
+            );
+        }
+        for language in ["en", "zh-min-nan", "be-x-old"] {
+            let text = format!(
+                "[dumps.en]\nindex = \"i\"\narticles = \"a\"\nlanguage = {:?}",
+                language
+            );
+            assert!(parse(&text).is_ok(), "{:?}", language);
+        }
+    }
+
+    #[test]
+    fn incomplete_configs_are_rejected() {
+        assert!(matches!(config_error(""), ConfigError::MissingKey(key) if key == "dumps"));
+        assert!(matches!(
+            config_error("[dumps.en]\nindex = \"i\"\narticles = \"a\"\ncompare_index = \"c\""),
+            ConfigError::MissingKey(key) if key == "dumps.en.compare_articles"
+        ));
+        assert!(matches!(
+            config_error("[dumps.en]\nindex = \"i\""),
+            ConfigError::InvalidFile(..)
+        ));
+        assert!(matches!(
+            config_error("default_dump = \"fr\"\n[dumps.en]\nindex = \"i\"\narticles = \"a\""),
+            ConfigError::InvalidValue(key, _) if key == "default_dump"
+        ));
+        assert!(matches!(
+            config_error("[dumps.en]\nindex = \"i\"\narticles = \"a\"\nstart_page = \" \""),
+            ConfigError::InvalidValue(key, _) if key == "dumps.en.start_page"
+        ));
+        assert!(matches!(
+            config_error("[dumps.en]\nindex = \"i\"\narticles = \"a\"\nmedia = \"/nonexistent/media\""),
+            ConfigError::InvalidValue(key, _) if key == "dumps.en.media"
+        ));
+    }
+
+    #[test]
+    fn environment_configures_one_dump() {
+        let config = from_vars(&[
+            ("WIKI_INDEX_FILE", "index.txt"),
+            ("WIKI_ARTICLE_DB", "articles.xml.bz2"),
+            ("WIKI_COMPARE_INDEX_FILE", "old-index.txt"),
+            ("WIKI_CACHE_SIZE", "8"),
+            ("WIKI_DATA_DIR", "data"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.dumps.len(), 1);
+        let dump = config.default_dump();
+        assert_eq!(dump.name, ENV_DUMP_NAME);
+        assert_eq!(dump.index, PathBuf::from("index.txt"));
+        assert_eq!(dump.articles, PathBuf::from("articles.xml.bz2"));
+        // Both compare files are needed to compare against
+        assert_eq!(dump.compare, None);
+        assert_eq!(config.cache_budget, Some(8 * 1024 * 1024));
+        assert_eq!(config.data_dir, Some(PathBuf::from("data")));
+    }
+
+    #[test]
+    fn environment_needs_index_and_articles() {
+        let err = |vars: &[(&str, &str)]| -> ConfigError {
+            from_vars(vars).unwrap_err().downcast().unwrap()
+        };
+        assert!(matches!(err(&[]), ConfigError::NotConfigured(_)));
+        assert!(matches!(
+            err(&[("WIKI_INDEX_FILE", "index.txt")]),
+            ConfigError::MissingKey(key) if key == "WIKI_ARTICLE_DB"
+        ));
+        assert!(matches!(
+            err(&[
+                ("WIKI_INDEX_FILE", "index.txt"),
+                ("WIKI_ARTICLE_DB", "articles.xml.bz2"),
+                ("WIKI_CACHE_SIZE", "lots"),
+            ]),
+            ConfigError::InvalidValue(key, _) if key == "WIKI_CACHE_SIZE"
+        ));
+    }
+
+    #[test]
+    fn oversized_caches_are_rejected() {
+        let size = usize::MAX / 1024;
+        let text = format!(
+            "[cache]\nsize = {}\n[dumps.en]\nindex = \"i\"\narticles = \"a\"",
+            size
+        );
+        assert!(matches!(
+            config_error(&text),
+            ConfigError::InvalidValue(key, _) if key == "cache.size"
+        ));
+
+        let err = from_vars(&[
+            ("WIKI_INDEX_FILE", "index.txt"),
+            ("WIKI_ARTICLE_DB", "articles.xml.bz2"),
+            ("WIKI_CACHE_SIZE", &size.to_string()),
+        ])
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ConfigError::InvalidValue(key, _)) if key == "WIKI_CACHE_SIZE"
+        ));
+    }
+}
@@ -0,0 +1,399 @@
+/// Longest edit script the line diff searches for before giving up and
+/// treating the whole text as replaced.
+const MAX_LINE_EDITS: usize = 2000;
+
+/// Same for the word diff within a changed block of lines.
+const MAX_WORD_EDITS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block<'a> {
+    /// Lines that are the same in both texts.
+    Unchanged(Vec<&'a str>),
+    /// Lines that differ, as word-level changes.
+    Changed(Vec<Change<'a>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Diffs two wikitexts. Lines are diffed first, and the words of each
+/// changed run of lines are diffed against each other afterwards, which
+/// keeps the word diff small even for long articles.
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Block<'a>> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let edits = match myers(&old_lines, &new_lines, MAX_LINE_EDITS) {
+        Some(edits) => edits,
+        None => replace_all(old_lines.len(), new_lines.len()),
+    };
+
+    let mut blocks = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+
+    for edit in edits {
+        match edit {
+            Edit::Equal(old_idx, _) => {
+                if !deleted.is_empty() || !inserted.is_empty() {
+                    blocks.push(Block::Changed(diff_words(&deleted, &inserted)));
+                    deleted.clear();
+                    inserted.clear();
+                }
+                unchanged.push(old_lines[old_idx]);
+            }
+            Edit::Delete(old_idx) => {
+                if !unchanged.is_empty() {
+                    blocks.push(Block::Unchanged(std::mem::take(&mut unchanged)));
+                }
+                deleted.push(old_lines[old_idx]);
+            }
+            Edit::Insert(new_idx) => {
+                if !unchanged.is_empty() {
+                    blocks.push(Block::Unchanged(std::mem::take(&mut unchanged)));
+                }
+                inserted.push(new_lines[new_idx]);
+            }
+        }
+    }
+
+    if !unchanged.is_empty() {
+        blocks.push(Block::Unchanged(unchanged));
+    }
+    if !deleted.is_empty() || !inserted.is_empty() {
+        blocks.push(Block::Changed(diff_words(&deleted, &inserted)));
+    }
+
+    blocks
+}
+
+fn diff_words<'a>(old_lines: &[&'a str], new_lines: &[&'a str]) -> Vec<Change<'a>> {
+    let old_words: Vec<&str> = old_lines.iter().flat_map(|line| tokenize(line)).collect();
+    let new_words: Vec<&str> = new_lines.iter().flat_map(|line| tokenize(line)).collect();
+
+    let edits = match myers(&old_words, &new_words, MAX_WORD_EDITS) {
+        Some(edits) => edits,
+        None => replace_all(old_words.len(), new_words.len()),
+    };
+
+    let mut changes: Vec<Change> = Vec::new();
+    for edit in edits {
+        let change = match edit {
+            Edit::Equal(old_idx, _) => Change::Equal(old_words[old_idx]),
+            Edit::Delete(old_idx) => Change::Delete(old_words[old_idx]),
+            Edit::Insert(new_idx) => Change::Insert(new_words[new_idx]),
+        };
+        changes.push(change);
+    }
+
+    changes
+}
+
+/// Splits text into words, runs of whitespace and single punctuation
+/// characters, so that markup like `[[` or `|` diffs separately from the
+/// words around it.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<char> = None;
+
+    for (idx, chr) in text.char_indices() {
+        if let Some(previous) = previous {
+            let continues = (previous.is_alphanumeric() && chr.is_alphanumeric())
+                || (previous.is_whitespace() && chr.is_whitespace());
+            if !continues {
+                tokens.push(&text[start..idx]);
+                start = idx;
+            }
+        }
+        previous = Some(chr);
+    }
+
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+fn replace_all(old_len: usize, new_len: usize) -> Vec<Edit> {
+    (0..old_len)
+        .map(Edit::Delete)
+        .chain((0..new_len).map(Edit::Insert))
+        .collect()
+}
+
+/// Myers' O(ND) diff. Returns `None` if the texts differ in more than
+/// `max_edits` places, since the trace kept for backtracking grows
+/// quadratically with the number of edits.
+fn myers<T: PartialEq>(old: &[T], new: &[T], max_edits: usize) -> Option<Vec<Edit>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m).min(max_edits as isize);
+
+    // v[k] is the furthest x reached on diagonal k; `trace[d]` holds the
+    // part of v needed to backtrack from step d, covering k in -d-1..=d+1
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let offset = max + 1;
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert(prev_y as usize));
+            } else {
+                edits.push(Edit::Delete(prev_x as usize));
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Puts the texts on both sides of a diff back together.
+    fn sides(blocks: &[Block]) -> (String, String) {
+        let (mut old, mut new) = (String::new(), String::new());
+        for block in blocks {
+            match block {
+                Block::Unchanged(lines) => {
+                    old.extend(lines.iter().copied());
+                    new.extend(lines.iter().copied());
+                }
+                Block::Changed(changes) => {
+                    for change in changes {
+                        match change {
+                            Change::Equal(text) => {
+                                old.push_str(text);
+                                new.push_str(text);
+                            }
+                            Change::Delete(text) => old.push_str(text),
+                            Change::Insert(text) => new.push_str(text),
+                        }
+                    }
+                }
+            }
+        }
+        (old, new)
+    }
+
+    fn assert_sides(old: &str, new: &str) {
+        assert_eq!(sides(&diff(old, new)), (old.to_owned(), new.to_owned()));
+    }
+
+    #[test]
+    fn empty_texts_have_no_blocks() {
+        assert_eq!(diff("", ""), []);
+    }
+
+    #[test]
+    fn text_is_inserted_into_empty_text() {
+        assert_eq!(
+            diff("", "a b\nc\n"),
+            [Block::Changed(vec![
+                Change::Insert("a"),
+                Change::Insert(" "),
+                Change::Insert("b"),
+                Change::Insert("\n"),
+                Change::Insert("c"),
+                Change::Insert("\n"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn text_is_deleted_into_empty_text() {
+        assert_eq!(
+            diff("a\nb", ""),
+            [Block::Changed(vec![
+                Change::Delete("a"),
+                Change::Delete("\n"),
+                Change::Delete("b"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn identical_texts_are_unchanged() {
+        assert_eq!(
+            diff("a\nb\n", "a\nb\n"),
+            [Block::Unchanged(vec!["a\n", "b\n"])]
+        );
+    }
+
+    #[test]
+    fn inserted_line_is_changed_block() {
+        assert_eq!(
+            diff("a\nc\n", "a\nb\nc\n"),
+            [
+                Block::Unchanged(vec!["a\n"]),
+                Block::Changed(vec![Change::Insert("b"), Change::Insert("\n")]),
+                Block::Unchanged(vec!["c\n"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleted_line_is_changed_block() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nc\n"),
+            [
+                Block::Unchanged(vec!["a\n"]),
+                Block::Changed(vec![Change::Delete("b"), Change::Delete("\n")]),
+                Block::Unchanged(vec!["c\n"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_line_diffs_words() {
+        assert_eq!(
+            diff("intro\nThe [[old]] cat.\n", "intro\nThe [[new]] cat.\n"),
+            [
+                Block::Unchanged(vec!["intro\n"]),
+                Block::Changed(vec![
+                    Change::Equal("The"),
+                    Change::Equal(" "),
+                    Change::Equal("["),
+                    Change::Equal("["),
+                    Change::Delete("old"),
+                    Change::Insert("new"),
+                    Change::Equal("]"),
+                    Change::Equal("]"),
+                    Change::Equal(" "),
+                    Change::Equal("cat"),
+                    Change::Equal("."),
+                    Change::Equal("\n"),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn scattered_changes_keep_both_sides() {
+        assert_sides(
+            "one\ntwo\nthree\nfour\nfive\n",
+            "zero\none\n2\nthree\nfive\nsix",
+        );
+        assert_sides("a b c d e f", "b a c e d f g");
+    }
+
+    #[test]
+    fn myers_gives_up_past_max_edits() {
+        let old: Vec<usize> = (0..10).collect();
+        let new: Vec<usize> = (10..20).collect();
+        assert_eq!(myers(&old, &new, 19), None);
+        assert_eq!(myers(&old, &new, 20).map(|edits| edits.len()), Some(20));
+    }
+
+    #[test]
+    fn too_many_line_edits_replace_all_lines() {
+        let old: String = (0..MAX_LINE_EDITS).map(|i| format!("a{}\n", i)).collect();
+        let new: String = (0..MAX_LINE_EDITS).map(|i| format!("b{}\n", i)).collect();
+        let blocks = diff(&old, &new);
+        assert_eq!(sides(&blocks), (old.clone(), new.clone()));
+
+        let changes = match &blocks[..] {
+            [Block::Changed(changes)] => changes,
+            blocks => panic!("expected one changed block, got {}", blocks.len()),
+        };
+        // The words are replaced as a whole too, as every line differs
+        let deleted = changes
+            .iter()
+            .take_while(|change| matches!(change, Change::Delete(_)))
+            .count();
+        assert_eq!(deleted, 2 * MAX_LINE_EDITS);
+        assert!(changes[deleted..]
+            .iter()
+            .all(|change| matches!(change, Change::Insert(_))));
+    }
+
+    #[test]
+    fn too_many_word_edits_replace_all_words() {
+        let words = |prefix: &str| -> Vec<String> {
+            (0..MAX_WORD_EDITS)
+                .map(|i| format!("{}{}", prefix, i))
+                .collect()
+        };
+        let old = format!("x\n{}", words("a").join(" "));
+        let new = format!("x\n{}", words("b").join(" "));
+        let blocks = diff(&old, &new);
+        assert_eq!(sides(&blocks), (old.clone(), new.clone()));
+
+        let changes = match &blocks[..] {
+            [Block::Unchanged(_), Block::Changed(changes)] => changes,
+            _ => panic!("expected an unchanged and a changed block"),
+        };
+        assert!(!changes
+            .iter()
+            .any(|change| matches!(change, Change::Equal(_))));
+        assert_eq!(changes.len(), 2 * (2 * MAX_WORD_EDITS - 1));
+    }
+}
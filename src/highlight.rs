@@ -0,0 +1,243 @@
+/// Renders wikitext as an HTML table with one row per line, marking up
+/// templates, links, tags, comments and headings with `<span>`s whose class
+/// is the kind of markup. Templates and links nest, so spans that are still
+/// open at the end of a line are closed and reopened on the next one.
+pub fn highlight_wikitext(text: &str) -> String {
+    let mut highlighter = Highlighter {
+        html: String::new(),
+        open: Vec::new(),
+    };
+
+    highlighter.html.push_str(r#"<table class="source">"#);
+    for (number, line) in text.lines().enumerate() {
+        highlighter.html.push_str(&format!(
+            r#"<tr><td class="line-number">{}</td><td class="line">"#,
+            number + 1
+        ));
+        for class in highlighter.open.clone() {
+            highlighter
+                .html
+                .push_str(&format!(r#"<span class="{}">"#, class));
+        }
+
+        highlighter.highlight_line(line);
+
+        for _ in &highlighter.open {
+            highlighter.html.push_str("</span>");
+        }
+        highlighter.html.push_str("</td></tr>");
+    }
+    highlighter.html.push_str("</table>");
+
+    highlighter.html
+}
+
+struct Highlighter {
+    html: String,
+    /// Classes of the spans currently open, innermost last.
+    open: Vec<&'static str>,
+}
+
+impl Highlighter {
+    fn highlight_line(&mut self, line: &str) {
+        let trimmed = line.trim_end();
+        let is_heading = self.open.is_empty()
+            && trimmed.len() > 2
+            && trimmed.starts_with('=')
+            && trimmed.ends_with('=');
+
+        if is_heading {
+            self.push_span("heading", line);
+            return;
+        }
+
+        let mut rest = line;
+        while let Some(chr) = rest.chars().next() {
+            if self.open.last() == Some(&"comment") {
+                match rest.find("-->") {
+                    Some(end) => {
+                        self.push_escaped(&rest[..end + 3]);
+                        self.close_span();
+                        rest = &rest[end + 3..];
+                    }
+                    None => {
+                        self.push_escaped(rest);
+                        rest = "";
+                    }
+                }
+                continue;
+            }
+
+            if rest.starts_with("<!--") {
+                self.open_span("comment");
+                self.push_escaped("<!--");
+                rest = &rest[4..];
+            } else if rest.starts_with("{{") {
+                self.open_span("template");
+                self.push_escaped("{{");
+                rest = &rest[2..];
+            } else if rest.starts_with("}}") && self.open.last() == Some(&"template") {
+                self.push_escaped("}}");
+                self.close_span();
+                rest = &rest[2..];
+            } else if rest.starts_with("[[") {
+                self.open_span("link");
+                self.push_escaped("[[");
+                rest = &rest[2..];
+            } else if rest.starts_with("]]") && self.open.last() == Some(&"link") {
+                self.push_escaped("]]");
+                self.close_span();
+                rest = &rest[2..];
+            } else if let Some(tag_len) = Self::tag_length(rest) {
+                self.push_span("tag", &rest[..tag_len]);
+                rest = &rest[tag_len..];
+            } else {
+                self.push_escaped(&rest[..chr.len_utf8()]);
+                rest = &rest[chr.len_utf8()..];
+            }
+        }
+    }
+
+    /// Length of the HTML tag at the start of `text`, like `<ref name="a">`
+    /// or `</ref>`, if there is one that closes on the same line.
+    fn tag_length(text: &str) -> Option<usize> {
+        let mut chars = text.chars();
+        if chars.next() != Some('<') {
+            return None;
+        }
+
+        let next = chars.next()?;
+        if !(next.is_ascii_alphabetic() || next == '/') {
+            return None;
+        }
+
+        text.find('>').map(|end| end + 1)
+    }
+
+    fn open_span(&mut self, class: &'static str) {
+        self.html.push_str(&format!(r#"<span class="{}">"#, class));
+        self.open.push(class);
+    }
+
+    fn close_span(&mut self) {
+        self.html.push_str("</span>");
+        self.open.pop();
+    }
+
+    fn push_span(&mut self, class: &str, text: &str) {
+        self.html.push_str(&format!(r#"<span class="{}">"#, class));
+        self.push_escaped(text);
+        self.html.push_str("</span>");
+    }
+
+    fn push_escaped(&mut self, text: &str) {
+        for chr in text.chars() {
+            match chr {
+                '&' => self.html.push_str("&amp;"),
+                '<' => self.html.push_str("&lt;"),
+                '>' => self.html.push_str("&gt;"),
+                '"' => self.html.push_str("&quot;"),
+                _ => self.html.push(chr),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The HTML of each line of highlighted wikitext.
+    fn lines(text: &str) -> Vec<String> {
+        let html = highlight_wikitext(text);
+        assert_eq!(
+            html.matches("<span").count(),
+            html.matches("</span>").count(),
+            "{}",
+            html
+        );
+        html.split(r#"<td class="line">"#)
+            .skip(1)
+            .map(|line| line.split("</td></tr>").next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn templates_and_links_nest() {
+        assert_eq!(
+            lines("a {{cite|[[B]] {{c}}}} d"),
+            [concat!(
+                r#"a <span class="template">{{cite|"#,
+                r#"<span class="link">[[B]]</span> "#,
+                r#"<span class="template">{{c}}</span>}}</span> d"#
+            )]
+        );
+    }
+
+    #[test]
+    fn spans_continue_on_next_lines() {
+        assert_eq!(
+            lines("{{Infobox\n| name = [[A\nB]]\n}}"),
+            [
+                r#"<span class="template">{{Infobox</span>"#,
+                r#"<span class="template">| name = <span class="link">[[A</span></span>"#,
+                r#"<span class="template"><span class="link">B]]</span></span>"#,
+                r#"<span class="template">}}</span>"#,
+            ]
+        );
+        assert_eq!(
+            lines("<!-- a\n{{b}}\n-->c"),
+            [
+                r#"<span class="comment">&lt;!-- a</span>"#,
+                r#"<span class="comment">{{b}}</span>"#,
+                r#"<span class="comment">--&gt;</span>c"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_spans_close_at_end() {
+        assert_eq!(lines("a {{b"), [r#"a <span class="template">{{b</span>"#]);
+        assert_eq!(lines("a [[b"), [r#"a <span class="link">[[b</span>"#]);
+        assert_eq!(
+            lines("a <!-- b"),
+            [r#"a <span class="comment">&lt;!-- b</span>"#]
+        );
+    }
+
+    #[test]
+    fn stray_closing_brackets_are_text() {
+        assert_eq!(lines("a}} b]]"), ["a}} b]]"]);
+        assert_eq!(lines("[[a}}]]"), [r#"<span class="link">[[a}}]]</span>"#]);
+    }
+
+    #[test]
+    fn headings_and_tags_are_marked() {
+        assert_eq!(
+            lines("== A ==\ntext<ref name=\"r\">x</ref>"),
+            [
+                r#"<span class="heading">== A ==</span>"#,
+                r#"text<span class="tag">&lt;ref name=&quot;r&quot;&gt;</span>x<span class="tag">&lt;/ref&gt;</span>"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn source_is_escaped() {
+        assert_eq!(
+            lines("<script>alert(\"&\")</script> a < b > c"),
+            [concat!(
+                r#"<span class="tag">&lt;script&gt;</span>alert(&quot;&amp;&quot;)"#,
+                r#"<span class="tag">&lt;/script&gt;</span> a &lt; b &gt; c"#
+            )]
+        );
+    }
+
+    #[test]
+    fn lines_are_numbered() {
+        let html = highlight_wikitext("a\n\nb");
+        assert!(html.starts_with(r#"<table class="source">"#));
+        assert!(html.contains(r#"<td class="line-number">3</td><td class="line">b</td>"#));
+        assert_eq!(highlight_wikitext(""), r#"<table class="source"></table>"#);
+    }
+}
@@ -0,0 +1,260 @@
+//! The articles visited in the app, newest last, which are saved to a JSON
+//! file in the data directory so that the window can reopen the last one.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    storage::{load_json_or_move_aside, save_json_atomically},
+    wiki::normalize::search_key_string,
+};
+
+/// Visits kept in the history, the oldest ones are dropped first.
+const MAX_VISITS: usize = 1000;
+
+/// Name of the history file in the data directory.
+const HISTORY_FILE: &str = "history.json";
+
+/// How often changed visits are saved in the background, rather than on
+/// every request for an article.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Visit {
+    /// Name of the dump the article is in
+    pub wiki: String,
+    pub title: String,
+    /// Unix time of the visit, in seconds
+    pub timestamp: i64,
+    /// How far the article was scrolled down when it was left, in pixels
+    pub scroll: u32,
+}
+
+pub struct BrowsingHistory {
+    /// File the visits are saved to, `None` to keep them in memory only
+    path: Option<PathBuf>,
+    visits: Mutex<Vec<Visit>>,
+    /// Whether the visits changed since they were last saved
+    changed: AtomicBool,
+    /// Held while saving, so that an older copy of the visits is never
+    /// written over a newer one
+    saving: Mutex<()>,
+}
+
+impl BrowsingHistory {
+    /// Loads the history from the data directory, which starts out empty if
+    /// there is no history file yet, or if it can't be parsed, see
+    /// [`load_json_or_move_aside`].
+    pub fn load(data_dir: Option<&Path>) -> anyhow::Result<BrowsingHistory> {
+        let path = data_dir.map(|dir| dir.join(HISTORY_FILE));
+        let visits = match &path {
+            Some(path) => load_json_or_move_aside(path, "history")?,
+            None => Vec::new(),
+        };
+
+        Ok(BrowsingHistory {
+            path,
+            visits: Mutex::new(visits),
+            changed: AtomicBool::new(false),
+            saving: Mutex::new(()),
+        })
+    }
+
+    /// Saves the visits every few seconds on a thread of its own while they
+    /// change, for as long as the history is in use. Visits are only kept in
+    /// memory until then, see [`BrowsingHistory::save`].
+    pub fn save_periodically(history: &Arc<BrowsingHistory>) {
+        if history.path.is_none() {
+            return;
+        }
+
+        let history = Arc::downgrade(history);
+        thread::spawn(move || loop {
+            thread::sleep(SAVE_INTERVAL);
+            let history = match history.upgrade() {
+                Some(history) => history,
+                None => break,
+            };
+            if let Err(err) = history.save() {
+                println!("Failed to save history: {:#}", err);
+            }
+        });
+    }
+
+    /// Records a visit of an article. Opening the article that was visited
+    /// last again, e.g. by reloading it, only updates the time of that visit.
+    pub fn record_visit(&self, wiki: &str, title: &str) {
+        let mut visits = self.visits.lock().expect("History lock poisoned");
+        let timestamp = Utc::now().timestamp();
+        match visits.last_mut() {
+            Some(last) if last.wiki == wiki && last.title == title => last.timestamp = timestamp,
+            _ => visits.push(Visit {
+                wiki: wiki.to_owned(),
+                title: title.to_owned(),
+                timestamp,
+                scroll: 0,
+            }),
+        }
+
+        if visits.len() > MAX_VISITS {
+            let excess = visits.len() - MAX_VISITS;
+            visits.drain(..excess);
+        }
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// Sets the scroll position of the latest visit of an article.
+    pub fn set_scroll(&self, wiki: &str, title: &str, scroll: u32) {
+        let mut visits = self.visits.lock().expect("History lock poisoned");
+        match visits
+            .iter_mut()
+            .rev()
+            .find(|visit| visit.wiki == wiki && visit.title == title)
+        {
+            Some(visit) => visit.scroll = scroll,
+            None => return,
+        }
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn last_visit(&self) -> Option<Visit> {
+        let visits = self.visits.lock().expect("History lock poisoned");
+        visits.last().cloned()
+    }
+
+    /// Finds the visits of articles whose titles contain the query, ignoring
+    /// case and accents, newest first. An empty query finds all visits.
+    pub fn search(&self, query: &str) -> Vec<Visit> {
+        let query = search_key_string(query.trim());
+        let visits = self.visits.lock().expect("History lock poisoned");
+        visits
+            .iter()
+            .rev()
+            .filter(|visit| search_key_string(&visit.title).contains(&query))
+            .cloned()
+            .collect()
+    }
+
+    /// Clears the history, which is saved right away rather than in the
+    /// background, so that the visits are gone from the file as well.
+    pub fn clear(&self) -> anyhow::Result<()> {
+        self.visits.lock().expect("History lock poisoned").clear();
+        self.changed.store(true, Ordering::SeqCst);
+        self.save()
+    }
+
+    /// Saves the visits if they changed since they were last saved.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let _saving = self.saving.lock().expect("History lock poisoned");
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        // Requests can go on visiting articles while the copy is written
+        let visits = self.visits.lock().expect("History lock poisoned").clone();
+        let result = save_json_atomically(path, &visits, "history");
+        if result.is_err() {
+            self.changed.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+/// Saves the visits since the last save, e.g. when the app quits.
+impl Drop for BrowsingHistory {
+    fn drop(&mut self) {
+        if let Err(err) = self.save() {
+            println!("Failed to save history: {:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::TempDir;
+
+    #[test]
+    fn visits_are_saved() {
+        let dir = TempDir::new("history-saved");
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        history.record_visit("en", "Rust");
+        history.record_visit("en", "Zürich");
+        history.set_scroll("en", "Rust", 120);
+        history.save().unwrap();
+
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        let visits = history.search("");
+        let titles: Vec<&str> = visits.iter().map(|visit| visit.title.as_str()).collect();
+        assert_eq!(titles, ["Zürich", "Rust"]);
+        assert_eq!(visits[1].scroll, 120);
+        assert_eq!(history.search("zur").len(), 1);
+    }
+
+    #[test]
+    fn visits_are_saved_later() {
+        let dir = TempDir::new("history-later");
+        let path = dir.0.join(HISTORY_FILE);
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        history.record_visit("en", "Rust");
+        assert!(!path.exists());
+
+        history.save().unwrap();
+        let saved = fs::read(&path).unwrap();
+        // Saving without changes leaves the file alone
+        fs::write(&path, "[]").unwrap();
+        history.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[]");
+
+        // Visits since the last save are saved when the history is dropped
+        fs::write(&path, saved).unwrap();
+        history.record_visit("en", "Zürich");
+        drop(history);
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        assert_eq!(history.search("").len(), 2);
+    }
+
+    #[test]
+    fn clearing_is_saved_right_away() {
+        let dir = TempDir::new("history-cleared");
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        history.record_visit("en", "Rust");
+        history.save().unwrap();
+
+        history.clear().unwrap();
+        let saved = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        assert!(saved.last_visit().is_none());
+    }
+
+    #[test]
+    fn unparsable_history_starts_empty() {
+        let dir = TempDir::new("history-unparsable");
+        fs::write(dir.0.join(HISTORY_FILE), "{ not json").unwrap();
+
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        assert!(history.last_visit().is_none());
+        assert!(dir.0.join("history.json.bad").is_file());
+
+        // The history is saved again from scratch
+        history.record_visit("en", "Rust");
+        history.save().unwrap();
+        let history = BrowsingHistory::load(Some(&dir.0)).unwrap();
+        assert_eq!(history.last_visit().unwrap().title, "Rust");
+    }
+}
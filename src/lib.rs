@@ -0,0 +1,4 @@
+//! Reading Wikipedia dumps, which the app is built on. It is a library of
+//! its own so that the benchmarks can use it as well.
+
+pub mod wiki;
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{bail, Context};
+use chrono::{Datelike, NaiveDate};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    config::DumpConfig,
+    wiki::{
+        article::{ArticleDatabase, ArticleError},
+        index::{Index, IndexEntry},
+        links::{category_name, LinkIndex, LinksError},
+        ranking::{self, Ranks},
+        revisions::{RevisionIndex, RevisionsError},
+        siteinfo::SiteInfo,
+    },
+};
+
+/// A dump with everything needed to read from it.
+pub struct Wiki {
+    /// Name of the dump in the config, used in the URLs of its pages
+    pub name: String,
+    pub index: Index,
+    /// Positions in the index of the pages random articles are drawn from,
+    /// sorted, see [`Wiki::random_articles`]
+    pub articles: Vec<u32>,
+    pub article_db: ArticleDatabase,
+    pub site_info: SiteInfo,
+    /// Categories of the pages and the links between them, if the dump has
+    /// a links file
+    pub links: Option<LinkIndex>,
+    /// The pages that revisions belong to, if the dump has a revisions file
+    pub revisions: Option<RevisionIndex>,
+    /// A second dump of the same wiki, e.g. from an earlier date, to diff against
+    pub compare_dump: Option<(Index, ArticleDatabase)>,
+    /// Directory to serve the files embedded in articles from
+    pub media_root: Option<PathBuf>,
+    /// Article shown at the root URL, the article of the day if `None`
+    pub start_page: Option<String>,
+}
+
+impl Wiki {
+    pub fn load(dump: &DumpConfig, cache_budget: Option<usize>) -> anyhow::Result<Wiki> {
+        let mut article_db = Self::open_article_db(dump)?;
+        if let Some(cache_budget) = cache_budget {
+            article_db = article_db.with_cache_budget(cache_budget);
+        }
+
+        let mut site_info = article_db.get_site_info()?;
+        site_info.language = dump.language.clone();
+
+        let mut index = Index::from_file(&dump.index).with_context(|| {
+            format!(
+                "Failed to open index {} of dump `{}`",
+                dump.index.display(),
+                dump.name
+            )
+        })?;
+        let ranks = Self::rank(&mut index, dump, &site_info)?;
+        let articles = Self::random_articles(&index, &site_info, ranks.as_ref());
+
+        let links = match &dump.links {
+            Some(path) => Some(LinkIndex::from_file(path, &index).with_context(|| {
+                format!(
+                    "Failed to read links {} of dump `{}`",
+                    path.display(),
+                    dump.name
+                )
+            })?),
+            None => None,
+        };
+
+        let revisions = match &dump.revisions {
+            Some(path) => Some(RevisionIndex::from_file(path, &index).with_context(|| {
+                format!(
+                    "Failed to read revisions {} of dump `{}`",
+                    path.display(),
+                    dump.name
+                )
+            })?),
+            None => None,
+        };
+
+        let compare_dump = match &dump.compare {
+            Some((index_path, articles_path)) => Some((
+                Index::from_file(index_path)?,
+                ArticleDatabase::from_file(articles_path)?,
+            )),
+            None => None,
+        };
+
+        Ok(Wiki {
+            name: dump.name.clone(),
+            index,
+            articles,
+            article_db,
+            site_info,
+            links,
+            revisions,
+            compare_dump,
+            media_root: dump.media.clone(),
+            start_page: dump.start_page.clone(),
+        })
+    }
+
+    /// Finds the article a revision belongs to in the revisions file of the
+    /// dump. Without one, revisions can only be opened together with the
+    /// title of their article, as finding them would mean decompressing the
+    /// whole dump.
+    pub fn find_revision(&self, revision_id: u64) -> anyhow::Result<&IndexEntry> {
+        let revisions = self
+            .revisions
+            .as_ref()
+            .ok_or(RevisionsError::MissingRevisionsFile)?;
+        match revisions.page_of(revision_id) {
+            Some(position) => Ok(&self.index.entries()[position]),
+            None => bail!(ArticleError::RevisionNotFound),
+        }
+    }
+
+    /// Picks an article of the main namespace that isn't a redirect with
+    /// `rng`, among the members of `category` if one is given. It is drawn
+    /// from the articles found when loading the dump, so that nothing is
+    /// read from the dump but the article itself.
+    pub fn random_article(
+        &self,
+        category: Option<&str>,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<&IndexEntry> {
+        let members: Vec<u32>;
+        let candidates = match category {
+            Some(category) => {
+                let links = self.links.as_ref().ok_or(LinksError::MissingLinksFile)?;
+                members = links
+                    .category_members(&category_name(category, &self.site_info))
+                    .iter()
+                    .copied()
+                    .filter(|position| self.articles.binary_search(position).is_ok())
+                    .collect();
+                &members
+            }
+            None => &self.articles,
+        };
+        if candidates.is_empty() {
+            bail!(ArticleError::ArticleNotFound);
+        }
+
+        let entry = &self.index.entries()[candidates[rng.gen_range(0..candidates.len())] as usize];
+        self.follow_redirect(entry)
+    }
+
+    /// Picks the article of the day, a random article that stays the same
+    /// for the whole date. ChaCha is seeded with the date rather than
+    /// `StdRng`, whose algorithm may change with any release of `rand`.
+    pub fn article_of_the_day(&self, date: NaiveDate) -> anyhow::Result<&IndexEntry> {
+        let mut rng = ChaCha8Rng::seed_from_u64(date.num_days_from_ce() as u64);
+        self.random_article(None, &mut rng)
+    }
+
+    /// Without a ranks file, redirects are only known once they are read,
+    /// so a redirect that was drawn stands for the article it redirects to.
+    /// Reading it costs little, as its block is needed to show it anyway.
+    fn follow_redirect<'a>(&'a self, entry: &'a IndexEntry) -> anyhow::Result<&'a IndexEntry> {
+        let target = match self.article_db.get_article(entry)?.redirect {
+            Some(target) => self.site_info.normalize_title(&target),
+            None => return Ok(entry),
+        };
+        Ok(self.index.find_article_exact(&target).unwrap_or(entry))
+    }
+
+    /// Positions of the pages that random articles are drawn from, which
+    /// are those in the main namespace. If the dump has a ranks file, which
+    /// only ranks articles, redirects are left out as well.
+    fn random_articles(index: &Index, site_info: &SiteInfo, ranks: Option<&Ranks>) -> Vec<u32> {
+        index
+            .entries()
+            .par_iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                site_info
+                    .namespace_of(&entry.page_name)
+                    .map_or(0, |ns| ns.key)
+                    == 0
+                    && ranks.is_none_or(|ranks| ranks.get(entry.page_id).is_some())
+            })
+            .map(|(position, _)| position as u32)
+            .collect()
+    }
+
+    /// Sets the importance of the articles from the ranks and pageviews
+    /// files of the dump, if it has any, returning the ranks.
+    fn rank(
+        index: &mut Index,
+        dump: &DumpConfig,
+        site_info: &SiteInfo,
+    ) -> anyhow::Result<Option<Ranks>> {
+        if dump.ranks.is_none() && dump.pageviews.is_none() {
+            return Ok(None);
+        }
+
+        let ranks = match &dump.ranks {
+            Some(path) => Some(Ranks::from_file(path).with_context(|| {
+                format!(
+                    "Failed to read ranks {} of dump `{}`",
+                    path.display(),
+                    dump.name
+                )
+            })?),
+            None => None,
+        };
+        // Pageview dumps list the views of other wikis as well
+        let projects = ranking::pageview_projects(site_info);
+        let pageviews = match &dump.pageviews {
+            Some(path) => ranking::read_pageviews_file(path, &projects).with_context(|| {
+                format!(
+                    "Failed to read pageviews {} of dump `{}`",
+                    path.display(),
+                    dump.name
+                )
+            })?,
+            None => HashMap::new(),
+        };
+
+        index.set_importance(|entry| {
+            ranking::importance(
+                ranks.as_ref().and_then(|ranks| ranks.get(entry.page_id)),
+                pageviews.get(&entry.page_name).copied(),
+            )
+        });
+        Ok(ranks)
+    }
+
+    /// Opens only the article database of a dump, which is all that is
+    /// needed to build its index.
+    pub fn open_article_db(dump: &DumpConfig) -> anyhow::Result<ArticleDatabase> {
+        ArticleDatabase::from_file(&dump.articles).with_context(|| {
+            format!(
+                "Failed to open article database {} of dump `{}`",
+                dump.articles.display(),
+                dump.name
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+impl Wiki {
+    /// Offset of the stream with the pages in `tests/data/articles.xml.bz2`,
+    /// after the one with the site info.
+    pub const TEST_PAGES_OFFSET: u64 = 303;
+
+    /// A wiki with the dump in `tests/data`, which has an article about Rust,
+    /// one about Cargo and a redirect to the first. `titles` are added to its
+    /// index without pages of their own, to be searched for.
+    pub fn from_test_dump(name: &str, titles: &[&str]) -> Wiki {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/articles.xml.bz2");
+        let article_db = ArticleDatabase::from_file(path).unwrap();
+        let site_info = article_db.get_site_info().unwrap();
+
+        let pages = [
+            "Rust (programming language)",
+            "Cargo (software)",
+            "Rust language",
+        ];
+        let entries = pages
+            .iter()
+            .chain(titles)
+            .enumerate()
+            .map(|(idx, title)| IndexEntry {
+                offset: Self::TEST_PAGES_OFFSET,
+                end_offset: None,
+                page_id: idx as u64 + 1,
+                page_name: title.to_string(),
+            })
+            .collect();
+        let index = Index::from_entries(entries);
+
+        Wiki {
+            name: name.to_owned(),
+            articles: Self::random_articles(&index, &site_info, None),
+            index,
+            article_db,
+            site_info,
+            links: None,
+            revisions: None,
+            compare_dump: None,
+            media_root: None,
+            start_page: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+    use crate::{
+        storage::TempDir,
+        wiki::{links::PageRecord, ranking::PageLinks},
+    };
+
+    fn titles(entries: &[&IndexEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| entry.page_name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn random_articles_skip_other_namespaces() {
+        let wiki = Wiki::from_test_dump(
+            "en",
+            &["Category:Programming languages", "File:Rust logo.svg"],
+        );
+        let articles: Vec<&str> = wiki
+            .articles
+            .iter()
+            .map(|&position| wiki.index.entries()[position as usize].page_name.as_str())
+            .collect();
+        assert_eq!(
+            articles,
+            [
+                "Rust (programming language)",
+                "Cargo (software)",
+                "Rust language"
+            ]
+        );
+
+        // Drawing the redirect gives the article it redirects to
+        let mut drawn: Vec<&IndexEntry> = (0..3)
+            .map(|step| {
+                let mut rng = StepRng::new(step * (u64::MAX / 3 + 1), 0);
+                wiki.random_article(None, &mut rng).unwrap()
+            })
+            .collect();
+        drawn.sort_by_key(|entry| entry.page_id);
+        assert_eq!(
+            titles(&drawn),
+            [
+                "Rust (programming language)",
+                "Rust (programming language)",
+                "Cargo (software)"
+            ]
+        );
+    }
+
+    /// Ranks of the first two pages of the test dump, as a ranks file only
+    /// ranks articles and the third page is a redirect.
+    fn ranks() -> Ranks {
+        let links = |page| PageLinks {
+            page,
+            redirect: None,
+            links: Vec::new(),
+        };
+        Ranks::compute(3, &[links(0), links(1)], &[(0, 1), (1, 2)])
+    }
+
+    #[test]
+    fn random_articles_skip_redirects_with_ranks() {
+        let mut wiki = Wiki::from_test_dump("en", &["Category:Programming languages"]);
+        wiki.articles = Wiki::random_articles(&wiki.index, &wiki.site_info, Some(&ranks()));
+        assert_eq!(wiki.articles, [0, 1]);
+    }
+
+    #[test]
+    fn random_articles_of_categories_are_articles() {
+        let mut wiki = Wiki::from_test_dump("en", &["Category:Rust"]);
+        let err = wiki
+            .random_article(Some("Programming languages"), &mut rand::thread_rng())
+            .err()
+            .unwrap();
+        assert!(err.is::<LinksError>());
+
+        let dir = TempDir::new("library-links");
+        let path = dir.0.join("links.txt");
+        let record = |page_id| PageRecord {
+            page_id,
+            links: Vec::new(),
+            categories: vec!["Programming languages".to_owned()],
+        };
+        // The article, the redirect to it and a category
+        LinkIndex::write_to_file(&path, &[record(1), record(3), record(4)]).unwrap();
+        wiki.links = Some(LinkIndex::from_file(&path, &wiki.index).unwrap());
+        wiki.articles = Wiki::random_articles(&wiki.index, &wiki.site_info, Some(&ranks()));
+
+        for seed in 0..10 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            for category in ["Programming languages", "Category:Programming_languages"] {
+                let article = wiki.random_article(Some(category), &mut rng).unwrap();
+                assert_eq!(article.page_name, "Rust (programming language)");
+            }
+        }
+        let err = wiki
+            .random_article(Some("Databases"), &mut rand::thread_rng())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::ArticleNotFound)
+        ));
+    }
+
+    #[test]
+    fn article_of_the_day_stays_the_same_for_the_day() {
+        let wiki = Wiki::from_test_dump("en", &["File:Rust logo.svg"]);
+        let first_day = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+
+        let mut articles: Vec<&str> = first_day
+            .iter_days()
+            .take(30)
+            .map(|date| {
+                let article = &wiki.article_of_the_day(date).unwrap().page_name;
+                assert_eq!(&wiki.article_of_the_day(date).unwrap().page_name, article);
+                article.as_str()
+            })
+            .collect();
+        articles.sort_unstable();
+        articles.dedup();
+        // Both articles come up on some day, but neither the file nor the
+        // redirect does
+        assert_eq!(
+            articles,
+            ["Cargo (software)", "Rust (programming language)"]
+        );
+    }
+}
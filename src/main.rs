@@ -1,148 +1,142 @@
 // #![windows_subsystem = "windows"]
 
-use std::time::Instant;
+use std::sync::Arc;
 
-use anyhow::bail;
-use thiserror::Error;
-use url::Url;
-use urlencoding::decode;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use urlencoding::encode;
 use wry::{
     application::{
         event::{Event, StartCause, WindowEvent},
         event_loop::{ControlFlow, EventLoop},
         window::WindowBuilder,
     },
-    http::ResponseBuilder,
     webview::WebViewBuilder,
 };
+// The modules of the app refer to it as `crate::wiki`
+use wiki_rs::wiki;
 
 use crate::{
-    pages::{render_article_page, render_results_page},
+    bookmarks::Bookmarks,
+    cli::{Cli, Command},
+    config::{Config, Theme},
+    history::BrowsingHistory,
+    library::Wiki,
     resource::ResourceManager,
+    router::Router,
 };
 
+mod api;
+mod bookmarks;
+mod cli;
+mod config;
+mod diff;
+mod highlight;
+mod history;
+mod library;
 mod pages;
 mod renderer;
 mod resource;
-mod wiki;
-
-#[derive(Debug)]
-enum ParsedUrl {
-    Resource(String),
-    Article(String),
-    Search(String),
-}
-
-#[derive(Error, Debug)]
-pub enum UrlError {
-    #[error("url namespace not found")]
-    UnknownNamespace,
-
-    #[error("url path is incomplete")]
-    IncompletePath,
-
-    #[error("missing query parameter")]
-    MissingParameter,
-}
-
-fn parse_url(url: &str) -> anyhow::Result<ParsedUrl> {
-    let url = Url::parse(url)?;
-    let mut path = url.path_segments().unwrap();
-
-    let mut next_path_part = move || return path.next().ok_or(UrlError::IncompletePath);
-    let namespace = decode(next_path_part()?)?;
-
-    let mut next_path_part_string = || anyhow::Ok(decode(next_path_part()?)?.to_string());
-    let query = url.query_pairs().next();
-
-    Ok(match &*namespace {
-        "res" => ParsedUrl::Resource(next_path_part_string()?),
-        "article" => ParsedUrl::Article(next_path_part_string()?),
-        "search" => ParsedUrl::Search(query.ok_or(UrlError::MissingParameter)?.1.to_string()),
-        _ => bail!(UrlError::UnknownNamespace),
-    })
-}
+mod router;
+mod server;
+mod storage;
 
 fn main() -> anyhow::Result<()> {
-    println!("Starting up wiki.rs ...");
-
-    dotenv::dotenv()?;
-    let index_path = std::env::var("WIKI_INDEX_FILE")?;
-    let articles_path = std::env::var("WIKI_ARTICLE_DB")?;
+    let cli = Cli::parse_args();
+
+    // A .env file is optional, the variables may as well be set directly
+    dotenv::dotenv().ok();
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(dump) = &cli.dump {
+        config.set_default_dump(dump)?;
+    }
+
+    match cli.command {
+        Some(Command::Build(command)) => {
+            return cli::build(command, config.default_dump(), config.cache_budget);
+        }
+        Some(Command::Query(command)) => {
+            let wiki = Wiki::load(config.default_dump(), config.cache_budget)?;
+            return cli::run(command, &wiki);
+        }
+        None => {}
+    }
 
-    let index = wiki::index::Index::from_file(&index_path)?;
-    let article_db = wiki::article::ArticleDatabase::from_file(&articles_path)?;
+    println!("Starting up wiki.rs ...");
 
-    println!("Loaded {} articles from index", index.size());
+    let wikis: Vec<Wiki> = config
+        .dumps
+        .par_iter()
+        .map(|dump| Wiki::load(dump, config.cache_budget))
+        .collect::<anyhow::Result<_>>()?;
+
+    for wiki in &wikis {
+        println!(
+            "Loaded {} articles of {} ({}), generated by {}",
+            wiki.index.size(),
+            wiki.site_info.site_name,
+            wiki.name,
+            wiki.site_info.generator
+        );
+    }
 
     let mut resources = ResourceManager::new();
     resources.register_template("article.html", include_bytes!("../res/article.html"));
     resources.register_template("search.html", include_bytes!("../res/search.html"));
+    resources.register_template("source.html", include_bytes!("../res/source.html"));
+    resources.register_template("history.html", include_bytes!("../res/history.html"));
+    resources.register_template("diff.html", include_bytes!("../res/diff.html"));
+    resources.register_template("browsing.html", include_bytes!("../res/browsing.html"));
+    resources.register_template("bookmarks.html", include_bytes!("../res/bookmarks.html"));
+    resources.register_template("about.html", include_bytes!("../res/about.html"));
+    resources.register_template("error.html", include_bytes!("../res/error.html"));
     resources.register_resource("styles.css", include_bytes!("../res/styles.css"));
+    resources.register_resource("suggest.js", include_bytes!("../res/suggest.js"));
+    resources.register_resource("navigation.js", include_bytes!("../res/navigation.js"));
+    resources.register_resource("bookmarks.js", include_bytes!("../res/bookmarks.js"));
+    resources.register_resource(
+        "theme.css",
+        match config.theme {
+            Theme::Light => &[],
+            Theme::Dark => include_bytes!("../res/dark.css"),
+        },
+    );
+
+    let history = Arc::new(BrowsingHistory::load(config.data_dir.as_deref())?);
+    BrowsingHistory::save_periodically(&history);
+
+    // The window reopens the article that was visited last, where it was
+    // left, unless its dump is gone from the config
+    let start_url = match history.last_visit() {
+        Some(visit) if wikis.iter().any(|wiki| wiki.name == visit.wiki) => format!(
+            "/w/{}/article/{}#scroll={}",
+            visit.wiki,
+            encode(&visit.title),
+            visit.scroll
+        ),
+        _ => "/".to_owned(),
+    };
+
+    let bookmarks = Bookmarks::load(config.data_dir.as_deref())?;
+    let router = Router::new(wikis, resources, history.clone(), bookmarks);
+
+    if let Some(addr) = cli.serve {
+        return server::serve(router, &addr);
+    }
+
+    let title = format!("{} - wiki.rs", router.default_wiki().site_info.site_name);
+
+    // The webview loads its pages from a server on the loopback interface,
+    // so that articles are loaded and rendered on the server's workers
+    // while the window stays responsive. wry's custom protocols are answered
+    // on the thread of the event loop, which is what froze the window. The
+    // server only answers the window, see `server::start`
+    let server = server::start(router, "127.0.0.1:0", true)?;
 
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("wiki.rs")
-        .build(&event_loop)?;
+    let window = WindowBuilder::new().with_title(title).build(&event_loop)?;
 
     let mut _web_view = WebViewBuilder::new(window)?
-        .with_custom_protocol("local".into(), move |request| {
-            println!("Handling local request for {}", request.uri());
-            let url = parse_url(request.uri());
-
-            if let Ok(url) = url {
-                match url {
-                    ParsedUrl::Article(name) => {
-                        println!("Loading article {}", name);
-                        let time = Instant::now();
-
-                        let name_cleaned = name.replace("_", " ");
-                        let article = index.find_article_exact(&name_cleaned);
-                        if article.is_none() {
-                            return ResponseBuilder::new()
-                                .mimetype("text/plain")
-                                .body("not found".to_string().into_bytes());
-                        }
-
-                        let article = article.unwrap();
-                        println!("Located article in {:.2?}", time.elapsed());
-
-                        let time = Instant::now();
-                        let article_data = article_db.get_article(&article).unwrap();
-                        println!("Extracted article in {:.2?}", time.elapsed());
-
-                        let time = Instant::now();
-                        let article_html = render_article_page(&resources, &article_data);
-                        println!("Rendered article in {:.2?}", time.elapsed());
-
-                        ResponseBuilder::new()
-                            .mimetype("text/html")
-                            .body(article_html.into_bytes())
-                    }
-                    ParsedUrl::Search(query) => {
-                        let results = index.find_article(&query);
-
-                        ResponseBuilder::new()
-                            .mimetype("text/html")
-                            .body(render_results_page(&resources, &query, &results).into_bytes())
-                    }
-                    ParsedUrl::Resource(name) => {
-                        if let Some(resource) = resources.find_resource(&name) {
-                            return resource.into();
-                        }
-
-                        ResponseBuilder::new()
-                            .mimetype("text/plain")
-                            .body("not found".to_string().into_bytes())
-                    }
-                }
-            } else {
-                ResponseBuilder::new()
-                    .mimetype("text/plain")
-                    .body("not found".to_string().into_bytes())
-            }
-        })
-        .with_url("local://wiki.rs/article/Rust_(programming_language)")?
+        .with_url(&server.session_url(&start_url))?
         .build()?;
 
     event_loop.run(move |event, _, control_flow| {
@@ -153,7 +147,14 @@ fn main() -> anyhow::Result<()> {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
-            } => *control_flow = ControlFlow::Exit,
+            } => {
+                // The event loop exits the process without dropping anything,
+                // so the latest visits are saved here
+                if let Err(err) = history.save() {
+                    println!("Failed to save history: {:#}", err);
+                }
+                *control_flow = ControlFlow::Exit
+            }
             _ => (),
         }
     });
@@ -1,47 +1,798 @@
+use chrono::{Local, TimeZone};
 use kata::TemplateContext;
+use urlencoding::encode;
 
 use crate::{
+    bookmarks::Bookmark,
+    diff::{diff, Block, Change},
+    highlight::highlight_wikitext,
+    history::Visit,
+    library::Wiki,
     renderer::ArticleRenderer,
     resource::ResourceManager,
-    wiki::{article::Article, index::IndexEntry},
+    router::{SearchHit, SearchPage},
+    wiki::{article::Article, index::IndexEntry, revision::Revision, summary::section_anchor},
 };
 
-pub fn render_article_page(resources: &ResourceManager, article: &Article) -> String {
+/// Unchanged lines shown around each change in a diff.
+const DIFF_CONTEXT_LINES: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub enum DiffLayout {
+    SideBySide,
+    Inline,
+}
+
+/// What pages need to know about the app around them: the wiki they belong
+/// to, whose URLs their relative links resolve against, and the wikis to
+/// offer in the switcher of the nav bar.
+pub struct Scope<'a> {
+    pub resources: &'a ResourceManager,
+    pub wiki: &'a Wiki,
+    pub wikis: &'a [Wiki],
+}
+
+impl Scope<'_> {
+    fn template_context(&self) -> TemplateContext {
+        let mut ctx = TemplateContext::new();
+        ctx.set_str("base_href", &format!("/w/{}/", self.wiki.name));
+        ctx.set_str("site_name", &self.wiki.site_info.site_name);
+        ctx.set_str("wiki_switcher", &self.wiki_switcher());
+        ctx
+    }
+
+    fn wiki_switcher(&self) -> String {
+        if self.wikis.len() < 2 {
+            return String::new();
+        }
+
+        let options: String = self
+            .wikis
+            .iter()
+            .map(|wiki| {
+                format!(
+                    r#"<option value="{}"{}>{}</option>"#,
+                    wiki.name,
+                    if wiki.name == self.wiki.name {
+                        " selected"
+                    } else {
+                        ""
+                    },
+                    wiki_label(wiki)
+                )
+            })
+            .collect();
+
+        format!(
+            r#"<select class="wiki-switcher" onchange="location.href = '/w/' + this.value + '/'">{}</select>"#,
+            options
+        )
+    }
+}
+
+pub fn render_article_page(scope: &Scope, article: &Article, bookmarked: bool) -> String {
+    render_article(scope, article, "", Some(bookmarked))
+}
+
+pub fn render_revision_page(scope: &Scope, article: &Article) -> String {
+    let notice = format!(
+        r#"<div class="notice">This is an old revision of this page, as edited by {} at {}. It may differ significantly from the <a href="article/{}">current revision</a>.</div>"#,
+        escape_html(article.revision.contributor.display_name()),
+        article.revision.timestamp.format("%H:%M, %e %B %Y"),
+        encode(&article.title)
+    );
+    render_article(scope, article, &notice, None)
+}
+
+/// Renders an article. For the current revision, `bookmarked` tells whether
+/// the article is bookmarked, and the article can be bookmarked and is
+/// recorded in the browsing history. Old revisions pass `None`.
+fn render_article(
+    scope: &Scope,
+    article: &Article,
+    notice: &str,
+    bookmarked: Option<bool>,
+) -> String {
     let mut renderer = ArticleRenderer::new();
     renderer.render_article_body(article);
 
-    let template = resources
+    let template = scope
+        .resources
         .find_template("article.html")
         .expect("Failed to find article template");
 
-    let mut ctx = TemplateContext::new();
+    let mut ctx = scope.template_context();
     ctx.set_str("body", renderer.html());
     ctx.set_str("title", &article.title);
+    ctx.set_str("title_href", &encode(&article.title));
+    ctx.set_str("notice", notice);
+    ctx.set_str(
+        "visit",
+        &match bookmarked {
+            Some(_) => escape_html(&article.title),
+            None => String::new(),
+        },
+    );
+    ctx.set_str(
+        "bookmark",
+        match bookmarked {
+            Some(true) => {
+                r#"<button class="bookmark bookmarked" type="button" title="Remove the bookmark">&#9733; Bookmarked</button>"#
+            }
+            Some(false) => {
+                r#"<button class="bookmark" type="button" title="Bookmark this article">&#9734; Bookmark</button>"#
+            }
+            None => "",
+        },
+    );
+    ctx.set_str(
+        "last_edit",
+        &format!(
+            "Revision {} was last edited on {} by {}.",
+            article.revision.id,
+            article.revision.timestamp.format("%e %B %Y, at %H:%M"),
+            escape_html(article.revision.contributor.display_name())
+        ),
+    );
 
     template
         .render(&ctx)
         .expect("Failed to render article template")
 }
 
+/// Renders search results with the summaries of their articles. Without
+/// results, `corrections` are similar queries to offer instead.
 pub fn render_results_page(
-    resources: &ResourceManager,
+    scope: &Scope,
     query: &str,
-    index_entries: &Vec<&IndexEntry>,
+    page: &SearchPage,
+    corrections: &[String],
+    all_wikis: bool,
 ) -> String {
-    let results: Vec<&str> = index_entries
+    let results: Vec<String> = page
+        .hits
         .iter()
-        .map(|entry| entry.page_name.as_str())
+        .map(|hit| render_search_hit(hit, all_wikis))
         .collect();
+    let results: Vec<&str> = results.iter().map(|result| result.as_str()).collect();
 
-    let template = resources
+    let scope_link = match (all_wikis, scope.wikis.len() > 1) {
+        (false, true) => format!(
+            r#"<a href="search?q={}&scope=all">Search all wikis</a>"#,
+            encode(query)
+        ),
+        (true, _) => format!(
+            r#"<a href="search?q={}">Search only {}</a>"#,
+            encode(query),
+            wiki_label(scope.wiki)
+        ),
+        (false, false) => String::new(),
+    };
+
+    let corrections: Vec<String> = corrections
+        .iter()
+        .map(|correction| {
+            format!(
+                r#"<a href="search?q={}">{}</a>"#,
+                encode(correction),
+                escape_html(correction)
+            )
+        })
+        .collect();
+    let did_you_mean = match corrections.is_empty() {
+        true => String::new(),
+        false => format!("Did you mean {}?", corrections.join(" or ")),
+    };
+
+    let result_count = match page.hits.is_empty() {
+        true => String::new(),
+        false => format!(
+            "Results {}–{} of {}",
+            page.start + 1,
+            page.start + page.hits.len(),
+            page.total
+        ),
+    };
+
+    // Pages are linked with the same scope, so that their cursors stay valid
+    let search_url = match all_wikis {
+        true => format!("search?q={}&scope=all", encode(query)),
+        false => format!("search?q={}", encode(query)),
+    };
+    let mut pagination = Vec::new();
+    if page.start > 0 {
+        pagination.push(format!(r#"<a href="{}">First page</a>"#, search_url));
+    }
+    if let Some(next) = &page.next {
+        pagination.push(format!(
+            r#"<a href="{}&cursor={}">Next page</a>"#,
+            search_url,
+            encode(&next.to_string())
+        ));
+    }
+
+    let template = scope
+        .resources
         .find_template("search.html")
         .expect("Failed to find search template");
 
-    let mut ctx = TemplateContext::new();
-    ctx.set_str("query", query);
+    let mut ctx = scope.template_context();
+    ctx.set_str("query", &escape_html(query));
+    ctx.set_str("scope_link", &scope_link);
+    ctx.set_str("did_you_mean", &did_you_mean);
+    ctx.set_str("result_count", &result_count);
     ctx.set_str_array("results", &results);
+    ctx.set_str("pagination", &pagination.join(" "));
 
     template
         .render(&ctx)
         .expect("Failed to render search template")
 }
+
+/// Renders a search result as a link to its article, followed by what its
+/// summary has to offer: the short description, and either the section that
+/// the query was found in or the start of the lead.
+fn render_search_hit(hit: &SearchHit, all_wikis: bool) -> String {
+    let title = &hit.result.entry.page_name;
+    let href = format!("/w/{}/article/{}", hit.wiki.name, encode(title));
+
+    let mut html = format!(r#"<a href="{}">{}</a>"#, href, escape_html(title));
+    if all_wikis {
+        html.push_str(&format!(r#" <span class="wiki">{}</span>"#, hit.wiki.name));
+    }
+
+    let summary = match &hit.summary {
+        Some(summary) => summary,
+        None => return html,
+    };
+    if let Some(description) = &summary.short_description {
+        html.push_str(&format!(
+            r#" <span class="description">{}</span>"#,
+            escape_html(description)
+        ));
+    }
+
+    if let Some(redirect) = &summary.redirect {
+        html.push_str(&format!(
+            r#"<p class="snippet">Redirects to <a href="/w/{}/article/{}">{}</a></p>"#,
+            hit.wiki.name,
+            encode(redirect),
+            escape_html(redirect)
+        ));
+    } else if let Some(text_match) = &summary.text_match {
+        if let Some(heading) = &text_match.heading {
+            html.push_str(&format!(
+                r#" <a class="section" href="{}#{}">§ {}</a>"#,
+                href,
+                encode(&section_anchor(heading)),
+                escape_html(heading)
+            ));
+        }
+        html.push_str(&format!(
+            r#"<p class="snippet">{}</p>"#,
+            escape_html(&text_match.snippet)
+        ));
+    } else if !summary.lead.is_empty() {
+        html.push_str(&format!(
+            r#"<p class="snippet">{}</p>"#,
+            escape_html(&summary.lead)
+        ));
+    }
+
+    html
+}
+
+pub fn render_source_page(scope: &Scope, article: &Article) -> String {
+    let template = scope
+        .resources
+        .find_template("source.html")
+        .expect("Failed to find source template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("title", &article.title);
+    ctx.set_str("title_href", &encode(&article.title));
+    ctx.set_str("source", &highlight_wikitext(&article.body));
+
+    template
+        .render(&ctx)
+        .expect("Failed to render source template")
+}
+
+pub fn render_history_page(scope: &Scope, title: &str, revisions: &[Revision]) -> String {
+    let rows: Vec<String> = revisions
+        .iter()
+        .rev()
+        .map(|revision| {
+            let comment = match &revision.comment {
+                Some(comment) => {
+                    format!(r#"<span class="comment">({})</span>"#, escape_html(comment))
+                }
+                None => String::new(),
+            };
+            let prev = match revision.parent_id {
+                Some(parent_id) => format!(
+                    r#"<a href="diff/{}?from={}&to={}">prev</a>"#,
+                    encode(title),
+                    parent_id,
+                    revision.id
+                ),
+                None => "prev".to_string(),
+            };
+            format!(
+                r#"({} | <a href="diff/{}?from={}">cur</a>) <a href="revision/{}?title={}">{}</a> {} {} {}"#,
+                prev,
+                encode(title),
+                revision.id,
+                revision.id,
+                encode(title),
+                revision.timestamp.format("%H:%M, %e %B %Y"),
+                escape_html(revision.contributor.display_name()),
+                if revision.minor { "<b>m</b>" } else { "" },
+                comment
+            )
+        })
+        .collect();
+    let rows: Vec<&str> = rows.iter().map(|row| row.as_str()).collect();
+
+    let template = scope
+        .resources
+        .find_template("history.html")
+        .expect("Failed to find history template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("title", title);
+    ctx.set_str("title_href", &encode(title));
+    ctx.set_str("revision_count", &revisions.len().to_string());
+    ctx.set_str_array("revisions", &rows);
+
+    template
+        .render(&ctx)
+        .expect("Failed to render history template")
+}
+
+/// Renders the articles visited in all wikis, newest first. `query` is what
+/// they were filtered by.
+pub fn render_browsing_history_page(scope: &Scope, query: &str, visits: &[Visit]) -> String {
+    let rows: Vec<String> = visits
+        .iter()
+        .map(|visit| {
+            let time = match Local.timestamp_opt(visit.timestamp, 0).single() {
+                Some(time) => time.format("%H:%M, %e %B %Y").to_string(),
+                None => String::new(),
+            };
+            let wiki = match scope.wikis.len() > 1 {
+                true => format!(r#" <span class="wiki">{}</span>"#, escape_html(&visit.wiki)),
+                false => String::new(),
+            };
+            format!(
+                r#"{} <a href="/w/{}/article/{}">{}</a>{}"#,
+                time,
+                encode(&visit.wiki),
+                encode(&visit.title),
+                escape_html(&visit.title),
+                wiki
+            )
+        })
+        .collect();
+    let rows: Vec<&str> = rows.iter().map(|row| row.as_str()).collect();
+
+    let visit_count = match (visits.len(), query.trim().is_empty()) {
+        (0, true) => "No articles visited yet.".to_owned(),
+        (0, false) => "No visited articles match the search.".to_owned(),
+        (1, _) => "1 visit, newest first.".to_owned(),
+        (count, _) => format!("{} visits, newest first.", count),
+    };
+
+    let template = scope
+        .resources
+        .find_template("browsing.html")
+        .expect("Failed to find browsing history template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("query", &escape_html(query));
+    ctx.set_str("visit_count", &visit_count);
+    ctx.set_str_array("visits", &rows);
+
+    template
+        .render(&ctx)
+        .expect("Failed to render browsing history template")
+}
+
+/// Renders the bookmarks of all wikis grouped by their reading lists.
+/// `filtered` tells whether they were filtered by a list or tag, and `lists`
+/// and `tags` are the names of all reading lists and tags to filter by.
+pub fn render_bookmarks_page(
+    scope: &Scope,
+    bookmarks: &[Bookmark],
+    filtered: bool,
+    lists: &[String],
+    tags: &[String],
+) -> String {
+    let filter_links = |name: &str, values: &[String]| {
+        values
+            .iter()
+            .map(|value| {
+                format!(
+                    r#"<a href="bookmarks?{}={}">{}</a>"#,
+                    name,
+                    encode(value),
+                    escape_html(value)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let mut filters = Vec::new();
+    if !lists.is_empty() {
+        filters.push(format!("Lists: {}", filter_links("list", lists)));
+    }
+    if !tags.is_empty() {
+        filters.push(format!("Tags: {}", filter_links("tag", tags)));
+    }
+    if filtered {
+        filters.push(r#"<a href="bookmarks">Show all</a>"#.to_owned());
+    }
+
+    let mut groups = Vec::new();
+    for list in lists {
+        let items: Vec<String> = bookmarks
+            .iter()
+            .filter(|bookmark| bookmark.list == *list)
+            .map(|bookmark| render_bookmark(bookmark, scope.wikis.len() > 1))
+            .collect();
+        if !items.is_empty() {
+            groups.push(format!(
+                r#"<h2>{}</h2><ul class="bookmarks">{}</ul>"#,
+                escape_html(list),
+                items.concat()
+            ));
+        }
+    }
+    let groups: Vec<&str> = groups.iter().map(|group| group.as_str()).collect();
+
+    let bookmark_count = match (bookmarks.len(), filtered) {
+        (0, false) => {
+            "No bookmarks yet. Bookmark articles with the button next to their title.".to_owned()
+        }
+        (0, true) => "No bookmarks match the filter.".to_owned(),
+        (1, _) => "1 bookmark.".to_owned(),
+        (count, _) => format!("{} bookmarks.", count),
+    };
+
+    let template = scope
+        .resources
+        .find_template("bookmarks.html")
+        .expect("Failed to find bookmarks template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("filters", &filters.join(" &middot; "));
+    ctx.set_str("bookmark_count", &bookmark_count);
+    ctx.set_str_array("lists", &groups);
+
+    template
+        .render(&ctx)
+        .expect("Failed to render bookmarks template")
+}
+
+/// Renders a bookmark as a link to its article with its tags and note, and
+/// a form to edit them.
+fn render_bookmark(bookmark: &Bookmark, all_wikis: bool) -> String {
+    let mut html = format!(
+        r#"<li data-wiki="{}" data-title="{}"><a href="/w/{}/article/{}">{}</a>"#,
+        escape_html(&bookmark.wiki),
+        escape_html(&bookmark.title),
+        encode(&bookmark.wiki),
+        encode(&bookmark.title),
+        escape_html(&bookmark.title)
+    );
+    if all_wikis {
+        html.push_str(&format!(
+            r#" <span class="wiki">{}</span>"#,
+            escape_html(&bookmark.wiki)
+        ));
+    }
+    for tag in &bookmark.tags {
+        html.push_str(&format!(
+            r#" <a class="tag" href="bookmarks?tag={}">{}</a>"#,
+            encode(tag),
+            escape_html(tag)
+        ));
+    }
+    if !bookmark.note.is_empty() {
+        html.push_str(&format!(
+            r#"<p class="note">{}</p>"#,
+            escape_html(&bookmark.note)
+        ));
+    }
+
+    html.push_str(&format!(
+        r#"<details><summary>Edit</summary><form class="bookmark-edit">
+<label>List <input name="list" value="{}" /></label>
+<label>Tags <input name="tags" value="{}" placeholder="Separated by commas" /></label>
+<label>Note <input name="note" value="{}" /></label>
+<button type="submit">Save</button> <button type="button" class="remove">Remove</button>
+</form></details></li>"#,
+        escape_html(&bookmark.list),
+        escape_html(&bookmark.tags.join(", ")),
+        escape_html(&bookmark.note)
+    ));
+    html
+}
+
+pub fn render_diff_page(
+    scope: &Scope,
+    from: (&Article, bool),
+    to: (&Article, bool),
+    layout: DiffLayout,
+) -> String {
+    let (from_article, from_compare_dump) = from;
+    let (to_article, to_compare_dump) = to;
+    let blocks = diff(&from_article.body, &to_article.body);
+
+    let diff_html = match layout {
+        DiffLayout::SideBySide => render_side_by_side_diff(&blocks),
+        DiffLayout::Inline => render_inline_diff(&blocks),
+    };
+
+    let template = scope
+        .resources
+        .find_template("diff.html")
+        .expect("Failed to find diff template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("title", &to_article.title);
+    ctx.set_str("title_href", &encode(&to_article.title));
+    ctx.set_str("from", &revision_label(from_article, from_compare_dump));
+    ctx.set_str("to", &revision_label(to_article, to_compare_dump));
+    ctx.set_str("diff", &diff_html);
+
+    template
+        .render(&ctx)
+        .expect("Failed to render diff template")
+}
+
+fn revision_label(article: &Article, compare_dump: bool) -> String {
+    format!(
+        "Revision {} as of {} by {}{}",
+        article.revision.id,
+        article.revision.timestamp.format("%H:%M, %e %B %Y"),
+        escape_html(article.revision.contributor.display_name()),
+        if compare_dump {
+            " (comparison dump)"
+        } else {
+            ""
+        }
+    )
+}
+
+/// Shortens a run of unchanged lines to the lines next to the changes
+/// around it.
+fn context_lines<'a>(
+    lines: &[&'a str],
+    is_first: bool,
+    is_last: bool,
+) -> (Vec<&'a str>, usize, Vec<&'a str>) {
+    let before = if is_first { 0 } else { DIFF_CONTEXT_LINES };
+    let after = if is_last { 0 } else { DIFF_CONTEXT_LINES };
+
+    if lines.len() <= before + after {
+        return (lines.to_vec(), 0, Vec::new());
+    }
+
+    let hidden = lines.len() - before - after;
+    (
+        lines[..before].to_vec(),
+        hidden,
+        lines[lines.len() - after..].to_vec(),
+    )
+}
+
+fn render_side_by_side_diff(blocks: &[Block]) -> String {
+    let mut html = String::from(r#"<table class="diff side-by-side">"#);
+
+    for (i, block) in blocks.iter().enumerate() {
+        match block {
+            Block::Unchanged(lines) => {
+                let (head, hidden, tail) = context_lines(lines, i == 0, i == blocks.len() - 1);
+                for line in head {
+                    let line = escape_html(line);
+                    html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>", line, line));
+                }
+                if hidden > 0 {
+                    html.push_str(&format!(
+                        r#"<tr><td class="skipped" colspan="2">{} unchanged lines</td></tr>"#,
+                        hidden
+                    ));
+                }
+                for line in tail {
+                    let line = escape_html(line);
+                    html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>", line, line));
+                }
+            }
+            Block::Changed(changes) => {
+                let mut old = String::new();
+                let mut new = String::new();
+                for change in changes {
+                    match change {
+                        Change::Equal(text) => {
+                            old.push_str(&escape_html(text));
+                            new.push_str(&escape_html(text));
+                        }
+                        Change::Delete(text) => {
+                            old.push_str(&format!("<del>{}</del>", escape_html(text)))
+                        }
+                        Change::Insert(text) => {
+                            new.push_str(&format!("<ins>{}</ins>", escape_html(text)))
+                        }
+                    }
+                }
+                html.push_str(&format!(
+                    r#"<tr><td class="removed">{}</td><td class="added">{}</td></tr>"#,
+                    old, new
+                ));
+            }
+        }
+    }
+
+    html.push_str("</table>");
+    html
+}
+
+fn render_inline_diff(blocks: &[Block]) -> String {
+    let mut html = String::from(r#"<div class="diff inline">"#);
+
+    for (i, block) in blocks.iter().enumerate() {
+        match block {
+            Block::Unchanged(lines) => {
+                let (head, hidden, tail) = context_lines(lines, i == 0, i == blocks.len() - 1);
+                for line in head {
+                    html.push_str(&escape_html(line));
+                }
+                if hidden > 0 {
+                    html.push_str(&format!(
+                        r#"<div class="skipped">{} unchanged lines</div>"#,
+                        hidden
+                    ));
+                }
+                for line in tail {
+                    html.push_str(&escape_html(line));
+                }
+            }
+            Block::Changed(changes) => {
+                for change in changes {
+                    match change {
+                        Change::Equal(text) => html.push_str(&escape_html(text)),
+                        Change::Delete(text) => {
+                            html.push_str(&format!("<del>{}</del>", escape_html(text)))
+                        }
+                        Change::Insert(text) => {
+                            html.push_str(&format!("<ins>{}</ins>", escape_html(text)))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    html.push_str("</div>");
+    html
+}
+
+pub fn render_about_page(scope: &Scope) -> String {
+    let site_info = &scope.wiki.site_info;
+    let cache_stats = scope.wiki.article_db.cache_stats();
+
+    let namespaces: Vec<String> = site_info
+        .namespaces
+        .iter()
+        .map(|ns| match ns.name.as_str() {
+            "" => format!("{}: (Main)", ns.key),
+            name => format!("{}: {}", ns.key, name),
+        })
+        .collect();
+    let namespaces: Vec<&str> = namespaces.iter().map(|ns| ns.as_str()).collect();
+
+    let template = scope
+        .resources
+        .find_template("about.html")
+        .expect("Failed to find about template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("db_name", &site_info.db_name);
+    ctx.set_str("base", &site_info.base);
+    ctx.set_str("language", &site_info.language());
+    ctx.set_str("generator", &site_info.generator);
+    ctx.set_str("case", site_info.case.as_str());
+    ctx.set_str("article_count", &scope.wiki.index.size().to_string());
+    ctx.set_str_array("namespaces", &namespaces);
+    ctx.set_str(
+        "cache",
+        &format!(
+            "{} blocks, {:.1} of {:.1} MiB, {:.0}% hit rate ({} hits, {} misses)",
+            cache_stats.blocks,
+            cache_stats.used_bytes as f64 / (1024.0 * 1024.0),
+            cache_stats.budget_bytes as f64 / (1024.0 * 1024.0),
+            cache_stats.hit_rate() * 100.0,
+            cache_stats.hits,
+            cache_stats.misses
+        ),
+    );
+
+    template
+        .render(&ctx)
+        .expect("Failed to render about template")
+}
+
+/// Renders the page for a request that failed with the given status. For
+/// missing articles, `title` is the one asked for and `suggestions` are
+/// existing articles with similar titles.
+pub fn render_error_page(
+    scope: &Scope,
+    status: u16,
+    message: &str,
+    title: Option<&str>,
+    suggestions: &[&IndexEntry],
+) -> String {
+    let heading = match status {
+        400 => "Bad request",
+        404 => "Not found",
+        _ => "Something went wrong",
+    };
+    let explanation = match (status, title) {
+        (404, Some(title)) => format!(
+            "There is no article titled <i>{}</i> in {}.",
+            escape_html(title),
+            wiki_label(scope.wiki)
+        ),
+        _ => String::new(),
+    };
+
+    let suggestions: Vec<String> = suggestions
+        .iter()
+        .map(|entry| {
+            format!(
+                r#"<a href="article/{}">{}</a>"#,
+                encode(&entry.page_name),
+                escape_html(&entry.page_name)
+            )
+        })
+        .collect();
+    let suggestions: Vec<&str> = suggestions.iter().map(|link| link.as_str()).collect();
+    let suggestions_heading = match suggestions.is_empty() {
+        true => "",
+        false => "Did you mean one of these?",
+    };
+
+    let template = scope
+        .resources
+        .find_template("error.html")
+        .expect("Failed to find error template");
+
+    let mut ctx = scope.template_context();
+    ctx.set_str("status", &status.to_string());
+    ctx.set_str("heading", heading);
+    ctx.set_str("explanation", &explanation);
+    ctx.set_str("message", &escape_html(message));
+    ctx.set_str("suggestions_heading", suggestions_heading);
+    ctx.set_str_array("suggestions", &suggestions);
+
+    template
+        .render(&ctx)
+        .expect("Failed to render error template")
+}
+
+/// Names a wiki by its site and language, like `Wikipedia (de)`.
+fn wiki_label(wiki: &Wiki) -> String {
+    format!(
+        "{} ({})",
+        escape_html(&wiki.site_info.site_name),
+        wiki.site_info.language()
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,9 +1,12 @@
 use parse_wiki_text::{Configuration, Node};
 
-use crate::wiki::article::Article;
+use crate::wiki::{article::Article, summary::section_anchor};
 
 pub struct ArticleRenderer {
     html: String,
+    /// Path the links to articles and media files are relative to, empty
+    /// for links relative to the page of the article
+    base: String,
     is_italic: bool,
     is_bold: bool,
     is_bold_italic: bool,
@@ -12,8 +15,15 @@ pub struct ArticleRenderer {
 
 impl ArticleRenderer {
     pub fn new() -> Self {
+        Self::with_base("")
+    }
+
+    /// Creates a renderer whose links start with `base`, like `/w/en/` for
+    /// HTML that is shown outside of the article page.
+    pub fn with_base(base: &str) -> Self {
         Self {
             html: String::new(),
+            base: base.to_owned(),
             is_italic: false,
             is_bold: false,
             is_bold_italic: false,
@@ -76,8 +86,8 @@ impl ArticleRenderer {
             }
             Node::Category { target, .. } => {
                 self.append(&format!(
-                    r#"<a class="category" href="/article/{}">{}</a>"#,
-                    target, target
+                    r#"<a class="category" href="{}article/{}">{}</a>"#,
+                    self.base, target, target
                 ));
             }
             Node::CharacterEntity { character, .. } => {
@@ -99,7 +109,16 @@ impl ArticleRenderer {
             Node::Heading { level, nodes, .. } => {
                 let tag_name = format!("h{}", level);
 
-                self.open_tag(&tag_name);
+                // The id lets search results link to the section
+                let mut heading = String::new();
+                Self::collect_text(nodes, &mut heading);
+                self.append(&format!(
+                    r#"<{} id="{}">"#,
+                    tag_name,
+                    section_anchor(&heading)
+                        .replace('&', "&amp;")
+                        .replace('"', "&quot;")
+                ));
                 self.render_nodes(nodes);
                 self.close_tag(&tag_name);
             }
@@ -108,7 +127,7 @@ impl ArticleRenderer {
             }
             Node::Image { target, text, .. } => {
                 self.open_tag("figure");
-                self.append(&format!(r#"<img src="{}"/>"#, target));
+                self.append(&format!(r#"<img src="{}media/{}"/>"#, self.base, target));
 
                 self.open_tag("figcaption");
                 self.render_nodes(text);
@@ -116,15 +135,18 @@ impl ArticleRenderer {
                 self.close_tag("figure");
             }
             Node::Link { target, text, .. } => {
-                self.append(&format!("<a href=\"/article/{}\">", target));
+                self.append(&format!("<a href=\"{}article/{}\">", self.base, target));
                 self.render_nodes(text);
                 self.append("</a>");
             }
             Node::Redirect { target, .. } => {
-                self.append(&format!("<a href=\"/article/{}\">Redirect</a>", target));
+                self.append(&format!(
+                    "<a href=\"{}article/{}\">Redirect</a>",
+                    self.base, target
+                ));
             }
             Node::ExternalLink { nodes, .. } => {
-                self.append(r##"<a href="#">"##);
+                self.append(r#"<a class="external">"#);
                 self.render_nodes(nodes);
                 self.append("</a>");
             }
@@ -216,6 +238,22 @@ impl ArticleRenderer {
         }
     }
 
+    /// Collects the text of nodes, like the label of a link but not its
+    /// target.
+    fn collect_text(nodes: &[Node], text: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text { value, .. } => text.push_str(value),
+                Node::CharacterEntity { character, .. } => text.push(*character),
+                Node::Link { text: label, .. } => Self::collect_text(label, text),
+                Node::ExternalLink { nodes, .. } | Node::Tag { nodes, .. } => {
+                    Self::collect_text(nodes, text)
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn append(&mut self, data: &str) {
         self.html.push_str(data);
     }
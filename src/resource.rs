@@ -1,10 +1,8 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs, io, path::Path};
 
 use kata::Template;
-use wry::{
-    http::{Response, ResponseBuilder},
-    Error,
-};
+
+use crate::router::Response;
 
 pub enum MimeType {
     Text,
@@ -13,6 +11,8 @@ pub enum MimeType {
     Js,
     Png,
     Jpg,
+    Gif,
+    Svg,
     OctetStream,
 }
 
@@ -24,6 +24,8 @@ impl MimeType {
             ".js" => MimeType::Js,
             ".jpg" | ".jpeg" => MimeType::Jpg,
             ".png" => MimeType::Png,
+            ".gif" => MimeType::Gif,
+            ".svg" => MimeType::Svg,
             ".css" => MimeType::Css,
             _ => MimeType::OctetStream,
         }
@@ -37,6 +39,8 @@ impl MimeType {
             MimeType::Js => "application/javascript",
             MimeType::Png => "image/png",
             MimeType::Jpg => "image/jpg",
+            MimeType::Gif => "image/gif",
+            MimeType::Svg => "image/svg+xml",
             MimeType::OctetStream => "application/octet-stream",
         }
         .to_string()
@@ -50,6 +54,8 @@ impl MimeType {
             MimeType::Js => false,
             MimeType::Png => true,
             MimeType::Jpg => true,
+            MimeType::Gif => true,
+            MimeType::Svg => false,
             MimeType::OctetStream => true,
         }
     }
@@ -79,6 +85,15 @@ impl ResourceFile {
         }
     }
 
+    /// Reads a file from disk, e.g. from the media directory of a dump.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let name = path.to_string_lossy();
+        Ok(Self::new(
+            ResourceManager::infer_mime(&name),
+            fs::read(path)?,
+        ))
+    }
+
     pub fn to_binary(&self) -> Vec<u8> {
         match &self.data {
             ResourceData::Binary(bin) => bin.to_owned(),
@@ -96,11 +111,13 @@ impl ResourceFile {
     }
 }
 
-impl Into<Result<Response, Error>> for &ResourceFile {
-    fn into(self) -> Result<Response, Error> {
-        ResponseBuilder::new()
-            .mimetype(&self.mime_type)
-            .body(self.to_binary())
+impl Into<Response> for &ResourceFile {
+    fn into(self) -> Response {
+        Response {
+            status: 200,
+            mime_type: self.mime_type.clone(),
+            body: self.to_binary(),
+        }
     }
 }
 
@@ -145,8 +162,8 @@ impl ResourceManager {
         let extension_sep_idx = name.rfind(".");
         match extension_sep_idx {
             Some(extension_sep_idx) => {
-                let ext = &name[extension_sep_idx..];
-                MimeType::from_extension(ext)
+                let ext = name[extension_sep_idx..].to_lowercase();
+                MimeType::from_extension(&ext)
             }
             None => MimeType::OctetStream,
         }
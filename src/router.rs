@@ -0,0 +1,1597 @@
+use std::{cmp::Ordering, fmt, sync::Arc, time::Instant};
+
+use anyhow::bail;
+use chrono::Local;
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, ParallelIterator,
+};
+use serde::Serialize;
+use thiserror::Error;
+use url::Url;
+use urlencoding::decode;
+
+use crate::{
+    api,
+    bookmarks::{Bookmarks, BookmarksError},
+    history::BrowsingHistory,
+    library::Wiki,
+    pages::{
+        render_about_page, render_article_page, render_bookmarks_page,
+        render_browsing_history_page, render_diff_page, render_error_page, render_history_page,
+        render_results_page, render_revision_page, render_source_page, DiffLayout, Scope,
+    },
+    resource::{ResourceFile, ResourceManager},
+    wiki::{
+        article::{Article, ArticleDatabase, ArticleError},
+        index::{Index, IndexEntry, RankedResults, SearchResult},
+        links::LinksError,
+        query::{Query, QueryError},
+        revisions::RevisionsError,
+        summary::Summary,
+    },
+};
+
+/// Namespace key of media files, whose names start with `File:`.
+const FILE_NAMESPACE: i32 = 6;
+
+/// Most titles suggested on the error page for a missing article.
+const MAX_SUGGESTIONS: usize = 10;
+
+/// Results on a page of search results, unless an API request asks for a
+/// different number, up to `MAX_SEARCH_PAGE_SIZE`.
+const SEARCH_PAGE_SIZE: usize = 20;
+const MAX_SEARCH_PAGE_SIZE: usize = 100;
+
+/// Results of a search whose articles are loaded to show summaries of them.
+const MAX_SUMMARIES: usize = 20;
+
+/// Articles whose text is searched for the rest of a query that no title
+/// starts with, see `Router::search_text`.
+const MAX_TEXT_SEARCH_ARTICLES: usize = 20;
+
+/// Corrected queries offered on a search results page without results.
+const MAX_CORRECTIONS: usize = 3;
+
+/// Titles suggested while typing in the search box, unless the request
+/// asks for a different number, up to `MAX_TYPING_SUGGESTIONS`.
+const DEFAULT_TYPING_SUGGESTIONS: usize = 10;
+const MAX_TYPING_SUGGESTIONS: usize = 100;
+
+/// Method of a request, of those the router tells apart.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    /// Requests that change something, which links, images and forms of
+    /// other sites can't make without sending their origin along
+    Post,
+}
+
+/// A response to a request, independent of the front-end serving it.
+pub struct Response {
+    pub status: u16,
+    pub mime_type: String,
+    pub body: Vec<u8>,
+}
+
+/// A search result together with the wiki it was found in, and a summary of
+/// its article if that was loaded.
+pub struct SearchHit<'a> {
+    pub wiki: &'a Wiki,
+    pub result: SearchResult<'a>,
+    pub summary: Option<Summary>,
+}
+
+/// A page of the results of a search.
+pub struct SearchPage<'a> {
+    pub hits: Vec<SearchHit<'a>>,
+    /// Number of results on all pages together
+    pub total: usize,
+    /// Number of results on the pages before this one
+    pub start: usize,
+    /// Where the next page starts, if there is one
+    pub next: Option<Cursor>,
+}
+
+/// The last result of a page of search results, after which the next page
+/// starts. Results are ordered by their score, then by the name of their
+/// wiki and then by their order in the index, so a cursor continues at the
+/// same place no matter how many results there are.
+#[derive(Debug)]
+pub struct Cursor {
+    /// Number of results up to and including this one
+    skipped: usize,
+    score: f64,
+    wiki: String,
+    order: usize,
+}
+
+/// Handles the URLs of the app. The window and the headless server both
+/// hand their requests to the same router, from the server's worker threads.
+///
+/// Pages of a wiki live under `/w/<wiki>/`. URLs without that prefix refer
+/// to the default wiki, which is the first one.
+pub struct Router {
+    wikis: Vec<Wiki>,
+    resources: ResourceManager,
+    history: Arc<BrowsingHistory>,
+    bookmarks: Bookmarks,
+}
+
+#[derive(Debug)]
+enum ParsedUrl {
+    Resource(String),
+    Media(String),
+    StartPage,
+    Article(String),
+    /// A random article, from a category if one is given
+    Random {
+        category: Option<String>,
+    },
+    Search {
+        query: String,
+        all_wikis: bool,
+        cursor: Option<Cursor>,
+    },
+    Source(String),
+    History(String),
+    /// The articles visited in the app, filtered by a search
+    BrowsingHistory {
+        query: String,
+    },
+    /// The bookmarks, on a reading list and with a tag if given
+    Bookmarks {
+        list: Option<String>,
+        tag: Option<String>,
+    },
+    /// A revision, looked up by its id unless the title of its article is
+    /// given
+    Revision {
+        id: u64,
+        title: Option<String>,
+    },
+    Diff {
+        title: String,
+        from: RevisionSpec,
+        to: RevisionSpec,
+        layout: DiffLayout,
+    },
+    About,
+    Api(ApiRequest),
+}
+
+#[derive(Debug)]
+enum ApiRequest {
+    Article(String),
+    Search {
+        query: String,
+        all_wikis: bool,
+        cursor: Option<Cursor>,
+        limit: usize,
+    },
+    Suggest {
+        query: String,
+        limit: usize,
+    },
+    SiteInfo,
+    Random {
+        category: Option<String>,
+    },
+    History {
+        query: String,
+    },
+    /// Remembers how far an article was scrolled down when it was left
+    SetScroll {
+        title: String,
+        position: u32,
+    },
+    ClearHistory,
+    /// All bookmarks, in the format that `ImportBookmarks` reads
+    Bookmarks,
+    ToggleBookmark {
+        title: String,
+    },
+    UpdateBookmark {
+        title: String,
+        list: String,
+        tags: String,
+        note: String,
+    },
+    RemoveBookmark {
+        title: String,
+    },
+    /// Imports the bookmarks in the body of the request
+    ImportBookmarks,
+}
+
+/// Selects a revision in a diff URL: `123` is revision 123, `latest` the
+/// current revision, and a `compare:` prefix selects it from the
+/// comparison dump instead.
+#[derive(Debug)]
+struct RevisionSpec {
+    compare_dump: bool,
+    revision: Option<u64>,
+}
+
+#[derive(Error, Debug)]
+pub enum UrlError {
+    #[error("url namespace not found")]
+    UnknownNamespace,
+
+    #[error("url path is incomplete")]
+    IncompletePath,
+
+    #[error("missing query parameter")]
+    MissingParameter,
+
+    #[error("url refers to an unknown dump")]
+    UnknownDump,
+
+    #[error("url refers to an unknown wiki")]
+    UnknownWiki,
+
+    #[error("requested resource not found")]
+    ResourceNotFound,
+
+    #[error("invalid search results cursor")]
+    InvalidCursor,
+
+    #[error("request method not allowed")]
+    MethodNotAllowed,
+}
+
+/// Returned by handlers that gave up on a request because a newer navigation
+/// superseded it, so that nobody waits for its response anymore.
+#[derive(Error, Debug)]
+#[error("request was superseded by a newer navigation")]
+pub struct Superseded;
+
+/// Why a request failed, which decides the status it is answered with.
+#[derive(Error, Debug)]
+enum RequestError {
+    /// The URL could not be parsed, or refers to something that doesn't exist
+    #[error("{0:#}")]
+    BadUrl(anyhow::Error),
+
+    /// The URL is fine, but handling it failed
+    #[error("{0:#}")]
+    Failed(anyhow::Error),
+}
+
+impl RequestError {
+    fn status(&self) -> u16 {
+        let (err, default_status) = match self {
+            RequestError::BadUrl(err) => (err, 400),
+            RequestError::Failed(err) => (err, 500),
+        };
+
+        if err.is::<Superseded>() {
+            return 503;
+        }
+
+        if let Some(err) = err.downcast_ref::<UrlError>() {
+            return match err {
+                UrlError::UnknownNamespace | UrlError::UnknownWiki | UrlError::ResourceNotFound => {
+                    404
+                }
+                UrlError::IncompletePath
+                | UrlError::MissingParameter
+                | UrlError::UnknownDump
+                | UrlError::InvalidCursor => 400,
+                UrlError::MethodNotAllowed => 405,
+            };
+        }
+
+        if err.is::<QueryError>()
+            || err.is::<LinksError>()
+            || err.is::<RevisionsError>()
+            || err.is::<BookmarksError>()
+        {
+            return 400;
+        }
+
+        match err.downcast_ref::<ArticleError>() {
+            Some(ArticleError::ArticleNotFound | ArticleError::RevisionNotFound) => 404,
+            Some(ArticleError::MissingProperty(_)) => 500,
+            None => default_status,
+        }
+    }
+}
+
+impl ParsedUrl {
+    /// Title of the article the URL refers to, if any.
+    fn title(&self) -> Option<&str> {
+        match self {
+            ParsedUrl::Article(title)
+            | ParsedUrl::Source(title)
+            | ParsedUrl::History(title)
+            | ParsedUrl::Diff { title, .. }
+            | ParsedUrl::Api(ApiRequest::Article(title)) => Some(title),
+            ParsedUrl::Revision { title, .. } => title.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl ApiRequest {
+    /// Whether the request changes the history or the bookmarks, which
+    /// only POST requests may.
+    fn changes_state(&self) -> bool {
+        matches!(
+            self,
+            ApiRequest::SetScroll { .. }
+                | ApiRequest::ClearHistory
+                | ApiRequest::ToggleBookmark { .. }
+                | ApiRequest::UpdateBookmark { .. }
+                | ApiRequest::RemoveBookmark { .. }
+                | ApiRequest::ImportBookmarks
+        )
+    }
+}
+
+impl RevisionSpec {
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (compare_dump, revision) = match spec.strip_prefix("compare:") {
+            Some(revision) => (true, revision),
+            None => (false, spec),
+        };
+
+        let revision = match revision {
+            "latest" => None,
+            revision => Some(revision.parse()?),
+        };
+
+        Ok(Self {
+            compare_dump,
+            revision,
+        })
+    }
+}
+
+impl Cursor {
+    /// Parses a cursor in the format it is displayed in, see [`fmt::Display`].
+    fn parse(text: &str) -> Result<Cursor, UrlError> {
+        let mut parts = text.splitn(4, '.');
+        let mut next_part = || parts.next().ok_or(UrlError::InvalidCursor);
+        let skipped = next_part()?.parse().map_err(|_| UrlError::InvalidCursor)?;
+        let score = u64::from_str_radix(next_part()?, 16).map_err(|_| UrlError::InvalidCursor)?;
+        let order = next_part()?.parse().map_err(|_| UrlError::InvalidCursor)?;
+        let wiki = next_part()?.to_owned();
+
+        Ok(Cursor {
+            skipped,
+            score: f64::from_bits(score),
+            wiki,
+            order,
+        })
+    }
+
+    fn after(hit: &SearchHit, skipped: usize) -> Cursor {
+        Cursor {
+            skipped,
+            score: hit.result.score,
+            wiki: hit.wiki.name.clone(),
+            order: hit.result.order,
+        }
+    }
+
+    /// Whether a result of a wiki comes after the cursor, the way
+    /// [`Router::search`] orders results.
+    fn precedes(&self, wiki: &str, score: f64, order: usize) -> bool {
+        let ordering = self
+            .score
+            .total_cmp(&score)
+            .then(wiki.cmp(&self.wiki))
+            .then(order.cmp(&self.order));
+        ordering == Ordering::Greater
+    }
+}
+
+/// Displays a cursor as `skipped.score.order.wiki`, with the exact bits of
+/// the score in hex so that it survives the round trip.
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:x}.{}.{}",
+            self.skipped,
+            self.score.to_bits(),
+            self.order,
+            self.wiki
+        )
+    }
+}
+
+/// Parses a URL into the name of the wiki it is scoped to, if any, and
+/// what it refers to in that wiki.
+fn parse_url(url: &str) -> anyhow::Result<(Option<String>, ParsedUrl)> {
+    let url = Url::parse(url)?;
+    let mut path = url
+        .path_segments()
+        .ok_or(UrlError::IncompletePath)?
+        .peekable();
+
+    let wiki = match path.peek() {
+        Some(&"w") => {
+            path.next();
+            let wiki = path.next().ok_or(UrlError::IncompletePath)?;
+            Some(decode(wiki)?.to_string())
+        }
+        _ => None,
+    };
+
+    let mut next_path_part = move || return path.next().ok_or(UrlError::IncompletePath);
+    let namespace = decode(next_path_part().unwrap_or_default())?;
+
+    let mut next_path_part_string = || anyhow::Ok(decode(next_path_part()?)?.to_string());
+    let query = url.query_pairs().next();
+    let query_param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+            .ok_or(UrlError::MissingParameter)
+    };
+
+    let all_wikis = query_param("scope").is_ok_and(|scope| scope == "all");
+    // Only searches are paged, so other pages ignore a cursor
+    let cursor = || match query_param("cursor") {
+        Ok(cursor) => Cursor::parse(&cursor).map(Some),
+        Err(_) => Ok(None),
+    };
+
+    let parsed_url = match &*namespace {
+        "res" => ParsedUrl::Resource(next_path_part_string()?),
+        "media" => ParsedUrl::Media(next_path_part_string()?),
+        "article" => ParsedUrl::Article(next_path_part_string()?),
+        "random" => ParsedUrl::Random {
+            category: query_param("category").ok(),
+        },
+        "search" => ParsedUrl::Search {
+            query: query.ok_or(UrlError::MissingParameter)?.1.to_string(),
+            all_wikis,
+            cursor: cursor()?,
+        },
+        "source" => ParsedUrl::Source(next_path_part_string()?),
+        // Without a title, the history is the one of the app rather than of
+        // an article
+        "history" => match next_path_part_string() {
+            Ok(title) if !title.is_empty() => ParsedUrl::History(title),
+            Err(err) if !err.is::<UrlError>() => return Err(err),
+            _ => ParsedUrl::BrowsingHistory {
+                query: query_param("q").unwrap_or_default(),
+            },
+        },
+        "revision" => ParsedUrl::Revision {
+            id: next_path_part_string()?.parse()?,
+            title: query_param("title").ok(),
+        },
+        "diff" => ParsedUrl::Diff {
+            title: next_path_part_string()?,
+            from: RevisionSpec::parse(&query_param("from")?)?,
+            to: RevisionSpec::parse(&query_param("to").unwrap_or_else(|_| "latest".to_string()))?,
+            layout: match query_param("layout").as_deref() {
+                Ok("inline") => DiffLayout::Inline,
+                _ => DiffLayout::SideBySide,
+            },
+        },
+        "bookmarks" => ParsedUrl::Bookmarks {
+            list: query_param("list").ok(),
+            tag: query_param("tag").ok(),
+        },
+        "about" => ParsedUrl::About,
+        "api" => ParsedUrl::Api(match next_path_part_string()?.as_str() {
+            "article" => ApiRequest::Article(next_path_part_string()?),
+            "search" => ApiRequest::Search {
+                query: query_param("q")?,
+                all_wikis,
+                cursor: cursor()?,
+                limit: match query_param("limit") {
+                    Ok(limit) => limit.parse::<usize>()?.clamp(1, MAX_SEARCH_PAGE_SIZE),
+                    Err(_) => SEARCH_PAGE_SIZE,
+                },
+            },
+            "suggest" => ApiRequest::Suggest {
+                query: query_param("q")?,
+                limit: match query_param("limit") {
+                    Ok(limit) => limit.parse::<usize>()?.min(MAX_TYPING_SUGGESTIONS),
+                    Err(_) => DEFAULT_TYPING_SUGGESTIONS,
+                },
+            },
+            "siteinfo" => ApiRequest::SiteInfo,
+            "random" => ApiRequest::Random {
+                category: query_param("category").ok(),
+            },
+            "history" => match next_path_part_string().as_deref() {
+                Ok("scroll") => ApiRequest::SetScroll {
+                    title: query_param("title")?,
+                    position: query_param("position")?.parse()?,
+                },
+                Ok("clear") => ApiRequest::ClearHistory,
+                Ok("") | Err(_) => ApiRequest::History {
+                    query: query_param("q").unwrap_or_default(),
+                },
+                Ok(_) => bail!(UrlError::UnknownNamespace),
+            },
+            "bookmarks" => match next_path_part_string().as_deref() {
+                Ok("toggle") => ApiRequest::ToggleBookmark {
+                    title: query_param("title")?,
+                },
+                Ok("update") => ApiRequest::UpdateBookmark {
+                    title: query_param("title")?,
+                    list: query_param("list").unwrap_or_default(),
+                    tags: query_param("tags").unwrap_or_default(),
+                    note: query_param("note").unwrap_or_default(),
+                },
+                Ok("remove") => ApiRequest::RemoveBookmark {
+                    title: query_param("title")?,
+                },
+                Ok("import") => ApiRequest::ImportBookmarks,
+                Ok("") | Err(_) => ApiRequest::Bookmarks,
+                Ok(_) => bail!(UrlError::UnknownNamespace),
+            },
+            _ => bail!(UrlError::UnknownNamespace),
+        }),
+        "" => ParsedUrl::StartPage,
+        _ => bail!(UrlError::UnknownNamespace),
+    };
+
+    Ok((wiki, parsed_url))
+}
+
+fn load_revision(
+    index: &Index,
+    article_db: &ArticleDatabase,
+    title: &str,
+    revision: Option<u64>,
+) -> anyhow::Result<Article> {
+    let article = match index.find_article_exact(title) {
+        Some(article) => article,
+        None => bail!(ArticleError::ArticleNotFound),
+    };
+
+    match revision {
+        Some(revision) => article_db.get_revision(article, revision),
+        None => article_db.get_article(article),
+    }
+}
+
+impl Response {
+    pub fn html(html: String) -> Self {
+        Self {
+            status: 200,
+            mime_type: "text/html".to_string(),
+            body: html.into_bytes(),
+        }
+    }
+
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Self {
+            status,
+            mime_type: "application/json".to_string(),
+            body: serde_json::to_vec(value).expect("Failed to serialize JSON response"),
+        }
+    }
+
+    /// A response without a body, to API requests that only change something.
+    pub fn empty() -> Self {
+        Self {
+            status: 204,
+            mime_type: "text/plain".to_string(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl Router {
+    /// Creates a router for the given wikis, the first of which is the
+    /// default one, which records the articles it serves in `history`.
+    pub fn new(
+        wikis: Vec<Wiki>,
+        resources: ResourceManager,
+        history: Arc<BrowsingHistory>,
+        bookmarks: Bookmarks,
+    ) -> Self {
+        assert!(!wikis.is_empty(), "Router needs at least one wiki");
+        Self {
+            wikis,
+            resources,
+            history,
+            bookmarks,
+        }
+    }
+
+    pub fn default_wiki(&self) -> &Wiki {
+        &self.wikis[0]
+    }
+
+    /// Answers the request for a URL, with the body of the request if it
+    /// has one. Handlers of slow pages give up once `superseded` returns
+    /// true, as nobody waits for their response then.
+    pub fn handle(
+        &self,
+        url: &str,
+        method: Method,
+        body: &[u8],
+        superseded: &dyn Fn() -> bool,
+    ) -> Response {
+        let (wiki, parsed_url) = match self.resolve(url, method) {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                let err = RequestError::BadUrl(err);
+                return self.error_response(url, self.default_wiki(), None, &err);
+            }
+        };
+
+        let title = match &parsed_url {
+            ParsedUrl::StartPage => wiki.start_page.clone(),
+            parsed_url => parsed_url.title().map(|title| title.to_owned()),
+        };
+        match self.route(wiki, parsed_url, body, superseded) {
+            Ok(response) => response,
+            Err(err) => {
+                let err = RequestError::Failed(err);
+                self.error_response(url, wiki, title.as_deref(), &err)
+            }
+        }
+    }
+
+    /// Parses a URL and finds the wiki it refers to.
+    fn resolve(&self, url: &str, method: Method) -> anyhow::Result<(&Wiki, ParsedUrl)> {
+        let (wiki, parsed_url) = parse_url(url)?;
+        if matches!(&parsed_url, ParsedUrl::Api(request) if request.changes_state())
+            && method != Method::Post
+        {
+            bail!(UrlError::MethodNotAllowed);
+        }
+
+        let wiki = match wiki {
+            Some(name) => match self.wikis.iter().find(|wiki| wiki.name == name) {
+                Some(wiki) => wiki,
+                None => bail!(UrlError::UnknownWiki),
+            },
+            None => self.default_wiki(),
+        };
+
+        Ok((wiki, parsed_url))
+    }
+
+    fn route(
+        &self,
+        wiki: &Wiki,
+        parsed_url: ParsedUrl,
+        body: &[u8],
+        superseded: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Response> {
+        match parsed_url {
+            ParsedUrl::Article(name) => self.article(wiki, &name, superseded),
+            ParsedUrl::StartPage => match &wiki.start_page {
+                Some(start_page) => self.article(wiki, start_page, superseded),
+                None => {
+                    let article = wiki.article_of_the_day(Local::now().date_naive())?;
+                    self.article(wiki, &article.page_name, superseded)
+                }
+            },
+            ParsedUrl::Random { category } => {
+                let article = wiki.random_article(category.as_deref(), &mut rand::thread_rng())?;
+                self.article(wiki, &article.page_name, superseded)
+            }
+            ParsedUrl::Search {
+                query,
+                all_wikis,
+                cursor,
+            } => {
+                let parsed = Query::parse(&query)?;
+                let page = self.search(
+                    wiki,
+                    &query,
+                    &parsed,
+                    all_wikis,
+                    cursor.as_ref(),
+                    SEARCH_PAGE_SIZE,
+                )?;
+                // Corrections are only found for titles, not for filters
+                let corrections = match page.total == 0 && parsed.is_plain() {
+                    true => wiki
+                        .index
+                        .correct_prefix(&query.trim().replace('_', " "), MAX_CORRECTIONS),
+                    false => Vec::new(),
+                };
+                Ok(Response::html(render_results_page(
+                    &self.scope(wiki),
+                    &query,
+                    &page,
+                    &corrections,
+                    all_wikis,
+                )))
+            }
+            ParsedUrl::Source(name) => self.source(wiki, &name),
+            ParsedUrl::History(name) => self.history(wiki, &name, superseded),
+            ParsedUrl::BrowsingHistory { query } => {
+                Ok(Response::html(render_browsing_history_page(
+                    &self.scope(wiki),
+                    &query,
+                    &self.history.search(&query),
+                )))
+            }
+            ParsedUrl::Revision { id, title } => {
+                self.revision(wiki, id, title.as_deref(), superseded)
+            }
+            ParsedUrl::Diff {
+                title,
+                from,
+                to,
+                layout,
+            } => self.diff(wiki, &title, &from, &to, layout, superseded),
+            ParsedUrl::Bookmarks { list, tag } => {
+                let bookmarks = self.bookmarks.filter(list.as_deref(), tag.as_deref());
+                let (lists, tags) = self.bookmarks.lists_and_tags();
+                Ok(Response::html(render_bookmarks_page(
+                    &self.scope(wiki),
+                    &bookmarks,
+                    list.is_some() || tag.is_some(),
+                    &lists,
+                    &tags,
+                )))
+            }
+            ParsedUrl::About => Ok(Response::html(render_about_page(&self.scope(wiki)))),
+            ParsedUrl::Api(request) => self.api(wiki, request, body),
+            ParsedUrl::Resource(name) => match self.resources.find_resource(&name) {
+                Some(resource) => Ok(resource.into()),
+                None => bail!(UrlError::ResourceNotFound),
+            },
+            ParsedUrl::Media(name) => self.media(wiki, &name),
+        }
+    }
+
+    /// Answers a failed request with an error page, or with a JSON error for
+    /// API requests. Pages for missing articles suggest similar titles.
+    fn error_response(
+        &self,
+        url: &str,
+        wiki: &Wiki,
+        title: Option<&str>,
+        err: &RequestError,
+    ) -> Response {
+        let status = err.status();
+        if status == 503 {
+            println!("Gave up on {}: {}", url, err);
+            return Response::html(String::new()).with_status(status);
+        }
+        if status >= 500 {
+            println!("Failed to handle {}: {}", url, err);
+        }
+
+        if Self::is_api_url(url) {
+            return api::error_response(status, &err.to_string());
+        }
+
+        let title = title.map(|title| wiki.site_info.normalize_title(title));
+        let suggestions = match (status, &title) {
+            (404, Some(title)) => Self::suggest_titles(wiki, title),
+            _ => Vec::new(),
+        };
+        let page = render_error_page(
+            &self.scope(wiki),
+            status,
+            &err.to_string(),
+            title.as_deref(),
+            &suggestions,
+        );
+
+        Response::html(page).with_status(status)
+    }
+
+    /// Finds titles similar to one that doesn't exist: those a few typos
+    /// away, followed by those starting with ever shorter prefixes of it,
+    /// first dropping its words from the end, then trying the start of its
+    /// first word.
+    fn suggest_titles<'a>(wiki: &'a Wiki, title: &str) -> Vec<&'a IndexEntry> {
+        let mut suggestions = wiki.index.find_similar(title, MAX_SUGGESTIONS);
+
+        let mut prefixes: Vec<&str> = title
+            .char_indices()
+            .filter(|(_, chr)| *chr == ' ')
+            .map(|(idx, _)| &title[..idx])
+            .rev()
+            .collect();
+        prefixes.insert(0, title);
+        if let Some((idx, _)) = title.char_indices().nth(3) {
+            prefixes.push(&title[..idx]);
+        }
+
+        if let Some(results) = prefixes
+            .into_iter()
+            .map(|prefix| wiki.index.search(prefix, MAX_SUGGESTIONS))
+            .find(|results| !results.is_empty())
+        {
+            for SearchResult { entry, .. } in results {
+                if !suggestions.iter().any(|known| std::ptr::eq(*known, entry)) {
+                    suggestions.push(entry);
+                }
+            }
+        }
+
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    fn scope<'a>(&'a self, wiki: &'a Wiki) -> Scope<'a> {
+        Scope {
+            resources: &self.resources,
+            wiki,
+            wikis: &self.wikis,
+        }
+    }
+
+    /// Searches a wiki, or all wikis at once, for a page of up to `limit`
+    /// results after `cursor`. Plain queries are searched for in the titles,
+    /// falling back to the text of articles if no title starts with them,
+    /// and others are executed as structured queries. Results from different
+    /// wikis are ranked together by their scores, and summarized.
+    fn search<'a>(
+        &'a self,
+        wiki: &'a Wiki,
+        query: &str,
+        parsed: &Query,
+        all_wikis: bool,
+        cursor: Option<&Cursor>,
+        limit: usize,
+    ) -> anyhow::Result<SearchPage<'a>> {
+        let wikis = match all_wikis {
+            true => &self.wikis[..],
+            false => std::slice::from_ref(wiki),
+        };
+        let is_after = |wiki: &Wiki, score: f64, order: usize| {
+            cursor.is_none_or(|cursor| cursor.precedes(&wiki.name, score, order))
+        };
+
+        // Every wiki contributes up to a page of results, of which the best
+        // make up the page
+        let results = wikis
+            .par_iter()
+            .map(|wiki| {
+                let is_after = |score, order| is_after(wiki, score, order);
+                let results = match parsed.is_plain() {
+                    true => wiki.index.search_after(query, is_after, limit),
+                    false => parsed.execute(
+                        &wiki.index,
+                        &wiki.site_info,
+                        wiki.links.as_ref(),
+                        is_after,
+                        limit,
+                    )?,
+                };
+                Ok((wiki, results))
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+
+        let mut total = 0;
+        let mut remaining = 0;
+        let mut hits: Vec<SearchHit> = Vec::new();
+        for (wiki, results) in results {
+            let RankedResults {
+                results,
+                total: wiki_total,
+                remaining: wiki_remaining,
+            } = results;
+            total += wiki_total;
+            remaining += wiki_remaining;
+            hits.extend(results.into_iter().map(|result| SearchHit {
+                wiki,
+                result,
+                summary: None,
+            }));
+        }
+        if total == 0 && parsed.is_plain() {
+            hits = wikis
+                .par_iter()
+                .flat_map_iter(|wiki| Self::search_text(wiki, query))
+                .collect();
+            total = hits.len();
+            hits.retain(|hit| is_after(hit.wiki, hit.result.score, hit.result.order));
+            remaining = hits.len();
+        }
+
+        hits.sort_by(|left, right| {
+            right
+                .result
+                .score
+                .total_cmp(&left.result.score)
+                .then(left.wiki.name.cmp(&right.wiki.name))
+                .then(left.result.order.cmp(&right.result.order))
+        });
+        hits.truncate(limit);
+
+        hits.par_iter_mut()
+            .take(MAX_SUMMARIES)
+            .filter(|hit| hit.summary.is_none())
+            .for_each(|hit| {
+                // A result is still worth showing without its summary
+                if let Ok(article) = hit.wiki.article_db.get_article(hit.result.entry) {
+                    hit.summary = Some(Summary::of(&article, &[]));
+                }
+            });
+
+        let start = cursor.map_or(0, |cursor| cursor.skipped);
+        let next = match (remaining > hits.len(), hits.last()) {
+            (true, Some(last)) => Some(Cursor::after(last, start + hits.len())),
+            _ => None,
+        };
+        Ok(SearchPage {
+            hits,
+            total,
+            start,
+            next,
+        })
+    }
+
+    /// Searches the text of the articles whose titles start with the first
+    /// words of a query for its remaining words, as in `Rust ownership`.
+    /// The first words are the most that any title starts with, and matches
+    /// are ranked like their titles.
+    fn search_text<'a>(wiki: &'a Wiki, query: &str) -> Vec<SearchHit<'a>> {
+        let words: Vec<&str> = query
+            .split(|chr: char| chr.is_whitespace() || chr == '_')
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let (results, terms) = match (1..words.len()).rev().find_map(|count| {
+            let results = wiki
+                .index
+                .search(&words[..count].join(" "), MAX_TEXT_SEARCH_ARTICLES);
+            (!results.is_empty()).then_some((results, &words[count..]))
+        }) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+
+        results
+            .into_par_iter()
+            .filter_map(|result| {
+                let article = wiki.article_db.get_article(result.entry).ok()?;
+                let summary = Summary::of(&article, terms);
+                summary.text_match.as_ref()?;
+                Some(SearchHit {
+                    wiki,
+                    result,
+                    summary: Some(summary),
+                })
+            })
+            .collect()
+    }
+
+    /// Serves a file that articles embed from the media directory of the
+    /// dump, where files are stored under their names with underscores.
+    fn media(&self, wiki: &Wiki, name: &str) -> anyhow::Result<Response> {
+        let media_root = match &wiki.media_root {
+            Some(media_root) => media_root,
+            None => bail!(UrlError::ResourceNotFound),
+        };
+
+        let name = wiki.site_info.normalize_title(name);
+        let file_name = match wiki.site_info.namespace_of(&name) {
+            Some(ns) if ns.key == FILE_NAMESPACE => &name[ns.name.len() + 1..],
+            _ => &name,
+        };
+        let file_name = file_name.replace(' ', "_");
+        if file_name.starts_with('.') || file_name.contains(['/', '\\']) {
+            bail!(UrlError::ResourceNotFound);
+        }
+
+        match ResourceFile::from_path(&media_root.join(file_name)) {
+            Ok(file) => Ok((&file).into()),
+            Err(_) => bail!(UrlError::ResourceNotFound),
+        }
+    }
+
+    /// Whether the URL is of a page the user navigates to, as opposed to a
+    /// resource, media file or API call that a page loads.
+    pub fn is_page_url(url: &str) -> bool {
+        matches!(
+            parse_url(url),
+            Ok((_, parsed_url)) if !matches!(
+                parsed_url,
+                ParsedUrl::Resource(_) | ParsedUrl::Media(_) | ParsedUrl::Api(_)
+            )
+        )
+    }
+
+    fn is_api_url(url: &str) -> bool {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        let mut path = url.path().trim_start_matches('/').split('/');
+        match path.next() {
+            Some("w") => path.nth(1) == Some("api"),
+            namespace => namespace == Some("api"),
+        }
+    }
+
+    fn api(&self, wiki: &Wiki, request: ApiRequest, body: &[u8]) -> anyhow::Result<Response> {
+        match request {
+            ApiRequest::Article(name) => self.api_article(wiki, Self::find_article(wiki, &name)?),
+            ApiRequest::Random { category } => self.api_article(
+                wiki,
+                wiki.random_article(category.as_deref(), &mut rand::thread_rng())?,
+            ),
+            ApiRequest::Search {
+                query,
+                all_wikis,
+                cursor,
+                limit,
+            } => {
+                let parsed = Query::parse(&query)?;
+                Ok(api::search_response(
+                    &query,
+                    &self.search(wiki, &query, &parsed, all_wikis, cursor.as_ref(), limit)?,
+                ))
+            }
+            ApiRequest::Suggest { query, limit } => {
+                // Not normalized like a title, as a trailing space while typing
+                // is the end of a word
+                let prefix = query.trim_start().replace('_', " ");
+                // Every title starts with an empty prefix, which is nothing
+                // worth ranking all of them for
+                let suggestions = match prefix.is_empty() {
+                    true => Vec::new(),
+                    false => wiki.index.search(&prefix, limit),
+                };
+                Ok(api::suggest_response(
+                    &query,
+                    &wiki.name,
+                    &suggestions
+                        .iter()
+                        .map(|result| result.entry)
+                        .collect::<Vec<&IndexEntry>>(),
+                ))
+            }
+            ApiRequest::SiteInfo => Ok(api::site_info_response(&wiki.site_info, wiki.index.size())),
+            ApiRequest::History { query } => {
+                Ok(api::history_response(&self.history.search(&query)))
+            }
+            ApiRequest::SetScroll { title, position } => {
+                self.history.set_scroll(&wiki.name, &title, position);
+                Ok(Response::empty())
+            }
+            ApiRequest::ClearHistory => {
+                self.history.clear()?;
+                Ok(Response::empty())
+            }
+            ApiRequest::Bookmarks => Ok(api::bookmarks_response(&self.bookmarks.all())),
+            ApiRequest::ToggleBookmark { title } => {
+                let article = Self::find_article(wiki, &title)?;
+                let bookmarked = self.bookmarks.toggle(&wiki.name, &article.page_name)?;
+                Ok(api::bookmark_toggle_response(bookmarked))
+            }
+            ApiRequest::UpdateBookmark {
+                title,
+                list,
+                tags,
+                note,
+            } => {
+                let article = Self::find_article(wiki, &title)?;
+                let tags: Vec<&str> = tags.split(',').collect();
+                self.bookmarks
+                    .update(&wiki.name, &article.page_name, &list, &tags, &note)?;
+                Ok(Response::empty())
+            }
+            ApiRequest::RemoveBookmark { title } => {
+                let title = wiki.site_info.normalize_title(&title);
+                self.bookmarks.remove(&wiki.name, &title)?;
+                Ok(Response::empty())
+            }
+            ApiRequest::ImportBookmarks => {
+                let wikis: Vec<&str> = self.wikis.iter().map(|wiki| wiki.name.as_str()).collect();
+                let count = self.bookmarks.import(body, &wikis)?;
+                Ok(api::bookmarks_import_response(count))
+            }
+        }
+    }
+
+    fn api_article(&self, wiki: &Wiki, article: &IndexEntry) -> anyhow::Result<Response> {
+        let article_data = wiki.article_db.get_article(article)?;
+        Ok(api::article_response(&article_data, &wiki.name))
+    }
+
+    /// Looks up the index entry of an article by its title as given in a URL.
+    fn find_article<'a>(wiki: &'a Wiki, name: &str) -> anyhow::Result<&'a IndexEntry> {
+        let name_cleaned = wiki.site_info.normalize_title(name);
+        match wiki.index.find_article_exact(&name_cleaned) {
+            Some(article) => Ok(article),
+            None => bail!(ArticleError::ArticleNotFound),
+        }
+    }
+
+    fn article(
+        &self,
+        wiki: &Wiki,
+        name: &str,
+        superseded: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Response> {
+        println!("Loading article {}", name);
+        let time = Instant::now();
+
+        let article = Self::find_article(wiki, name)?;
+        println!("Located article in {:.2?}", time.elapsed());
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        let time = Instant::now();
+        let article_data = wiki.article_db.get_article(article)?;
+        let cache_stats = wiki.article_db.cache_stats();
+        println!(
+            "Extracted article in {:.2?} (block cache: {} hits, {} misses)",
+            time.elapsed(),
+            cache_stats.hits,
+            cache_stats.misses
+        );
+
+        // Rendering takes longest, and the block is cached for later anyway
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        let time = Instant::now();
+        let bookmarked = self
+            .bookmarks
+            .is_bookmarked(&wiki.name, &article_data.title);
+        let article_html = render_article_page(&self.scope(wiki), &article_data, bookmarked);
+        println!("Rendered article in {:.2?}", time.elapsed());
+
+        // An article that is never shown wasn't visited
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        // Visits are saved in the background, see `BrowsingHistory::save_periodically`
+        self.history.record_visit(&wiki.name, &article_data.title);
+
+        Ok(Response::html(article_html))
+    }
+
+    fn source(&self, wiki: &Wiki, name: &str) -> anyhow::Result<Response> {
+        let article = Self::find_article(wiki, name)?;
+        let article_data = wiki.article_db.get_article(article)?;
+
+        Ok(Response::html(render_source_page(
+            &self.scope(wiki),
+            &article_data,
+        )))
+    }
+
+    fn history(
+        &self,
+        wiki: &Wiki,
+        name: &str,
+        superseded: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Response> {
+        let article = Self::find_article(wiki, name)?;
+        let revisions = wiki.article_db.get_history(article)?;
+        // Pages with thousands of revisions take a while to render
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        Ok(Response::html(render_history_page(
+            &self.scope(wiki),
+            &article.page_name,
+            &revisions,
+        )))
+    }
+
+    fn revision(
+        &self,
+        wiki: &Wiki,
+        id: u64,
+        title: Option<&str>,
+        superseded: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Response> {
+        println!("Loading revision {}", id);
+
+        let article = match title {
+            Some(title) => Self::find_article(wiki, title)?,
+            None => wiki.find_revision(id)?,
+        };
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        let article_data = wiki.article_db.get_revision(article, id)?;
+        if superseded() {
+            bail!(Superseded);
+        }
+
+        Ok(Response::html(render_revision_page(
+            &self.scope(wiki),
+            &article_data,
+        )))
+    }
+
+    fn diff(
+        &self,
+        wiki: &Wiki,
+        title: &str,
+        from: &RevisionSpec,
+        to: &RevisionSpec,
+        layout: DiffLayout,
+        superseded: &dyn Fn() -> bool,
+    ) -> anyhow::Result<Response> {
+        println!("Diffing {} between {:?} and {:?}", title, from, to);
+        let time = Instant::now();
+
+        let name_cleaned = wiki.site_info.normalize_title(title);
+        let load = |spec: &RevisionSpec| {
+            let (index, article_db) = match (spec.compare_dump, &wiki.compare_dump) {
+                (false, _) => (&wiki.index, &wiki.article_db),
+                (true, Some((index, article_db))) => (index, article_db),
+                (true, None) => bail!(UrlError::UnknownDump),
+            };
+            load_revision(index, article_db, &name_cleaned, spec.revision)
+        };
+
+        // Each side may need a block of its own decompressed, and diffing
+        // them takes longer still
+        let from_article = load(from)?;
+        if superseded() {
+            bail!(Superseded);
+        }
+        let to_article = load(to)?;
+        if superseded() {
+            bail!(Superseded);
+        }
+        let diff_html = render_diff_page(
+            &self.scope(wiki),
+            (&from_article, from.compare_dump),
+            (&to_article, to.compare_dump),
+            layout,
+        );
+        println!("Rendered diff in {:.2?}", time.elapsed());
+
+        Ok(Response::html(diff_html))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    const PAGES_OFFSET: u64 = Wiki::TEST_PAGES_OFFSET;
+
+    fn wiki(name: &str, titles: &[&str]) -> Wiki {
+        Wiki::from_test_dump(name, titles)
+    }
+
+    fn router(wikis: Vec<Wiki>) -> Router {
+        Router::new(
+            wikis,
+            ResourceManager::new(),
+            Arc::new(BrowsingHistory::load(None).unwrap()),
+            Bookmarks::load(None).unwrap(),
+        )
+    }
+
+    /// Requests a route of the JSON API, returning the status and the JSON.
+    fn get_json(router: &Router, path: &str) -> (u16, Value) {
+        let response = router.handle(
+            &format!("http://localhost{}", path),
+            Method::Get,
+            &[],
+            &|| false,
+        );
+        assert_eq!(response.mime_type, "application/json", "{}", path);
+        (
+            response.status,
+            serde_json::from_slice(&response.body).unwrap(),
+        )
+    }
+
+    /// Pages through the results of a search with their cursors, returning
+    /// the wiki and title of every result.
+    fn page_through(router: &Router, query: &str) -> Vec<(String, String)> {
+        let mut results = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let path = match &cursor {
+                Some(cursor) => format!("{}&cursor={}", query, cursor),
+                None => query.to_owned(),
+            };
+            let (status, json) = get_json(router, &path);
+            assert_eq!(status, 200, "{}", json);
+            assert_eq!(json["start"], results.len());
+            results.extend(json["results"].as_array().unwrap().iter().map(|result| {
+                (
+                    result["wiki"].as_str().unwrap().to_owned(),
+                    result["title"].as_str().unwrap().to_owned(),
+                )
+            }));
+
+            match json["next"].as_str() {
+                Some(next) => cursor = Some(next.to_owned()),
+                None => break,
+            }
+        }
+        results
+    }
+
+    fn cursor(skipped: usize, score: f64, wiki: &str, order: usize) -> Cursor {
+        Cursor {
+            skipped,
+            score,
+            wiki: wiki.to_owned(),
+            order,
+        }
+    }
+
+    #[test]
+    fn cursor_survives_round_trip() {
+        for original in [
+            cursor(20, 0.1 + 0.2, "enwiki", 7),
+            cursor(0, -0.0, "", 0),
+            cursor(40, f64::MAX, "en.wiki.v2", usize::MAX),
+        ] {
+            let parsed = Cursor::parse(&original.to_string()).expect("cursor parses");
+            assert_eq!(parsed.skipped, original.skipped);
+            assert_eq!(parsed.score.to_bits(), original.score.to_bits());
+            assert_eq!(parsed.wiki, original.wiki);
+            assert_eq!(parsed.order, original.order);
+        }
+        assert_eq!(
+            cursor(3, 1.5, "de", 2).to_string(),
+            "3.3ff8000000000000.2.de"
+        );
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for text in [
+            "",
+            "20",
+            "20.3ff8000000000000.7",
+            "x.3ff8000000000000.7.en",
+            "20.nothex.7.en",
+            "20.3ff8000000000000.-7.en",
+            "20.13ff8000000000000.7.en",
+        ] {
+            assert!(
+                matches!(Cursor::parse(text), Err(UrlError::InvalidCursor)),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn cursor_precedes_later_results() {
+        let cursor = cursor(20, 0.5, "en", 7);
+        assert!(cursor.precedes("en", 0.4, 0));
+        assert!(cursor.precedes("en", 0.5, 8));
+        assert!(cursor.precedes("fr", 0.5, 0));
+        assert!(!cursor.precedes("en", 0.5, 7));
+        assert!(!cursor.precedes("de", 0.5, 9));
+        assert!(!cursor.precedes("en", 0.6, 9));
+    }
+
+    #[test]
+    fn only_searches_read_cursors() {
+        let (_, parsed) = parse_url("http://localhost/search?q=rust&cursor=20.0.7.en").unwrap();
+        assert!(matches!(
+            parsed,
+            ParsedUrl::Search {
+                cursor: Some(Cursor { skipped: 20, .. }),
+                ..
+            }
+        ));
+
+        for url in [
+            "http://localhost/search?q=rust&cursor=junk",
+            "http://localhost/api/search?q=rust&cursor=junk",
+        ] {
+            let err = parse_url(url).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(UrlError::InvalidCursor)),
+                "{}",
+                url
+            );
+        }
+
+        let (_, parsed) = parse_url("http://localhost/article/Rust?cursor=junk").unwrap();
+        assert!(matches!(parsed, ParsedUrl::Article(title) if title == "Rust"));
+    }
+
+    #[test]
+    fn revisions_are_found_by_id() {
+        let (_, parsed) = parse_url("http://localhost/revision/123").unwrap();
+        assert!(matches!(
+            parsed,
+            ParsedUrl::Revision {
+                id: 123,
+                title: None
+            }
+        ));
+
+        let (_, parsed) = parse_url("http://localhost/revision/123?title=Rust").unwrap();
+        assert!(matches!(
+            parsed,
+            ParsedUrl::Revision { id: 123, title: Some(title) } if title == "Rust"
+        ));
+    }
+
+    #[test]
+    fn search_pages_through_every_result_once() {
+        // Titles of the same length tie on their scores
+        let titles = ["Rust 4", "Rust 1", "Rustacean", "Rust 3", "Rust 2"];
+        let router = router(vec![wiki("en", &titles), wiki("de", &titles)]);
+
+        for scope in ["", "&scope=all"] {
+            let query = format!("/w/en/api/search?q=rust{}", scope);
+            let (_, all) = get_json(&router, &format!("{}&limit=100", query));
+            let expected: Vec<(String, String)> = all["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|result| {
+                    (
+                        result["wiki"].as_str().unwrap().to_owned(),
+                        result["title"].as_str().unwrap().to_owned(),
+                    )
+                })
+                .collect();
+            assert_eq!(expected.len(), all["total"].as_u64().unwrap() as usize);
+
+            for limit in [1, 2, 3] {
+                let paged = page_through(&router, &format!("{}&limit={}", query, limit));
+                assert_eq!(paged, expected, "{} with limit {}", scope, limit);
+            }
+        }
+
+        // Results of both wikis with the same score are ordered by wiki
+        let (_, all) = get_json(&router, "/api/search?q=rust&scope=all&limit=100");
+        let ties: Vec<(&str, &str)> = all["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                (
+                    result["wiki"].as_str().unwrap(),
+                    result["title"].as_str().unwrap(),
+                )
+            })
+            .filter(|(_, title)| title.len() == 6 && title.starts_with("Rust "))
+            .collect();
+        assert_eq!(
+            ties,
+            [
+                ("de", "Rust 1"),
+                ("de", "Rust 2"),
+                ("de", "Rust 3"),
+                ("de", "Rust 4"),
+                ("en", "Rust 1"),
+                ("en", "Rust 2"),
+                ("en", "Rust 3"),
+                ("en", "Rust 4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn api_article_links_are_absolute() {
+        let router = router(vec![wiki("en", &[]), wiki("de", &[])]);
+        for (path, base) in [
+            ("/api/article/Rust_(programming_language)", "/w/en/"),
+            ("/w/en/api/article/Rust_(programming_language)", "/w/en/"),
+            (
+                "/w/de/api/article/rust%20(programming%20language)",
+                "/w/de/",
+            ),
+        ] {
+            let (status, json) = get_json(&router, path);
+            assert_eq!(status, 200, "{}", path);
+            assert_eq!(json["id"], 1);
+            assert_eq!(json["title"], "Rust (programming language)");
+            assert_eq!(json["redirect"], Value::Null);
+            assert_eq!(json["revision"]["id"], 100);
+            assert_eq!(json["revision"]["contributor"]["name"], "Editor");
+            let html = json["html"].as_str().unwrap();
+            for link in [
+                format!(r#"href="{}article/Cargo (software)""#, base),
+                format!(r#"src="{}media/File:Rust logo.svg""#, base),
+                format!(r#"href="{}article/Category:Programming languages""#, base),
+            ] {
+                assert!(html.contains(&link), "{} in {}", link, html);
+            }
+        }
+
+        let (status, json) = get_json(&router, "/api/article/Rust_language");
+        assert_eq!(status, 200);
+        assert_eq!(json["redirect"], "Rust (programming language)");
+    }
+
+    #[test]
+    fn api_search_returns_pages() {
+        let router = router(vec![wiki("en", &["Rust 1", "Rust 2"])]);
+        let (status, json) = get_json(&router, "/api/search?q=rust&limit=2");
+        assert_eq!(status, 200);
+        assert_eq!(json["query"], "rust");
+        assert_eq!(json["total"], 4);
+        assert_eq!(json["start"], 0);
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["wiki"], "en");
+        assert_eq!(results[0]["title"], "Rust 1");
+        assert_eq!(results[0]["offset"], PAGES_OFFSET);
+        assert!(results[0]["score"].is_f64());
+
+        let next = json["next"].as_str().unwrap();
+        let (status, json) = get_json(
+            &router,
+            &format!("/api/search?q=rust&limit=2&cursor={}", next),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(json["start"], 2);
+        assert_eq!(json["next"], Value::Null);
+        let results = json["results"].as_array().unwrap();
+        let titles: Vec<&str> = results
+            .iter()
+            .map(|result| result["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, ["Rust language", "Rust (programming language)"]);
+        // Results with articles are summarized
+        assert_eq!(results[0]["redirect"], "Rust (programming language)");
+        assert!(results[1]["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("programming language"));
+    }
+
+    #[test]
+    fn api_suggest_returns_opensearch_suggestions() {
+        let router = router(vec![wiki("en", &[])]);
+        let (status, json) = get_json(&router, "/api/suggest?q=car");
+        assert_eq!(status, 200);
+        assert_eq!(
+            json,
+            serde_json::json!([
+                "car",
+                ["Cargo (software)"],
+                [""],
+                ["/w/en/article/Cargo%20%28software%29"]
+            ])
+        );
+
+        let (_, json) = get_json(&router, "/api/suggest?q=rust&limit=1");
+        assert_eq!(json[1].as_array().unwrap().len(), 1);
+        let (_, json) = get_json(&router, "/api/suggest?q=");
+        assert_eq!(json, serde_json::json!(["", [], [], []]));
+    }
+
+    #[test]
+    fn api_errors_are_json() {
+        let router = router(vec![wiki("en", &[])]);
+        for (path, status) in [
+            ("/api/article/Nothing", 404),
+            ("/api/nothing", 404),
+            ("/w/fr/api/siteinfo", 404),
+            ("/api/search?q=rust&cursor=junk", 400),
+            ("/api/search?q=rust&limit=many", 400),
+            ("/api/search", 400),
+            ("/api/history/clear", 405),
+        ] {
+            let (actual, json) = get_json(&router, path);
+            assert_eq!(actual, status, "{}", path);
+            let json = json.as_object().unwrap();
+            assert_eq!(json.len(), 2, "{}", path);
+            assert_eq!(json["status"], status, "{}", path);
+            assert!(!json["error"].as_str().unwrap().is_empty(), "{}", path);
+        }
+    }
+}
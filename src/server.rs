@@ -0,0 +1,480 @@
+use std::{
+    io::Read,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, bail};
+use rand::{distributions::Alphanumeric, Rng};
+use tiny_http::{Header, Method as HttpMethod, Request, Server};
+use url::Url;
+
+use crate::router::{Method, Response, Router};
+
+/// A server handling requests on its worker threads in the background.
+pub struct RunningServer {
+    /// Address the server listens on, with the actual port if it was
+    /// started on port 0
+    pub addr: SocketAddr,
+    session: Option<Arc<Session>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Largest request body that is read, like that of a bookmarks import.
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Query parameter of the URL the window opens first, carrying the token of
+/// its session, and the cookie it keeps the token in afterwards.
+const TOKEN_PARAM: &str = "token";
+const TOKEN_COOKIE: &str = "wiki-rs-token";
+
+/// Length of the random session token.
+const TOKEN_LENGTH: usize = 32;
+
+/// Guards a server that only the app's window uses against requests from
+/// other programs and from web pages, like ones whose domain was rebound to
+/// the loopback address to read the API.
+struct Session {
+    /// Values of the Host header the window sends, with the server's port
+    hosts: [String; 2],
+    token: String,
+}
+
+/// Whether a request belongs to the session.
+enum Access {
+    Granted,
+    /// The request carries the token in its URL, so the window is let in by
+    /// setting the cookie and redirecting it to the URL without the token
+    Start {
+        location: String,
+    },
+    Denied,
+}
+
+/// Counts the pages navigated to, so that the request for a page can tell
+/// whether a newer one has been requested since.
+#[derive(Default)]
+struct Navigations {
+    latest: AtomicU64,
+}
+
+/// Serves the app over HTTP instead of in a window, handling requests on
+/// one worker thread per CPU.
+pub fn serve(router: Router, addr: &str) -> anyhow::Result<()> {
+    let server = start(router, addr, false)?;
+    println!(
+        "Serving on http://{} with {} workers",
+        server.addr,
+        server.workers.len()
+    );
+    server.join()
+}
+
+/// Starts handling requests on one worker thread per CPU, keeping the
+/// calling thread free, e.g. for the event loop of the window.
+///
+/// With `window`, the server only has the app's window as its user, so
+/// requesting a page gives up on rendering the pages requested before it,
+/// whose responses would be thrown away anyway. Only requests from the
+/// window are answered then, which opens `RunningServer::session_url` first.
+pub fn start(router: Router, addr: &str, window: bool) -> anyhow::Result<RunningServer> {
+    let server = Arc::new(Server::http(addr).map_err(|err| anyhow!(err))?);
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| anyhow!("server is not listening on an IP address"))?;
+    let router = Arc::new(router);
+    let (navigations, session) = match window {
+        true => (
+            Some(Arc::new(Navigations::default())),
+            Some(Arc::new(Session::new(addr))),
+        ),
+        false => (None, None),
+    };
+
+    let workers = thread::available_parallelism()
+        .map(|workers| workers.get())
+        .unwrap_or(4);
+
+    let workers = (0..workers)
+        .map(|_| {
+            let server = server.clone();
+            let router = router.clone();
+            let navigations = navigations.clone();
+            let session = session.clone();
+            thread::spawn(move || loop {
+                match server.recv() {
+                    Ok(request) => {
+                        handle_request(&router, navigations.as_deref(), session.as_deref(), request)
+                    }
+                    Err(err) => {
+                        println!("Failed to receive request: {}", err);
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    Ok(RunningServer {
+        addr,
+        session,
+        workers,
+    })
+}
+
+impl RunningServer {
+    /// URL of a page for the window to open first, which carries the token
+    /// of its session if it has one. `path` may end with a fragment but has
+    /// no query.
+    pub fn session_url(&self, path: &str) -> String {
+        let (path, fragment) = match path.find('#') {
+            Some(idx) => path.split_at(idx),
+            None => (path, ""),
+        };
+        match &self.session {
+            Some(session) => format!(
+                "http://{}{}?{}={}{}",
+                self.addr, path, TOKEN_PARAM, session.token, fragment
+            ),
+            None => format!("http://{}{}{}", self.addr, path, fragment),
+        }
+    }
+
+    /// Blocks until all workers have stopped, even if some of them
+    /// panicked.
+    pub fn join(self) -> anyhow::Result<()> {
+        let panicked = self
+            .workers
+            .into_iter()
+            .map(|worker| worker.join())
+            .filter(|result| result.is_err())
+            .count();
+        if panicked > 0 {
+            bail!("{} server workers panicked", panicked);
+        }
+
+        Ok(())
+    }
+}
+
+impl Session {
+    fn new(addr: SocketAddr) -> Self {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        Self {
+            hosts: [addr.to_string(), format!("localhost:{}", addr.port())],
+            token,
+        }
+    }
+
+    fn check(&self, request: &Request) -> Access {
+        let header = |name| header_value(request, name);
+
+        // Pages of other domains resolving to the loopback address send their
+        // own domain
+        if !header("Host").is_some_and(|host| self.hosts.iter().any(|known| known == host)) {
+            return Access::Denied;
+        }
+
+        let has_cookie = header("Cookie").is_some_and(|cookies| {
+            cookies
+                .split(';')
+                .filter_map(|cookie| cookie.trim().split_once('='))
+                .any(|(name, value)| name == TOKEN_COOKIE && value == self.token)
+        });
+        if has_cookie {
+            return Access::Granted;
+        }
+
+        let mut url = match Url::parse(&format!("http://wiki.rs{}", request.url())) {
+            Ok(url) => url,
+            Err(_) => return Access::Denied,
+        };
+        let (tokens, params): (Vec<_>, Vec<_>) = url
+            .query_pairs()
+            .into_owned()
+            .partition(|(name, _)| name == TOKEN_PARAM);
+        if !tokens.iter().any(|(_, token)| *token == self.token) {
+            return Access::Denied;
+        }
+
+        url.set_query(None);
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        let location = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        Access::Start { location }
+    }
+}
+
+impl Navigations {
+    /// Registers a navigation, returning its number.
+    fn start(&self) -> u64 {
+        self.latest.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn is_superseded(&self, navigation: u64) -> bool {
+        self.latest.load(Ordering::SeqCst) != navigation
+    }
+}
+
+fn handle_request(
+    router: &Router,
+    navigations: Option<&Navigations>,
+    session: Option<&Session>,
+    mut request: Request,
+) {
+    println!("Handling http request for {}", request.url());
+
+    match session.map_or(Access::Granted, |session| session.check(&request)) {
+        Access::Granted => (),
+        Access::Start { location } => {
+            let token = session
+                .map(|session| session.token.as_str())
+                .unwrap_or_default();
+            let cookie = format!(
+                "{}={}; Path=/; HttpOnly; SameSite=Strict",
+                TOKEN_COOKIE, token
+            );
+            let http_response = tiny_http::Response::empty(303)
+                .with_header(header("Set-Cookie", &cookie))
+                .with_header(header("Location", &location));
+            return respond(request, http_response);
+        }
+        Access::Denied => {
+            println!("Denied request from outside the window");
+            let http_response = tiny_http::Response::from_string("Forbidden")
+                .with_status_code(403)
+                .with_header(header("Content-Type", "text/plain"));
+            return respond(request, http_response);
+        }
+    }
+
+    // The router expects absolute URLs
+    let url = format!("http://wiki.rs{}", request.url());
+
+    // Only pages supersede each other, the resources they load don't
+    let navigation = match navigations {
+        Some(navigations) if Router::is_page_url(&url) => Some((navigations, navigations.start())),
+        _ => None,
+    };
+    let superseded = || {
+        navigation.is_some_and(|(navigations, navigation)| navigations.is_superseded(navigation))
+    };
+
+    let method = match request.method() {
+        HttpMethod::Post => Method::Post,
+        _ => Method::Get,
+    };
+    if method == Method::Post && !is_same_origin(&request) {
+        println!("Denied request from another origin");
+        let http_response = tiny_http::Response::from_string("Forbidden")
+            .with_status_code(403)
+            .with_header(header("Content-Type", "text/plain"));
+        return respond(request, http_response);
+    }
+
+    let mut body = Vec::new();
+    if let Err(err) = request
+        .as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_end(&mut body)
+    {
+        println!("Failed to read request body: {}", err);
+    }
+
+    // A bug in handling one request shouldn't take its worker down with it
+    let response = panic::catch_unwind(AssertUnwindSafe(|| {
+        router.handle(&url, method, &body, &superseded)
+    }))
+    .unwrap_or_else(|_| Response {
+        status: 500,
+        mime_type: "text/plain".to_string(),
+        body: b"Internal server error".to_vec(),
+    });
+    // Superseded pages are answered too, usually with a 503 from the router
+    // having given up on them, so that their connections aren't left hanging
+    let http_response = tiny_http::Response::from_data(response.body)
+        .with_status_code(response.status)
+        .with_header(header("Content-Type", &response.mime_type));
+    respond(request, http_response);
+}
+
+/// Whether a request comes from a page of the server itself. Browsers send
+/// the origin of every `POST`, so requests without one are refused as well,
+/// as nothing tells where they come from. In server mode, anyone on the
+/// network could otherwise clear the history or change the bookmarks.
+fn is_same_origin(request: &Request) -> bool {
+    let header = |name| header_value(request, name);
+    match (header("Origin"), header("Host")) {
+        (Some(origin), Some(host)) => origin.strip_prefix("http://") == Some(host),
+        _ => false,
+    }
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Failed to create header")
+}
+
+fn respond<R: Read>(request: Request, response: tiny_http::Response<R>) {
+    if let Err(err) = request.respond(response) {
+        println!("Failed to send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_http::TestRequest;
+
+    use super::*;
+
+    fn session() -> Session {
+        Session::new("127.0.0.1:8080".parse().unwrap())
+    }
+
+    fn test_request(path: &str, headers: &[(&str, &str)]) -> Request {
+        headers
+            .iter()
+            .fold(
+                TestRequest::new().with_path(path),
+                |request, (name, value)| request.with_header(header(name, value)),
+            )
+            .into()
+    }
+
+    #[test]
+    fn session_grants_requests_with_its_cookie() {
+        let session = session();
+        let cookie = format!("theme=dark; {}={}", TOKEN_COOKIE, session.token);
+        for host in ["127.0.0.1:8080", "localhost:8080"] {
+            let request = test_request("/article/Rust", &[("Host", host), ("Cookie", &cookie)]);
+            assert!(
+                matches!(session.check(&request), Access::Granted),
+                "{}",
+                host
+            );
+        }
+    }
+
+    #[test]
+    fn session_denies_other_hosts() {
+        let session = session();
+        let cookie = format!("{}={}", TOKEN_COOKIE, session.token);
+        for host in [None, Some("attacker.example:8080"), Some("127.0.0.1:9090")] {
+            let mut headers = vec![("Cookie", cookie.as_str())];
+            headers.extend(host.map(|host| ("Host", host)));
+            let request = test_request("/api/siteinfo", &headers);
+            assert!(
+                matches!(session.check(&request), Access::Denied),
+                "{:?}",
+                host
+            );
+        }
+    }
+
+    #[test]
+    fn session_denies_requests_without_token() {
+        let session = session();
+        for headers in [
+            vec![("Host", "127.0.0.1:8080")],
+            vec![
+                ("Host", "127.0.0.1:8080"),
+                ("Cookie", "wiki-rs-token=wrong"),
+            ],
+            vec![
+                ("Host", "127.0.0.1:8080"),
+                ("Cookie", "other-wiki-rs-token="),
+            ],
+        ] {
+            let request = test_request("/api/siteinfo?token=wrong", &headers);
+            assert!(
+                matches!(session.check(&request), Access::Denied),
+                "{:?}",
+                headers
+            );
+        }
+    }
+
+    #[test]
+    fn session_starts_with_token_in_url() {
+        let session = session();
+        let path = format!("/w/en/search?q=a+b&token={}&scope=all", session.token);
+        let request = test_request(&path, &[("Host", "127.0.0.1:8080")]);
+        match session.check(&request) {
+            Access::Start { location } => assert_eq!(location, "/w/en/search?q=a+b&scope=all"),
+            _ => panic!("the session doesn't start"),
+        }
+
+        let path = format!("/?token={}", session.token);
+        let request = test_request(&path, &[("Host", "localhost:8080")]);
+        assert!(matches!(session.check(&request), Access::Start { location } if location == "/"));
+    }
+
+    #[test]
+    fn session_url_carries_token() {
+        let session = Arc::new(session());
+        let server = RunningServer {
+            addr: "127.0.0.1:8080".parse().unwrap(),
+            session: Some(session.clone()),
+            workers: Vec::new(),
+        };
+        assert_eq!(
+            server.session_url("/w/en/article/Rust#scroll=10"),
+            format!(
+                "http://127.0.0.1:8080/w/en/article/Rust?token={}#scroll=10",
+                session.token
+            )
+        );
+    }
+
+    #[test]
+    fn same_origin_needs_matching_origin() {
+        let host = ("Host", "127.0.0.1:8080");
+        assert!(is_same_origin(&test_request(
+            "/",
+            &[host, ("Origin", "http://127.0.0.1:8080")]
+        )));
+        for origin in [
+            "http://attacker.example",
+            "https://127.0.0.1:8080",
+            "http://127.0.0.1:8081",
+            "null",
+        ] {
+            let request = test_request("/", &[host, ("Origin", origin)]);
+            assert!(!is_same_origin(&request), "{}", origin);
+        }
+        assert!(!is_same_origin(&test_request(
+            "/",
+            &[("Origin", "http://127.0.0.1:8080")]
+        )));
+    }
+
+    #[test]
+    fn same_origin_needs_an_origin() {
+        assert!(!is_same_origin(&test_request(
+            "/api/history/clear",
+            &[("Host", "192.168.1.20:8080")]
+        )));
+        assert!(!is_same_origin(&test_request("/api/history/clear", &[])));
+    }
+}
@@ -0,0 +1,116 @@
+//! The JSON files the app keeps in its data directory, like the browsing
+//! history and the bookmarks.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Loads the JSON file at `path`, described as `what` in messages, which
+/// holds the default value if it doesn't exist yet. A file that can't be
+/// parsed is moved aside to `<name>.json.bad`, where it can still be
+/// repaired, rather than keeping the app from starting.
+pub fn load_json_or_move_aside<T: DeserializeOwned + Default>(
+    path: &Path,
+    what: &str,
+) -> anyhow::Result<T> {
+    if !path.is_file() {
+        return Ok(T::default());
+    }
+
+    let json =
+        fs::read(path).with_context(|| format!("Failed to read {} {}", what, path.display()))?;
+    match serde_json::from_slice(&json) {
+        Ok(value) => Ok(value),
+        Err(err) => {
+            let bad_path = path.with_extension("json.bad");
+            println!(
+                "Failed to parse {} {}, moving the file to {}: {}",
+                what,
+                path.display(),
+                bad_path.display(),
+                err
+            );
+            fs::rename(path, &bad_path)
+                .with_context(|| format!("Failed to move {} {} aside", what, path.display()))?;
+            Ok(T::default())
+        }
+    }
+}
+
+/// Saves a value as JSON to `path`, described as `what` in messages. It is
+/// written next to the file and then moved over it, so that the app quitting
+/// halfway through doesn't leave half a file behind.
+pub fn save_json_atomically<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+    what: &str,
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_vec_pretty(value)?)
+        .and_then(|()| fs::rename(&temp_path, path))
+        .with_context(|| format!("Failed to save {} {}", what, path.display()))
+}
+
+/// A directory of its own for each test, which is removed when dropped.
+#[cfg(test)]
+pub struct TempDir(pub std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("wiki-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_is_default() {
+        let dir = TempDir::new("storage-missing");
+        let value: Vec<u32> = load_json_or_move_aside(&dir.0.join("a.json"), "test").unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn saved_file_loads_again() {
+        let dir = TempDir::new("storage-saved");
+        let path = dir.0.join("nested").join("a.json");
+        save_json_atomically(&path, &[1, 2, 3], "test").unwrap();
+
+        let value: Vec<u32> = load_json_or_move_aside(&path, "test").unwrap();
+        assert_eq!(value, [1, 2, 3]);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn unparsable_file_is_moved_aside() {
+        let dir = TempDir::new("storage-unparsable");
+        let path = dir.0.join("a.json");
+        fs::write(&path, "[1, 2,").unwrap();
+
+        let value: Vec<u32> = load_json_or_move_aside(&path, "test").unwrap();
+        assert!(value.is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(dir.0.join("a.json.bad")).unwrap(),
+            "[1, 2,"
+        );
+    }
+}
@@ -1,112 +1,614 @@
-use std::{fs::File, io};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+};
 
 use anyhow::{bail, Ok};
 use bzip2_rs::DecoderReader;
-use chrono::{DateTime, Utc};
+use memchr::memmem;
 use memmap::{Mmap, MmapOptions};
-use minidom::Element;
+use quick_xml::{escape::unescape, events::Event, name::QName, reader::Reader};
 use thiserror::Error;
 
-use super::index::IndexEntry;
+use super::{
+    cache::{BlockCache, CacheStats, DEFAULT_CACHE_BUDGET},
+    index::IndexEntry,
+    revision::Revision,
+    siteinfo::{SiteInfo, SiteInfoError},
+};
+
+/// Every bzip2 stream starts with this signature, followed by a digit for
+/// the block size and the magic number of its first block.
+const STREAM_SIGNATURE: &[u8] = b"BZh";
+const BLOCK_MAGIC: &[u8] = &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+
+/// Most bytes decoded from the start of a dump in search of the end of its
+/// `<siteinfo>`, which takes a few kilobytes even with many namespaces.
+const MAX_SITE_INFO_BYTES: usize = 1 << 20;
+const SITE_INFO_END: &[u8] = b"</siteinfo>";
 
 pub struct ArticleDatabase {
     data: Mmap,
+    cache: BlockCache,
 }
 
 #[derive(Debug, Clone)]
 pub struct Article {
     pub id: u64,
     pub title: String,
-    pub last_changed_at: DateTime<Utc>,
-    pub last_changed_by: String,
+    pub namespace: i32,
+    /// Title of the page this one redirects to.
+    pub redirect: Option<String>,
+    pub revision: Revision,
     pub body: String,
 }
 
+/// A page listed by [`ArticleDatabase::list_pages`].
+pub struct PageListing {
+    pub id: u64,
+    pub title: String,
+    /// Ids of its revisions, oldest first
+    pub revisions: Vec<u64>,
+}
+
+/// A page found in a chunk, with the metadata of its revisions and their
+/// positions in the chunk.
+struct Page<'a> {
+    id: u64,
+    title: Cow<'a, str>,
+    namespace: i32,
+    redirect: Option<String>,
+    revisions: Vec<(usize, Revision)>,
+}
+
 #[derive(Error, Debug)]
 pub enum ArticleError {
     #[error("requested article not found")]
     ArticleNotFound,
 
+    #[error("requested revision not found")]
+    RevisionNotFound,
+
     #[error("missing property on page")]
     MissingProperty(String),
 }
 
 impl ArticleDatabase {
-    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         Self::load(File::open(path)?)
     }
 
     pub fn load(file: File) -> anyhow::Result<Self> {
         Ok(Self {
             data: unsafe { MmapOptions::new().map(&file)? },
+            cache: BlockCache::new(DEFAULT_CACHE_BUDGET),
         })
     }
 
+    /// Sets how many bytes of decompressed blocks may be kept in memory.
+    pub fn with_cache_budget(self, budget: usize) -> Self {
+        self.cache.set_budget(budget);
+        self
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Size of the compressed file in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Finds the offsets of all bzip2 streams in the file by scanning for
+    /// their headers. This reads the whole file, so it is only meant for
+    /// rebuilding the index.
+    pub fn stream_offsets(&self) -> Vec<u64> {
+        memmem::find_iter(&self.data, STREAM_SIGNATURE)
+            .filter(|&offset| Self::is_stream_header(&self.data[offset..]))
+            .map(|offset| offset as u64)
+            .collect()
+    }
+
+    fn is_stream_header(data: &[u8]) -> bool {
+        data.len() >= 10 && (b'1'..=b'9').contains(&data[3]) && &data[4..10] == BLOCK_MAGIC
+    }
+
+    /// Lists the pages in the stream between the given offsets, with the
+    /// ids of their revisions. The stream bypasses the block cache, since
+    /// every stream is only read once when building an index.
+    pub fn list_pages(
+        &self,
+        offset: u64,
+        end_offset: Option<u64>,
+    ) -> anyhow::Result<Vec<PageListing>> {
+        let chunk = self.decode_stream(offset, end_offset)?;
+        let mut reader = Reader::from_str(&chunk);
+        // The last stream closes the <mediawiki> element opened by the first
+        reader.check_end_names(false);
+
+        let mut pages = Vec::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) if start.local_name().as_ref() == b"page" => {
+                    let page = match Self::parse_page(&mut reader, None)? {
+                        Some(page) => page,
+                        None => continue,
+                    };
+                    pages.push(PageListing {
+                        id: page.id,
+                        title: unescape(&page.title)?.into_owned(),
+                        revisions: page
+                            .revisions
+                            .into_iter()
+                            .map(|(_, revision)| revision.id)
+                            .collect(),
+                    });
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(pages)
+    }
+
+    /// Loads the latest revisions of all pages in the stream between the
+    /// given offsets. Like [`ArticleDatabase::list_pages`], this bypasses
+    /// the block cache, as it is meant for scanning the whole dump.
+    pub fn list_articles(
+        &self,
+        offset: u64,
+        end_offset: Option<u64>,
+    ) -> anyhow::Result<Vec<Article>> {
+        let chunk = self.decode_stream(offset, end_offset)?;
+        let mut reader = Reader::from_str(&chunk);
+        reader.check_end_names(false);
+
+        let mut articles = Vec::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) if start.local_name().as_ref() == b"page" => {
+                    let page = match Self::parse_page(&mut reader, None)? {
+                        Some(page) => page,
+                        None => continue,
+                    };
+                    if let Some(&(position, _)) = page.revisions.last() {
+                        articles.push(Self::read_article(&chunk, page, position)?);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(articles)
+    }
+
+    /// Loads the latest revision of an article.
     pub fn get_article(&self, idx: &IndexEntry) -> anyhow::Result<Article> {
         let chunk = self.get_article_chunk(idx)?;
-        let root: Element = chunk.parse()?;
-        let article = root.children().find(|ch| {
-            let option = ch.get_child("id", "");
-            if let Some(id) = option {
-                id.text() == idx.page_id.to_string()
-            } else {
-                false
+        let page = Self::find_page(&chunk, idx.page_id)?;
+
+        // Dumps list revisions oldest first
+        let position = match page.revisions.last() {
+            Some((position, _)) => *position,
+            None => bail!(ArticleError::MissingProperty("revision".to_owned())),
+        };
+        Self::read_article(&chunk, page, position)
+    }
+
+    /// Loads an article as it was at the given revision.
+    pub fn get_revision(&self, idx: &IndexEntry, revision_id: u64) -> anyhow::Result<Article> {
+        let chunk = self.get_article_chunk(idx)?;
+        let page = Self::find_page(&chunk, idx.page_id)?;
+
+        let position = page
+            .revisions
+            .iter()
+            .find(|(_, revision)| revision.id == revision_id)
+            .map(|(position, _)| *position);
+        match position {
+            Some(position) => Self::read_article(&chunk, page, position),
+            None => bail!(ArticleError::RevisionNotFound),
+        }
+    }
+
+    /// Lists the metadata of all revisions of an article, oldest first.
+    pub fn get_history(&self, idx: &IndexEntry) -> anyhow::Result<Vec<Revision>> {
+        let chunk = self.get_article_chunk(idx)?;
+        let page = Self::find_page(&chunk, idx.page_id)?;
+
+        Ok(page
+            .revisions
+            .into_iter()
+            .map(|(_, revision)| revision)
+            .collect())
+    }
+
+    /// Reads the `<siteinfo>` header, which multistream dumps keep in the
+    /// first bzip2 stream of the file ahead of any pages. Fails if it doesn't
+    /// end within the first `MAX_SITE_INFO_BYTES` of the dump.
+    pub fn get_site_info(&self) -> anyhow::Result<SiteInfo> {
+        let mut reader = DecoderReader::new(&self.data[..]);
+        let mut decoded = Vec::<u8>::new();
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
             }
-        });
 
-        match article {
-            Some(article) => Self::parse_article(article),
-            None => bail!(ArticleError::ArticleNotFound),
+            // Only the new bytes can complete the end tag, along with the
+            // end of what was read before
+            let start = decoded.len().saturating_sub(SITE_INFO_END.len() - 1);
+            decoded.extend_from_slice(&buffer[..read]);
+            if memmem::find(&decoded[start..], SITE_INFO_END).is_some() {
+                break;
+            }
+            if decoded.len() >= MAX_SITE_INFO_BYTES {
+                bail!(SiteInfoError::MissingSiteInfo);
+            }
         }
+
+        SiteInfo::parse(&String::from_utf8_lossy(&decoded))
     }
 
-    fn get_article_chunk(&self, idx: &IndexEntry) -> anyhow::Result<String> {
-        let offset = idx.offset as usize;
-        let bzip_data = &self.data[offset..];
+    fn get_article_chunk(&self, idx: &IndexEntry) -> anyhow::Result<Arc<String>> {
+        if let Some(chunk) = self.cache.get(idx.offset) {
+            return Ok(chunk);
+        }
+
+        let raw_xml_pages = Arc::new(self.decode_stream(idx.offset, idx.end_offset)?);
+        self.cache.insert(idx.offset, raw_xml_pages.clone());
+
+        Ok(raw_xml_pages)
+    }
+
+    fn decode_stream(&self, offset: u64, end_offset: Option<u64>) -> anyhow::Result<String> {
+        let offset = offset as usize;
+        let end_offset = match end_offset {
+            Some(end_offset) => end_offset as usize,
+            None => self.data.len(),
+        };
+        let bzip_data = &self.data[offset..end_offset];
 
         let mut decoded = Vec::<u8>::new();
         let mut reader = DecoderReader::new(bzip_data);
         io::copy(&mut reader, &mut decoded)?;
 
-        let raw_xml_pages = String::from_utf8(decoded)?;
-        let reconstructed_xml = format!("<pages xmlns=\"\">{}</pages>", &raw_xml_pages);
+        Ok(String::from_utf8(decoded)?)
+    }
+
+    fn find_page(chunk: &str, page_id: u64) -> anyhow::Result<Page<'_>> {
+        let mut reader = Reader::from_str(chunk);
 
-        Ok(reconstructed_xml)
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) if start.local_name().as_ref() == b"page" => {
+                    if let Some(page) = Self::parse_page(&mut reader, Some(page_id))? {
+                        return Ok(page);
+                    }
+                }
+                Event::Eof => bail!(ArticleError::ArticleNotFound),
+                _ => {}
+            }
+        }
     }
 
-    fn parse_article(article: &Element) -> anyhow::Result<Article> {
-        let id: u64 = article.try_get_child("id")?.text().parse()?;
-        let title = article.try_get_child("title")?.text();
+    /// Parses the `<page>` the reader is positioned in, if it has the given
+    /// id or no id is given. Pages with other ids are skipped as soon as
+    /// their `<id>` is seen, without allocating anything for them. Only the
+    /// metadata of revisions is read, together with where they start so
+    /// their text can be loaded later.
+    fn parse_page<'a>(
+        reader: &mut Reader<&'a [u8]>,
+        page_id: Option<u64>,
+    ) -> anyhow::Result<Option<Page<'a>>> {
+        let mut title = None;
+        let mut namespace = None;
+        let mut id = None;
+        let mut redirect = None;
+        let mut revisions = Vec::new();
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => match start.local_name().as_ref() {
+                    b"title" => title = Some(reader.read_text(start.name())?),
+                    b"ns" => namespace = Some(reader.read_text(start.name())?.parse()?),
+                    b"id" => {
+                        let page: u64 = reader.read_text(start.name())?.parse()?;
+                        if page_id.is_some_and(|page_id| page != page_id) {
+                            reader.read_to_end(QName(b"page"))?;
+                            return Ok(None);
+                        }
+                        id = Some(page);
+                    }
+                    b"revision" => {
+                        let position = reader.buffer_position();
+                        let (revision, _) = Revision::parse(reader, false)?;
+                        revisions.push((position, revision));
+                    }
+                    _ => {
+                        reader.read_to_end(start.name())?;
+                    }
+                },
+                Event::Empty(empty) if empty.local_name().as_ref() == b"redirect" => {
+                    if let Some(target) = empty.try_get_attribute("title")? {
+                        redirect = Some(target.unescape_value()?.into_owned());
+                    }
+                }
+                Event::End(end) if end.local_name().as_ref() == b"page" => break,
+                Event::Eof => bail!(ArticleError::ArticleNotFound),
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| ArticleError::MissingProperty(name.to_owned());
+        Ok(Some(Page {
+            id: id.ok_or_else(|| missing("id"))?,
+            title: title.ok_or_else(|| missing("title"))?,
+            namespace: namespace.unwrap_or(0),
+            redirect,
+            revisions,
+        }))
+    }
 
-        let revision = article.try_get_child("revision")?;
-        let body = revision.try_get_child("text")?.text();
+    /// Reads the revision starting at `position` in the chunk, including its
+    /// text, and combines it with the page into an article.
+    fn read_article(chunk: &str, page: Page, position: usize) -> anyhow::Result<Article> {
+        let mut reader = Reader::from_str(&chunk[position..]);
+        // The reader starts inside of the revision, so it never sees its start tag
+        reader.check_end_names(false);
 
-        let last_changed_at: DateTime<Utc> = revision.try_get_child("timestamp")?.text().parse()?;
-        let last_changed_by = revision
-            .try_get_child("contributor")?
-            .try_get_child("username")?
-            .text();
+        let (revision, body) = Revision::parse(&mut reader, true)?;
 
         Ok(Article {
-            id,
-            title,
-            last_changed_by,
-            last_changed_at,
-            body,
+            id: page.id,
+            title: unescape(&page.title)?.into_owned(),
+            namespace: page.namespace,
+            redirect: page.redirect,
+            revision,
+            body: body.unwrap_or_default(),
         })
     }
 }
 
-trait TryGetChild {
-    fn try_get_child(&self, name: &str) -> anyhow::Result<&Element>;
-}
+#[cfg(test)]
+mod tests {
+    use memmap::MmapMut;
+
+    use super::*;
+
+    /// A chunk of two pages, the second of which has three revisions.
+    const CHUNK: &str = r#"
+  <page>
+    <title>AccessibleComputing</title>
+    <ns>0</ns>
+    <id>10</id>
+    <redirect title="Computer accessibility" />
+    <revision>
+      <id>1002250816</id>
+      <timestamp>2021-01-23T15:15:01Z</timestamp>
+      <contributor><username>Elli</username><id>20842734</id></contributor>
+      <text bytes="111" xml:space="preserve">#REDIRECT [[Computer accessibility]]</text>
+    </revision>
+  </page>
+  <page>
+    <title>Anarchism &amp; more</title>
+    <ns>0</ns>
+    <id>12</id>
+    <revision>
+      <id>100</id>
+      <timestamp>2001-01-01T00:00:00Z</timestamp>
+      <contributor><ip>127.0.0.1</ip></contributor>
+      <text xml:space="preserve">First</text>
+    </revision>
+    <revision>
+      <id>200</id>
+      <parentid>100</parentid>
+      <timestamp>2002-02-02T00:00:00Z</timestamp>
+      <contributor><username>Someone</username><id>5</id></contributor>
+      <minor />
+      <comment>typo</comment>
+      <text xml:space="preserve">Second &lt;b&gt;</text>
+    </revision>
+    <revision>
+      <id>300</id>
+      <parentid>200</parentid>
+      <timestamp>2003-03-03T00:00:00Z</timestamp>
+      <contributor deleted="deleted" />
+      <text xml:space="preserve">Third</text>
+    </revision>
+  </page>
+"#;
+
+    /// A database whose only block, at offset 0, is already decompressed
+    /// into the cache, so that reading from it never touches the file.
+    fn database(chunk: &str) -> ArticleDatabase {
+        let article_db = ArticleDatabase {
+            data: MmapMut::map_anon(1).unwrap().make_read_only().unwrap(),
+            cache: BlockCache::new(DEFAULT_CACHE_BUDGET),
+        };
+        article_db.cache.insert(0, Arc::new(chunk.to_owned()));
+        article_db
+    }
+
+    fn entry(page_id: u64) -> IndexEntry {
+        IndexEntry {
+            offset: 0,
+            end_offset: None,
+            page_id,
+            page_name: String::new(),
+        }
+    }
+
+    #[test]
+    fn history_lists_revisions_oldest_first() {
+        let history = database(CHUNK).get_history(&entry(12)).unwrap();
+
+        let ids: Vec<u64> = history.iter().map(|revision| revision.id).collect();
+        assert_eq!(ids, [100, 200, 300]);
+        let parents: Vec<Option<u64>> = history.iter().map(|revision| revision.parent_id).collect();
+        assert_eq!(parents, [None, Some(100), Some(200)]);
+        assert!(history[1].minor);
+        assert_eq!(history[1].comment.as_deref(), Some("typo"));
+    }
 
-impl TryGetChild for &Element {
-    fn try_get_child(&self, name: &str) -> anyhow::Result<&Element> {
-        let child = self.get_child(name, "");
-        match child {
-            Some(child) => Ok(child),
-            None => bail!(ArticleError::MissingProperty(name.to_owned())),
+    #[test]
+    fn revisions_are_read_with_their_text() {
+        let article_db = database(CHUNK);
+        for (revision_id, body) in [(100, "First"), (200, "Second <b>"), (300, "Third")] {
+            let article = article_db.get_revision(&entry(12), revision_id).unwrap();
+            assert_eq!(article.id, 12);
+            assert_eq!(article.title, "Anarchism & more");
+            assert_eq!(article.revision.id, revision_id);
+            assert_eq!(article.body, body);
         }
+
+        let latest = article_db.get_article(&entry(12)).unwrap();
+        assert_eq!(latest.revision.id, 300);
+        assert_eq!(latest.body, "Third");
+    }
+
+    #[test]
+    fn revisions_of_other_pages_are_not_found() {
+        let article_db = database(CHUNK);
+        let err = article_db.get_revision(&entry(10), 200).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::RevisionNotFound)
+        ));
+        let err = article_db.get_history(&entry(11)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::ArticleNotFound)
+        ));
+    }
+
+    #[test]
+    fn pages_before_the_target_are_skipped() {
+        let page = ArticleDatabase::find_page(CHUNK, 12).unwrap();
+        assert_eq!(page.id, 12);
+        assert_eq!(page.title, "Anarchism &amp; more");
+        assert_eq!(page.namespace, 0);
+        assert_eq!(page.redirect, None);
+        assert_eq!(page.revisions.len(), 3);
+
+        // The text of a revision starts at its position
+        let (position, _) = page.revisions[1];
+        assert!(CHUNK[position..].trim_start().starts_with("<id>200</id>"));
+    }
+
+    #[test]
+    fn first_page_is_found() {
+        let page = ArticleDatabase::find_page(CHUNK, 10).unwrap();
+        assert_eq!(page.title, "AccessibleComputing");
+        assert_eq!(page.redirect.as_deref(), Some("Computer accessibility"));
+        assert_eq!(page.revisions.len(), 1);
+
+        let position = page.revisions[0].0;
+        let article = ArticleDatabase::read_article(CHUNK, page, position).unwrap();
+        assert_eq!(article.redirect.as_deref(), Some("Computer accessibility"));
+        assert_eq!(article.body, "#REDIRECT [[Computer accessibility]]");
+    }
+
+    #[test]
+    fn missing_page_is_not_found() {
+        for page_id in [0, 11, 13] {
+            let err = ArticleDatabase::find_page(CHUNK, page_id).err().unwrap();
+            assert!(
+                matches!(err.downcast_ref(), Some(ArticleError::ArticleNotFound)),
+                "{}",
+                page_id
+            );
+        }
+        let err = ArticleDatabase::find_page("", 12).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::ArticleNotFound)
+        ));
+    }
+
+    #[test]
+    fn namespaces_and_escaped_redirects_are_read() {
+        let chunk = r#"<page>
+            <title>Talk:Q&amp;A</title>
+            <ns>1</ns>
+            <id>7</id>
+            <redirect title="Talk:Questions &amp; answers" />
+            <revision>
+              <id>70</id>
+              <timestamp>2020-01-01T00:00:00Z</timestamp>
+              <contributor><ip>::1</ip></contributor>
+              <text />
+            </revision>
+          </page>
+          <page>
+            <title>No namespace</title>
+            <id>8</id>
+            <revision>
+              <id>80</id>
+              <timestamp>2020-01-01T00:00:00Z</timestamp>
+              <contributor><ip>::1</ip></contributor>
+              <text>Body</text>
+            </revision>
+          </page>"#;
+
+        let article = database(chunk).get_article(&entry(7)).unwrap();
+        assert_eq!(article.title, "Talk:Q&A");
+        assert_eq!(article.namespace, 1);
+        assert_eq!(
+            article.redirect.as_deref(),
+            Some("Talk:Questions & answers")
+        );
+        assert_eq!(article.body, "");
+
+        let article = database(chunk).get_article(&entry(8)).unwrap();
+        assert_eq!(article.namespace, 0);
+        assert_eq!(article.redirect, None);
+        assert_eq!(article.body, "Body");
+    }
+
+    #[test]
+    fn page_without_revisions_is_incomplete() {
+        let chunk = "<page><title>Empty</title><ns>0</ns><id>5</id></page>";
+        let article_db = database(chunk);
+        assert!(article_db.get_history(&entry(5)).unwrap().is_empty());
+        let err = article_db.get_article(&entry(5)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::MissingProperty(name)) if name == "revision"
+        ));
+
+        let err = ArticleDatabase::find_page("<page><title>No id</title></page>", 5)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArticleError::MissingProperty(name)) if name == "id"
+        ));
+    }
+
+    #[test]
+    fn pages_are_listed_from_chunks() {
+        let mut reader = Reader::from_str(CHUNK);
+        let mut pages = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(start) if start.local_name().as_ref() == b"page" => {
+                    pages.extend(ArticleDatabase::parse_page(&mut reader, None).unwrap());
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let ids: Vec<u64> = pages.iter().map(|page| page.id).collect();
+        assert_eq!(ids, [10, 12]);
     }
 }
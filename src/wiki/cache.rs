@@ -0,0 +1,196 @@
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+
+/// Memory budget used when none is configured, in bytes.
+pub const DEFAULT_CACHE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Keeps recently decompressed bzip2 streams around, keyed by their offset
+/// in the article database, so neighbouring articles don't decompress the
+/// same block again. Blocks are evicted least recently used first once
+/// their combined size exceeds the budget.
+pub struct BlockCache {
+    inner: Mutex<BlockCacheInner>,
+}
+
+struct BlockCacheInner {
+    blocks: LruCache<u64, Arc<String>>,
+    budget: usize,
+    used: usize,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub blocks: usize,
+    pub used_bytes: usize,
+    pub budget_bytes: usize,
+}
+
+impl BlockCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            inner: Mutex::new(BlockCacheInner {
+                blocks: LruCache::unbounded(),
+                budget,
+                used: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, offset: u64) -> Option<Arc<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        let block = inner.blocks.get(&offset).cloned();
+
+        match block {
+            Some(_) => inner.hits += 1,
+            None => inner.misses += 1,
+        }
+        block
+    }
+
+    pub fn insert(&self, offset: u64, block: Arc<String>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        // A block that doesn't fit into the budget would just evict
+        // everything else and then itself, so don't bother.
+        if block.len() > inner.budget {
+            return;
+        }
+
+        inner.used += block.len();
+        if let Some(previous) = inner.blocks.put(offset, block) {
+            inner.used -= previous.len();
+        }
+        inner.evict();
+    }
+
+    pub fn set_budget(&self, budget: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.budget = budget;
+        inner.evict();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            blocks: inner.blocks.len(),
+            used_bytes: inner.used,
+            budget_bytes: inner.budget,
+        }
+    }
+}
+
+impl BlockCacheInner {
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            match self.blocks.pop_lru() {
+                Some((_, block)) => self.used -= block.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(len: usize) -> Arc<String> {
+        Arc::new("x".repeat(len))
+    }
+
+    fn cached(cache: &BlockCache, offsets: &[u64]) -> Vec<u64> {
+        let inner = cache.inner.lock().unwrap();
+        offsets
+            .iter()
+            .copied()
+            .filter(|offset| inner.blocks.contains(offset))
+            .collect()
+    }
+
+    #[test]
+    fn least_recently_used_blocks_are_evicted() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, block(40));
+        cache.insert(1, block(40));
+        // Using the first block makes the second the least recently used
+        assert!(cache.get(0).is_some());
+        cache.insert(2, block(40));
+
+        assert_eq!(cached(&cache, &[0, 1, 2]), [0, 2]);
+        let stats = cache.stats();
+        assert_eq!(stats.blocks, 2);
+        assert_eq!(stats.used_bytes, 80);
+        assert_eq!(stats.budget_bytes, 100);
+
+        // Several blocks make way for a big one
+        cache.insert(3, block(90));
+        assert_eq!(cached(&cache, &[0, 1, 2, 3]), [3]);
+        assert_eq!(cache.stats().used_bytes, 90);
+    }
+
+    #[test]
+    fn blocks_over_budget_are_not_cached() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, block(50));
+        cache.insert(1, block(101));
+        assert_eq!(cached(&cache, &[0, 1]), [0]);
+        assert_eq!(cache.stats().used_bytes, 50);
+    }
+
+    #[test]
+    fn replaced_blocks_are_not_counted_twice() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, block(60));
+        cache.insert(0, block(30));
+        let stats = cache.stats();
+        assert_eq!((stats.blocks, stats.used_bytes), (1, 30));
+    }
+
+    #[test]
+    fn smaller_budget_evicts_blocks() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, block(40));
+        cache.insert(1, block(40));
+        cache.set_budget(50);
+        assert_eq!(cached(&cache, &[0, 1]), [1]);
+
+        cache.set_budget(0);
+        let stats = cache.stats();
+        assert_eq!((stats.blocks, stats.used_bytes), (0, 0));
+    }
+
+    #[test]
+    fn stats_count_hits_and_misses() {
+        let cache = BlockCache::new(100);
+        assert_eq!(cache.stats().hit_rate(), 0.0);
+
+        assert!(cache.get(0).is_none());
+        cache.insert(0, block(10));
+        assert_eq!(cache.get(0).as_deref().map(String::len), Some(10));
+        assert!(cache.get(0).is_some());
+        assert!(cache.get(1).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+}
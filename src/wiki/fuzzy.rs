@@ -0,0 +1,248 @@
+//! Typo-tolerant title matching. Titles match a query if few edits turn one
+//! into the other, where an edit inserts, deletes or replaces a character,
+//! or swaps two adjacent ones.
+
+/// Most edits allowed between a query of the given length in characters and
+/// what it matches, so that short queries don't match nearly everything.
+pub fn max_edits(query_len: usize) -> usize {
+    match query_len {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// A title reached by [`walk`].
+pub struct Visit {
+    /// Position of the title in the sorted titles
+    pub position: usize,
+    /// Edits between the whole title and the query, if within the limit
+    pub distance: Option<usize>,
+    /// Length in characters and edits of the prefix of the title that is
+    /// closest to the query, if within the limit
+    pub prefix: Option<(usize, usize)>,
+}
+
+/// Walks `count` titles, sorted by their search keys, as if they were a trie.
+/// `title_key` writes the search key of the title at a position.
+///
+/// The rows of the edit distance table of a prefix are shared by all titles
+/// starting with it, and once a prefix is too far from every prefix of the
+/// query, the titles starting with it are skipped by binary search. Every
+/// title reached is passed to `visit`, including the first of those.
+pub fn walk(
+    count: usize,
+    title_key: impl Fn(usize, &mut Vec<char>),
+    query: &[char],
+    max_edits: usize,
+    mut visit: impl FnMut(Visit),
+) {
+    let mut path: Vec<char> = Vec::new();
+    // Row `i` holds the edits between the first `i` characters of the path
+    // and each prefix of the query
+    let mut rows: Vec<Vec<usize>> = vec![(0..=query.len()).collect()];
+    let mut title = Vec::new();
+    let mut probe = Vec::new();
+
+    let mut position = 0;
+    while position < count {
+        title_key(position, &mut title);
+
+        let common = path
+            .iter()
+            .zip(&title)
+            .take_while(|(left, right)| left == right)
+            .count();
+        path.truncate(common);
+        rows.truncate(common + 1);
+
+        // The prefix of the title, if any, below which no title can match
+        let mut dead_end = None;
+        for &chr in &title[common..] {
+            let row = next_row(&rows, &path, query, chr);
+            if row.iter().min().is_some_and(|&edits| edits > max_edits) {
+                dead_end = Some(path.len() + 1);
+                break;
+            }
+
+            path.push(chr);
+            rows.push(row);
+        }
+
+        // Prefixes as long as the query are preferred on ties, as they
+        // replace characters rather than drop them
+        let prefix = rows
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(len, row)| (len, row[query.len()]))
+            .filter(|&(_, edits)| edits <= max_edits)
+            .min_by_key(|&(len, edits)| (edits, len.abs_diff(query.len())));
+        let distance = match dead_end {
+            Some(_) => None,
+            None => Some(rows[rows.len() - 1][query.len()]).filter(|&edits| edits <= max_edits),
+        };
+        visit(Visit {
+            position,
+            distance,
+            prefix,
+        });
+
+        position = match dead_end {
+            Some(len) => skip_prefix(position, count, &title_key, &title[..len], &mut probe),
+            None => position + 1,
+        };
+    }
+}
+
+/// Computes the row of the edit distance table for the path extended by a
+/// character.
+fn next_row(rows: &[Vec<usize>], path: &[char], query: &[char], chr: char) -> Vec<usize> {
+    let depth = path.len();
+    let prev = &rows[depth];
+
+    let mut row = Vec::with_capacity(prev.len());
+    row.push(depth + 1);
+    for i in 1..prev.len() {
+        let mut edits = (row[i - 1] + 1)
+            .min(prev[i] + 1)
+            .min(prev[i - 1] + usize::from(query[i - 1] != chr));
+
+        // Two swapped characters count as a single edit
+        if depth >= 1 && i >= 2 && chr == query[i - 2] && path[depth - 1] == query[i - 1] {
+            edits = edits.min(rows[depth - 1][i - 2] + 1);
+        }
+
+        row.push(edits);
+    }
+
+    row
+}
+
+/// Finds the position of the first title after `position` that doesn't
+/// start with `prefix`, given that all titles starting with it are adjacent.
+fn skip_prefix(
+    position: usize,
+    count: usize,
+    title_key: &impl Fn(usize, &mut Vec<char>),
+    prefix: &[char],
+    probe: &mut Vec<char>,
+) -> usize {
+    let (mut low, mut high) = (position + 1, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        title_key(mid, probe);
+        if probe.starts_with(prefix) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    /// Walks sorted titles, returning the visited titles with their
+    /// distance to the query.
+    fn walk_titles(titles: &[&str], query: &str) -> Vec<(String, Option<usize>)> {
+        let query = chars(query);
+        let mut visited = Vec::new();
+        walk(
+            titles.len(),
+            |position, key| {
+                key.clear();
+                key.extend(titles[position].chars());
+            },
+            &query,
+            max_edits(query.len()),
+            |visit| visited.push((titles[visit.position].to_owned(), visit.distance)),
+        );
+        visited
+    }
+
+    fn distance(title: &str, query: &str) -> Option<usize> {
+        walk_titles(&[title], query)[0].1
+    }
+
+    #[test]
+    fn max_edits_grows_with_query_length() {
+        let edits: Vec<usize> = (0..=8).map(max_edits).collect();
+        assert_eq!(edits, [0, 0, 0, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn short_queries_must_match_exactly() {
+        assert_eq!(distance("ab", "ab"), Some(0));
+        assert_eq!(distance("ac", "ab"), None);
+    }
+
+    #[test]
+    fn medium_queries_allow_one_edit() {
+        assert_eq!(distance("rust", "rust"), Some(0));
+        assert_eq!(distance("rusty", "rust"), Some(1));
+        assert_eq!(distance("rst", "rust"), Some(1));
+        assert_eq!(distance("rost", "rust"), Some(1));
+        assert_eq!(distance("rsot", "rust"), None);
+    }
+
+    #[test]
+    fn long_queries_allow_two_edits() {
+        assert_eq!(distance("berlin", "berlin"), Some(0));
+        assert_eq!(distance("barlyn", "berlin"), Some(2));
+        assert_eq!(distance("barlynn", "berlin"), None);
+    }
+
+    #[test]
+    fn swapped_characters_are_one_edit() {
+        assert_eq!(distance("rsut", "rust"), Some(1));
+        assert_eq!(distance("ebrlni", "berlin"), Some(2));
+    }
+
+    #[test]
+    fn prefix_is_closest_start_of_title() {
+        let query = chars("rust");
+        let mut prefixes = Vec::new();
+        walk(
+            1,
+            |_, key| {
+                key.clear();
+                key.extend("rosting".chars());
+            },
+            &query,
+            1,
+            |visit| prefixes.push(visit.prefix),
+        );
+        assert_eq!(prefixes, [Some((4, 1))]);
+    }
+
+    #[test]
+    fn walk_skips_titles_below_dead_ends() {
+        let titles = ["abc", "rust", "xxa", "xxb", "xxc", "xyz", "zest"];
+        let visited = walk_titles(&titles, "rust");
+        let visited: Vec<&str> = visited.iter().map(|(title, _)| title.as_str()).collect();
+        // `xx` is more than one edit away from every start of `rust`, so
+        // only the first title starting with it is visited
+        assert_eq!(visited, ["abc", "rust", "xxa", "xyz", "zest"]);
+    }
+
+    #[test]
+    fn skip_prefix_finds_end_of_prefix() {
+        let titles = ["aa", "ab", "ba", "bb", "bc", "c"];
+        let title_key = |position: usize, key: &mut Vec<char>| {
+            key.clear();
+            key.extend(titles[position].chars());
+        };
+        let mut probe = Vec::new();
+        assert_eq!(skip_prefix(0, 6, &title_key, &['a'], &mut probe), 2);
+        assert_eq!(skip_prefix(2, 6, &title_key, &['b'], &mut probe), 5);
+        assert_eq!(skip_prefix(3, 6, &title_key, &['b', 'b'], &mut probe), 4);
+        assert_eq!(skip_prefix(5, 6, &title_key, &['c'], &mut probe), 6);
+    }
+}
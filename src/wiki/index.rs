@@ -1,20 +1,73 @@
-use std::{fs::File, io::Read, str};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+    str,
+};
 
 use rayon::{
-    prelude::{IntoParallelRefIterator, ParallelIterator},
+    prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSliceMut,
     str::ParallelString,
 };
 
+use super::{
+    fuzzy,
+    normalize::{cmp_search_keys, search_key},
+    ranking,
+};
+
 pub struct Index {
     entries: Vec<IndexEntry>,
+    /// Positions of the entries, ordered by the search keys of their titles
+    /// so that titles with a given prefix can be found by binary search.
+    sorted: Vec<u32>,
+    /// Importance of each entry, see [`ranking::importance`]. Empty if
+    /// nothing is known about the importance of articles.
+    importance: Vec<f32>,
+}
+
+/// A title matching a search, with the score it is ranked by.
+pub struct SearchResult<'a> {
+    pub entry: &'a IndexEntry,
+    pub score: f64,
+    /// Position of the title among the matches before they were ranked,
+    /// which orders titles with the same score the same way every time
+    pub order: usize,
+}
+
+/// The best results of a search, out of all its matches.
+pub struct RankedResults<'a> {
+    pub results: Vec<SearchResult<'a>>,
+    /// Number of titles matching the search
+    pub total: usize,
+    /// Number of matching titles that were not skipped, of which `results`
+    /// holds the best
+    pub remaining: usize,
 }
 
 pub struct IndexEntry {
     pub offset: u64,
+    /// Offset of the next bzip2 stream, which is where the stream containing
+    /// this entry ends. `None` for entries in the last stream of the file.
+    pub end_offset: Option<u64>,
     pub page_id: u64,
     pub page_name: String,
 }
 
+/// A query correction found by [`Index::correct_prefix`].
+struct Correction {
+    distance: usize,
+    /// Difference in length to the query, in characters
+    len_difference: usize,
+    /// Number of titles found starting with the correction
+    titles: usize,
+    /// Spelling of the correction in the first title starting with it
+    spelling: String,
+}
+
 impl IndexEntry {
     fn parse(line: &str) -> anyhow::Result<Self> {
         let mut separators = [0usize; 2];
@@ -43,6 +96,7 @@ impl IndexEntry {
 
         return Ok(Self {
             offset,
+            end_offset: None,
             page_id,
             page_name,
         });
@@ -50,7 +104,7 @@ impl IndexEntry {
 }
 
 impl Index {
-    pub fn from_file(path: &str) -> anyhow::Result<Index> {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Index> {
         let file = File::open(path)?;
         Self::load(file)
     }
@@ -60,32 +114,300 @@ impl Index {
         file.read_to_string(&mut data)?;
 
         let lines: Vec<&str> = data.par_lines().collect();
+        let mut entries: Vec<IndexEntry> = lines
+            .par_iter()
+            .map(|line| IndexEntry::parse(line).expect("Failed to parse index entry"))
+            .collect();
+
+        Self::assign_end_offsets(&mut entries);
+        let sorted = Self::sort_titles(&entries);
         return Ok(Index {
-            entries: lines
-                .par_iter()
-                .map(|line| IndexEntry::parse(line).expect("Failed to parse index entry"))
-                .collect(),
+            entries,
+            sorted,
+            importance: Vec::new(),
+        });
+    }
+
+    /// Builds an index from entries that are in file order, like the ones
+    /// listed by scanning an article database.
+    pub fn from_entries(mut entries: Vec<IndexEntry>) -> Index {
+        Self::assign_end_offsets(&mut entries);
+        let sorted = Self::sort_titles(&entries);
+        Index {
+            entries,
+            sorted,
+            importance: Vec::new(),
+        }
+    }
+
+    /// Sets the importance of every entry, which search results are ranked
+    /// by along with how well they match.
+    pub fn set_importance(&mut self, importance: impl Fn(&IndexEntry) -> f32 + Sync + Send) {
+        self.importance = self.entries.par_iter().map(importance).collect();
+    }
+
+    /// Writes the index in the `offset:page_id:title` format of the dumps.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{}:{}:{}",
+                entry.offset, entry.page_id, entry.page_name
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The index lists entries in file order, so the end of each stream is
+    /// the next distinct offset that follows it.
+    fn assign_end_offsets(entries: &mut [IndexEntry]) {
+        let mut stream_offset = None;
+        let mut next_stream_offset = None;
+
+        for entry in entries.iter_mut().rev() {
+            if stream_offset != Some(entry.offset) {
+                next_stream_offset = stream_offset;
+                stream_offset = Some(entry.offset);
+            }
+            entry.end_offset = next_stream_offset;
+        }
+    }
+
+    fn sort_titles(entries: &[IndexEntry]) -> Vec<u32> {
+        let mut sorted: Vec<u32> = (0..entries.len() as u32).collect();
+        sorted.par_sort_unstable_by(|&left, &right| {
+            cmp_search_keys(
+                &entries[left as usize].page_name,
+                &entries[right as usize].page_name,
+            )
         });
+        sorted
+    }
+
+    /// Finds up to `limit` titles starting with a query, best first. Titles
+    /// are scored by how much of them the query covers and by the importance
+    /// of their articles, and titles with the same score stay in order.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult<'_>> {
+        self.search_after(query, |_, _| true, limit).results
+    }
+
+    /// Searches like [`Index::search`], skipping the results for which
+    /// `is_after` returns false when given their score and order, like the
+    /// ones shown on earlier pages of results.
+    pub fn search_after(
+        &self,
+        query: &str,
+        is_after: impl Fn(f64, usize) -> bool,
+        limit: usize,
+    ) -> RankedResults<'_> {
+        let query: String = search_key(query).collect();
+        self.rank(
+            self.starting_with(&query),
+            query.chars().count(),
+            is_after,
+            limit,
+        )
+    }
+
+    /// Positions of the titles starting with a prefix, in the order of their
+    /// search keys.
+    pub fn positions_starting_with(&self, prefix: &str) -> impl Iterator<Item = usize> + '_ {
+        let prefix: String = search_key(prefix).collect();
+        self.starting_with(&prefix)
     }
 
-    pub fn find_article(&self, query: &str) -> Vec<&IndexEntry> {
-        let query = query.to_lowercase();
+    /// Scores the entries at `positions` like [`Index::search`] does for a
+    /// query of `query_len` characters, returning up to `limit` of the best
+    /// for which `is_after` returns true. Entries with the same score stay in
+    /// the order of `positions`.
+    pub fn rank(
+        &self,
+        positions: impl Iterator<Item = usize>,
+        query_len: usize,
+        is_after: impl Fn(f64, usize) -> bool,
+        limit: usize,
+    ) -> RankedResults<'_> {
+        let by_score = |left: &SearchResult, right: &SearchResult| {
+            right
+                .score
+                .total_cmp(&left.score)
+                .then(left.order.cmp(&right.order))
+        };
+
+        // Only the best results are kept while scoring, so that short
+        // prefixes matching most of the titles don't collect all of them
+        let mut total = 0;
+        let mut remaining = 0;
+        let mut results: Vec<SearchResult> = Vec::new();
+        for (order, idx) in positions.enumerate() {
+            total += 1;
+            let entry = &self.entries[idx];
+            let importance = self.importance.get(idx).copied().unwrap_or_default();
+            let title_len = entry.page_name.chars().count();
+            let score = ranking::score(query_len, title_len, importance);
+            if !is_after(score, order) {
+                continue;
+            }
+
+            remaining += 1;
+            results.push(SearchResult {
+                entry,
+                score,
+                order,
+            });
+            if results.len() >= 2 * limit.max(1) {
+                results.select_nth_unstable_by(limit, by_score);
+                results.truncate(limit);
+            }
+        }
+
+        if limit < results.len() {
+            results.select_nth_unstable_by(limit, by_score);
+            results.truncate(limit);
+        }
+        results.sort_unstable_by(by_score);
 
-        let mut results = self
-            .entries
+        RankedResults {
+            results,
+            total,
+            remaining,
+        }
+    }
+
+    /// Finds up to `limit` titles that are a few typos away from a title
+    /// that doesn't exist, closest first and then most important first.
+    pub fn find_similar(&self, title: &str, limit: usize) -> Vec<&IndexEntry> {
+        let query: Vec<char> = search_key(title).collect();
+
+        let mut matches = Vec::new();
+        self.walk_fuzzy(&query, |visit| {
+            if let Some(distance) = visit.distance.filter(|&distance| distance > 0) {
+                let idx = self.sorted[visit.position] as usize;
+                let importance = self.importance.get(idx).copied().unwrap_or_default();
+                matches.push((distance, importance, &self.entries[idx]));
+            }
+        });
+
+        matches.sort_by(|left, right| {
+            (left.0.cmp(&right.0))
+                .then(right.1.total_cmp(&left.1))
+                .then(left.2.page_name.len().cmp(&right.2.page_name.len()))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, entry)| entry)
+            .collect()
+    }
+
+    /// Corrects a query that no title starts with into up to `limit` queries
+    /// a few typos away that titles do start with. Corrections with fewer
+    /// typos come first, then those closer to the length of the query, then
+    /// those that more titles start with.
+    pub fn correct_prefix(&self, query: &str, limit: usize) -> Vec<String> {
+        let query: Vec<char> = search_key(query).collect();
+
+        // Corrections by their search keys
+        let mut corrections: HashMap<String, Correction> = HashMap::new();
+        self.walk_fuzzy(&query, |visit| {
+            let (len, distance) = match visit.prefix {
+                Some((len, distance)) if distance > 0 => (len, distance),
+                _ => return,
+            };
+
+            let entry = self.sorted_entry(visit.position);
+            let key: String = search_key(&entry.page_name).take(len).collect();
+            corrections
+                .entry(key.trim_end().to_owned())
+                .or_insert_with(|| Correction {
+                    distance,
+                    len_difference: len.abs_diff(query.len()),
+                    titles: 0,
+                    spelling: entry
+                        .page_name
+                        .chars()
+                        .take(len)
+                        .collect::<String>()
+                        .trim_end()
+                        .to_owned(),
+                })
+                .titles += 1;
+        });
+
+        let mut corrections: Vec<Correction> = corrections.into_values().collect();
+        corrections.sort_by(|left, right| {
+            (
+                left.distance,
+                left.len_difference,
+                right.titles,
+                &left.spelling,
+            )
+                .cmp(&(
+                    right.distance,
+                    right.len_difference,
+                    left.titles,
+                    &right.spelling,
+                ))
+        });
+        corrections
+            .into_iter()
+            .take(limit)
+            .map(|correction| correction.spelling)
+            .collect()
+    }
+
+    fn walk_fuzzy(&self, query: &[char], visit: impl FnMut(fuzzy::Visit)) {
+        let max_edits = fuzzy::max_edits(query.len());
+        if max_edits == 0 {
+            return;
+        }
+
+        let title_key = |position: usize, title: &mut Vec<char>| {
+            title.clear();
+            title.extend(search_key(&self.sorted_entry(position).page_name));
+        };
+        fuzzy::walk(self.sorted.len(), title_key, query, max_edits, visit);
+    }
+
+    /// Iterates over the positions of the entries whose search keys start
+    /// with `prefix`, which must be a search key itself, in the order of
+    /// their search keys.
+    fn starting_with(&self, prefix: &str) -> impl Iterator<Item = usize> + '_ {
+        let starts_with = |idx: u32| {
+            let mut key = search_key(&self.entries[idx as usize].page_name);
+            prefix.chars().all(|chr| key.next() == Some(chr))
+        };
+
+        let start = self.sorted.partition_point(|&idx| {
+            search_key(&self.entries[idx as usize].page_name).cmp(prefix.chars()) == Ordering::Less
+        });
+        // All keys starting with the prefix sort right after it
+        let len = self.sorted[start..].partition_point(|&idx| starts_with(idx));
+        self.sorted[start..start + len]
             .iter()
-            .filter(|entry| Self::starts_with(&entry.page_name, &query))
-            .take(100)
-            .collect::<Vec<&IndexEntry>>();
+            .map(|&idx| idx as usize)
+    }
 
-        results.sort_by(|a, b| a.page_name.len().cmp(&b.page_name.len()));
-        results
+    fn sorted_entry(&self, position: usize) -> &IndexEntry {
+        &self.entries[self.sorted[position] as usize]
     }
 
     pub fn find_article_exact(&self, name: &str) -> Option<&IndexEntry> {
+        self.find_position(name)
+            .map(|position| &self.entries[position])
+    }
+
+    /// Finds the position of an entry like [`Index::find_article_exact`].
+    pub fn find_position(&self, name: &str) -> Option<usize> {
         self.entries
             .par_iter()
-            .find_any(|entry| Self::equals(&entry.page_name, name))
+            .position_any(|entry| Self::equals(&entry.page_name, name))
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
     }
 
     pub fn size(&self) -> usize {
@@ -105,19 +427,208 @@ impl Index {
 
         true
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(offset: u64, page_id: u64, page_name: &str) -> IndexEntry {
+        IndexEntry {
+            offset,
+            end_offset: None,
+            page_id,
+            page_name: page_name.to_owned(),
+        }
+    }
+
+    fn end_offsets(index: &Index) -> Vec<Option<u64>> {
+        index
+            .entries()
+            .iter()
+            .map(|entry| entry.end_offset)
+            .collect()
+    }
+
+    #[test]
+    fn streams_end_where_the_next_one_starts() {
+        let index = Index::from_entries(vec![
+            entry(10, 1, "A"),
+            entry(10, 2, "B"),
+            entry(10, 3, "C"),
+            entry(25, 4, "D"),
+            entry(40, 5, "E"),
+            entry(40, 6, "F"),
+        ]);
+        assert_eq!(
+            end_offsets(&index),
+            [Some(25), Some(25), Some(25), Some(40), None, None]
+        );
+    }
 
-    fn starts_with(name: &str, query: &str) -> bool {
-        let mut name = name.chars();
-        for query_chr in query.chars() {
-            match name.next() {
-                Some(name_chr) => {
-                    if !name_chr.to_lowercase().eq(query_chr.to_lowercase()) {
-                        return false;
-                    }
-                }
-                None => return false,
+    #[test]
+    fn last_stream_has_no_end() {
+        assert_eq!(
+            end_offsets(&Index::from_entries(vec![entry(10, 1, "A")])),
+            [None]
+        );
+        assert_eq!(
+            end_offsets(&Index::from_entries(vec![
+                entry(10, 1, "A"),
+                entry(10, 2, "B")
+            ])),
+            [None, None]
+        );
+        assert!(end_offsets(&Index::from_entries(Vec::new())).is_empty());
+    }
+
+    #[test]
+    fn search_pages_through_ties_once() {
+        let titles = [
+            "Rust 3",
+            "Rust 1",
+            "Rustacean",
+            "Rust 2",
+            "Rust",
+            "Rusty 1",
+            "Rust 4",
+        ];
+        let mut index = Index::from_entries(
+            titles
+                .iter()
+                .enumerate()
+                .map(|(idx, title)| entry(10, idx as u64, title))
+                .collect(),
+        );
+        index.set_importance(|entry| match entry.page_name.as_str() {
+            "Rustacean" => 0.5,
+            _ => 0.0,
+        });
+        let all: Vec<(f64, usize, &str)> = index
+            .search("rust", 100)
+            .iter()
+            .map(|result| (result.score, result.order, result.entry.page_name.as_str()))
+            .collect();
+        assert_eq!(all.len(), titles.len());
+
+        let mut paged = Vec::new();
+        loop {
+            let last = paged.last().copied();
+            let is_after = |score: f64, order: usize| {
+                last.map_or(true, |(last_score, last_order, _)| {
+                    score < last_score || (score == last_score && order > last_order)
+                })
+            };
+            let page = index.search_after("rust", is_after, 2);
+            assert_eq!(page.total, titles.len());
+            assert_eq!(page.remaining, titles.len() - paged.len());
+            if page.results.is_empty() {
+                break;
             }
+            paged.extend(
+                page.results
+                    .iter()
+                    .map(|result| (result.score, result.order, result.entry.page_name.as_str())),
+            );
         }
-        true
+        assert_eq!(paged, all);
+        // Titles of the same length have the same score, and stay in order
+        let rust_titles: Vec<&str> = all
+            .iter()
+            .map(|&(_, _, title)| title)
+            .filter(|title| title.starts_with("Rust ") && title.len() == 6)
+            .collect();
+        assert_eq!(rust_titles, ["Rust 1", "Rust 2", "Rust 3", "Rust 4"]);
+    }
+
+    fn titles(titles: &[&str]) -> Index {
+        Index::from_entries(
+            titles
+                .iter()
+                .enumerate()
+                .map(|(idx, title)| entry(10, idx as u64, title))
+                .collect(),
+        )
+    }
+
+    fn names(entries: Vec<&IndexEntry>) -> Vec<&str> {
+        entries
+            .into_iter()
+            .map(|entry| entry.page_name.as_str())
+            .collect()
+    }
+
+    const TITLES: [&str; 11] = [
+        "Rust",
+        "Rest",
+        "Dust",
+        "Rusty",
+        "Trust",
+        "Ruby",
+        "Python",
+        "Rust (band)",
+        "Last",
+        "Rasta",
+        "Rudy",
+    ];
+
+    #[test]
+    fn similar_titles_are_closest_first() {
+        let index = titles(&TITLES);
+        // Titles with as many typos are ordered by length, then stay in order
+        assert_eq!(
+            names(index.find_similar("Rast", 10)),
+            ["Last", "Rest", "Rust", "Rasta"]
+        );
+        assert_eq!(names(index.find_similar("Rast", 2)), ["Last", "Rest"]);
+        // The title itself isn't similar to itself
+        assert_eq!(
+            names(index.find_similar("rust", 10)),
+            ["Dust", "Rest", "Rusty", "Trust"]
+        );
+    }
+
+    #[test]
+    fn similar_titles_are_most_important_first() {
+        let mut index = titles(&TITLES);
+        index.set_importance(|entry| match entry.page_name.as_str() {
+            "Rasta" => 0.9,
+            "Rust" => 0.5,
+            _ => 0.0,
+        });
+        assert_eq!(
+            names(index.find_similar("Rast", 10)),
+            ["Rasta", "Rust", "Last", "Rest"]
+        );
+    }
+
+    #[test]
+    fn nothing_is_similar_to_short_or_distant_titles() {
+        let index = titles(&TITLES);
+        assert!(index.find_similar("Ru", 10).is_empty());
+        assert!(index.find_similar("Xyzzy", 10).is_empty());
+        assert!(index.find_similar("Rast", 0).is_empty());
+        assert!(titles(&[]).find_similar("Rast", 10).is_empty());
+    }
+
+    #[test]
+    fn corrections_are_ranked() {
+        let index = titles(&TITLES);
+        // Three titles start with `Rust`, one with each of the others
+        assert_eq!(index.correct_prefix("rist", 10), ["Rust", "Rast", "Rest"]);
+        assert_eq!(index.correct_prefix("rist", 1), ["Rust"]);
+        assert_eq!(index.correct_prefix("Rudt", 10), ["Rust", "Rudy"]);
+        // Corrections are prefixes as long as the query, spelled like the
+        // titles
+        assert_eq!(index.correct_prefix("pythn", 10), ["Pytho"]);
+    }
+
+    #[test]
+    fn nothing_corrects_short_or_distant_queries() {
+        let index = titles(&TITLES);
+        assert!(index.correct_prefix("Ri", 10).is_empty());
+        assert!(index.correct_prefix("Xyzzy", 10).is_empty());
+        assert!(index.correct_prefix("rist", 0).is_empty());
+        assert!(titles(&[]).correct_prefix("rist", 10).is_empty());
     }
 }
@@ -0,0 +1,256 @@
+//! The categories of pages and the links between them, which are found by
+//! scanning a dump offline into a links file, so that searches can filter by
+//! them.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+use thiserror::Error;
+
+use super::{index::Index, siteinfo::SiteInfo};
+
+/// Namespace key of categories, whose names start with `Category:`.
+pub const CATEGORY_NAMESPACE: i32 = 14;
+
+#[derive(Error, Debug)]
+pub enum LinksError {
+    #[error("invalid line {0} in links file: {1}")]
+    InvalidLine(usize, String),
+
+    #[error("categories need a links file for the dump, see `wiki-rs build-links`")]
+    MissingLinksFile,
+}
+
+/// The links and categories of a page, as written to a links file.
+pub struct PageRecord {
+    pub page_id: u64,
+    /// Page ids of the pages it links to
+    pub links: Vec<u64>,
+    /// Names of its categories, without the namespace prefix
+    pub categories: Vec<String>,
+}
+
+/// Pages by their categories and by the pages they link to, given as
+/// positions in the index.
+#[derive(Default)]
+pub struct LinkIndex {
+    /// Members of each category, sorted
+    categories: HashMap<String, Vec<u32>>,
+    /// Pages linking to each page, sorted
+    backlinks: HashMap<u32, Vec<u32>>,
+}
+
+/// Normalizes the name of a category, which may be given with or without
+/// its `Category:` prefix, into the form it is stored under.
+pub fn category_name(name: &str, site_info: &SiteInfo) -> String {
+    let name = site_info.normalize_title(name);
+    match site_info.namespace_of(&name) {
+        Some(ns) if ns.key == CATEGORY_NAMESPACE => {
+            site_info.normalize_title(&name[ns.name.len() + 1..])
+        }
+        _ => name,
+    }
+}
+
+impl PageRecord {
+    fn parse(line: &str) -> Option<PageRecord> {
+        let mut fields = line.split('\t');
+        let page_id = fields.next()?.parse().ok()?;
+        let links = match fields.next()? {
+            "" => Vec::new(),
+            links => links
+                .split(',')
+                .map(|id| id.parse().ok())
+                .collect::<Option<_>>()?,
+        };
+        let categories = match fields.next()? {
+            "" => Vec::new(),
+            categories => categories.split('|').map(str::to_owned).collect(),
+        };
+
+        fields.next().is_none().then_some(PageRecord {
+            page_id,
+            links,
+            categories,
+        })
+    }
+}
+
+impl LinkIndex {
+    /// Reads a links file with lines of `page_id<TAB>links<TAB>categories`,
+    /// where links are page ids separated by commas and categories are
+    /// separated by `|`. Pages missing from `index` are left out.
+    pub fn from_file(path: impl AsRef<Path>, index: &Index) -> anyhow::Result<LinkIndex> {
+        // Positions of the entries ordered by page id
+        let mut by_page_id: Vec<(u64, u32)> = index
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.page_id, position as u32))
+            .collect();
+        by_page_id.sort_unstable();
+        let position_of = |page_id: u64| {
+            by_page_id
+                .binary_search_by_key(&page_id, |&(page_id, _)| page_id)
+                .ok()
+                .map(|idx| by_page_id[idx].1)
+        };
+
+        let mut links = LinkIndex::default();
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let record = match PageRecord::parse(&line) {
+                Some(record) => record,
+                None => bail!(LinksError::InvalidLine(number + 1, line)),
+            };
+            let page = match position_of(record.page_id) {
+                Some(page) => page,
+                None => continue,
+            };
+
+            for category in record.categories {
+                links.categories.entry(category).or_default().push(page);
+            }
+            for target in record.links.into_iter().filter_map(position_of) {
+                links.backlinks.entry(target).or_default().push(page);
+            }
+        }
+
+        for pages in links
+            .categories
+            .values_mut()
+            .chain(links.backlinks.values_mut())
+        {
+            pages.sort_unstable();
+            pages.dedup();
+        }
+        Ok(links)
+    }
+
+    pub fn write_to_file(path: impl AsRef<Path>, records: &[PageRecord]) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for record in records {
+            let links: Vec<String> = record.links.iter().map(u64::to_string).collect();
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                record.page_id,
+                links.join(","),
+                record.categories.join("|")
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Positions of the pages in a category, given by its name without the
+    /// namespace prefix.
+    pub fn category_members(&self, category: &str) -> &[u32] {
+        self.categories
+            .get(category)
+            .map_or(&[], |pages| pages.as_slice())
+    }
+
+    /// Positions of the pages linking to the page at a position.
+    pub fn backlinks(&self, page: usize) -> &[u32] {
+        self.backlinks
+            .get(&(page as u32))
+            .map_or(&[], |pages| pages.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::wiki::index::IndexEntry;
+
+    /// Writes records to a file of their own and reads them back.
+    fn round_trip(records: &[PageRecord], index: &Index) -> anyhow::Result<LinkIndex> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "wiki-rs-links-{}-{}.txt",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        LinkIndex::write_to_file(&path, records)?;
+        let result = LinkIndex::from_file(&path, index);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    fn index() -> Index {
+        let entry = |page_id, page_name: &str| IndexEntry {
+            offset: 10,
+            end_offset: None,
+            page_id,
+            page_name: page_name.to_owned(),
+        };
+        Index::from_entries(vec![
+            entry(12, "Anarchism"),
+            entry(25, "Autism"),
+            entry(39, "Albedo"),
+        ])
+    }
+
+    fn record(page_id: u64, links: &[u64], categories: &[&str]) -> PageRecord {
+        PageRecord {
+            page_id,
+            links: links.to_vec(),
+            categories: categories.iter().map(|&name| name.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn links_survive_round_trip() {
+        let links = round_trip(
+            &[
+                record(39, &[12, 25, 404], &["Climate", "Radiation"]),
+                record(12, &[39, 39], &["Political ideologies"]),
+                record(25, &[], &[]),
+                // Pages missing from the index are left out
+                record(404, &[12], &["Climate"]),
+            ],
+            &index(),
+        )
+        .unwrap();
+
+        assert_eq!(links.category_members("Climate"), [2]);
+        assert_eq!(links.category_members("Radiation"), [2]);
+        assert_eq!(links.category_members("Political ideologies"), [0]);
+        assert_eq!(links.category_members("Autism"), [] as [u32; 0]);
+        assert_eq!(links.backlinks(0), [2]);
+        assert_eq!(links.backlinks(1), [2]);
+        // Pages linking twice are listed once
+        assert_eq!(links.backlinks(2), [0]);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        let path =
+            std::env::temp_dir().join(format!("wiki-rs-links-invalid-{}.txt", std::process::id()));
+        std::fs::write(&path, "12\t39\t\n25\tx\t\n").unwrap();
+        let err = LinkIndex::from_file(&path, &index()).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(LinksError::InvalidLine(2, line)) if line == "25\tx\t"
+        ));
+    }
+
+    #[test]
+    fn lines_need_all_fields() {
+        for line in ["12", "12\t39", "12\t39\tA\tB", "x\t39\tA", "12\t39,\tA"] {
+            assert!(PageRecord::parse(line).is_none(), "{:?}", line);
+        }
+        let record = PageRecord::parse("12\t\t").unwrap();
+        assert!(record.links.is_empty() && record.categories.is_empty());
+    }
+}
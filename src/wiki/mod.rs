@@ -1,2 +1,12 @@
 pub mod article;
+pub mod cache;
+pub mod fuzzy;
 pub mod index;
+pub mod links;
+pub mod normalize;
+pub mod query;
+pub mod ranking;
+pub mod revision;
+pub mod revisions;
+pub mod siteinfo;
+pub mod summary;
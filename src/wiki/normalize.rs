@@ -0,0 +1,120 @@
+//! Normalization of titles and queries into the keys they are sorted and
+//! searched by, so that searches find titles however they are spelled in
+//! terms of case, accents and Unicode representation.
+
+use std::{cmp::Ordering, iter};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Converts text into its search key. Compatibility decomposition, as in
+/// NFKC, splits full-width characters, ligatures and composed characters
+/// into their plain parts. Of those, accents are dropped and the rest is
+/// case-folded, so that e.g. `Zürich` and `ZURICH` share the key `zurich`,
+/// and `Straße` and `STRASSE` share `strasse`.
+pub fn search_key(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.nfkd()
+        .filter(|&chr| !is_accent(chr))
+        .flat_map(char::to_lowercase)
+        .flat_map(fold_case)
+}
+
+/// Accents are the combining diacritical marks of the Latin, Greek and
+/// Cyrillic scripts. Combining marks of other scripts, like the vowel signs
+/// of Devanagari, are kept, as they tell different words apart.
+fn is_accent(chr: char) -> bool {
+    matches!(chr, '\u{0300}'..='\u{036F}')
+}
+
+/// Folds the few lowercase characters that case folding maps to others.
+fn fold_case(chr: char) -> impl Iterator<Item = char> {
+    let (first, second) = match chr {
+        'ß' => ('s', Some('s')),
+        'ς' => ('σ', None),
+        chr => (chr, None),
+    };
+    iter::once(first).chain(second)
+}
+
+/// Collects the search key of text into a string, taking a shortcut for
+/// ASCII text, whose key is just its lowercase form.
+pub fn search_key_string(text: &str) -> String {
+    match text.is_ascii() {
+        true => text.to_ascii_lowercase(),
+        false => search_key(text).collect(),
+    }
+}
+
+/// Compares the search keys of two texts, taking a shortcut for ASCII text
+/// that spares sorting all titles from normalizing them over and over.
+pub fn cmp_search_keys(left: &str, right: &str) -> Ordering {
+    match left.is_ascii() && right.is_ascii() {
+        true => left
+            .bytes()
+            .map(|byte| byte.to_ascii_lowercase())
+            .cmp(right.bytes().map(|byte| byte.to_ascii_lowercase())),
+        false => search_key(left).cmp(search_key(right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_key_folds_case_and_accents() {
+        assert_eq!(search_key_string("Zürich"), "zurich");
+        assert_eq!(search_key_string("ZURICH"), "zurich");
+        assert_eq!(search_key_string("Zu\u{0308}rich"), "zurich");
+        assert_eq!(search_key_string("Crème Brûlée"), "creme brulee");
+    }
+
+    #[test]
+    fn search_key_folds_special_letters() {
+        assert_eq!(search_key_string("Straße"), "strasse");
+        assert_eq!(search_key_string("STRASSE"), "strasse");
+        assert_eq!(search_key_string("ΟΔΟΣ"), search_key_string("οδος"));
+        assert_eq!(search_key_string("ﬁne"), "fine");
+    }
+
+    #[test]
+    fn search_key_keeps_other_combining_marks() {
+        assert_eq!(search_key_string("हिंदी"), "हिंदी");
+    }
+
+    #[test]
+    fn cmp_search_keys_agrees_with_search_keys() {
+        let titles = [
+            "",
+            "a",
+            "A",
+            "Zürich",
+            "ZURICH",
+            "zurich",
+            "Zurich (disambiguation)",
+            "Straße",
+            "Strasse",
+            "STRASSEN",
+            "Strass",
+            "Ärger",
+            "Aerger",
+            "Arger",
+            "Z",
+            "[[",
+            "~",
+            "é",
+            "ǅ",
+            "Ω",
+        ];
+        for left in titles {
+            for right in titles {
+                assert_eq!(
+                    cmp_search_keys(left, right),
+                    search_key_string(left).cmp(&search_key_string(right)),
+                    "{:?} and {:?}",
+                    left,
+                    right
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! The query language of searches. Besides words, which match the words of
+//! titles, queries can contain `"quoted phrases"`, exclusions like `-word`
+//! and filters like `intitle:`, `incategory:`, `linksto:`, `prefix:` and
+//! `ns:`. Queries without any of these are left to the prefix search of
+//! [`Index::search`].
+
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use thiserror::Error;
+
+use super::{
+    index::{Index, RankedResults},
+    links::{category_name, LinkIndex},
+    normalize::search_key_string,
+    siteinfo::SiteInfo,
+};
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("`{0}:` in the search query needs a value after it")]
+    MissingValue(String),
+
+    #[error("`-` in the search query needs something to exclude after it")]
+    MissingExclusion,
+
+    #[error("the search query only excludes, add something to search for")]
+    OnlyExclusions,
+
+    #[error("the search query filters by the unknown namespace `{0}`")]
+    UnknownNamespace(String),
+
+    #[error("`{0}:` needs a links file for the dump, see `wiki-rs build-links`")]
+    MissingLinks(String),
+}
+
+/// A parsed search query, whose clauses must all match a title.
+#[derive(Debug)]
+pub struct Query {
+    clauses: Vec<Clause>,
+}
+
+#[derive(Debug)]
+pub struct Clause {
+    /// Whether the clause must not match instead
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+#[derive(Debug)]
+pub enum Filter {
+    /// A word of the title starts with this
+    Word(String),
+    /// Consecutive words of the title are these
+    Phrase(String),
+    /// The title contains this anywhere
+    InTitle(String),
+    InCategory(String),
+    LinksTo(String),
+    /// The title starts with this
+    Prefix(String),
+    /// The title is in the namespace with this name or number
+    Namespace(String),
+}
+
+/// A clause resolved against a wiki, ready to be checked for every title.
+enum Matcher<'a> {
+    /// Search keys of consecutive words, the last of which only has to be a
+    /// prefix of a word unless the words are `exact`
+    Words {
+        words: Vec<String>,
+        exact: bool,
+    },
+    Contains(String),
+    StartsWith(String),
+    /// Sorted positions of the matching pages
+    Pages(&'a [u32]),
+    Namespace(i32),
+}
+
+impl Query {
+    /// Parses a query, which fails for filters without values and queries
+    /// that only exclude. A quote that is never closed is part of a word,
+    /// like the one in `12" single`.
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut clauses = Vec::new();
+        let mut rest = text.trim_start();
+
+        while !rest.is_empty() {
+            let negated = rest.starts_with('-');
+            if negated {
+                rest = &rest[1..];
+                if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                    return Err(QueryError::MissingExclusion);
+                }
+            }
+
+            let filter = match Self::operator(rest) {
+                // A prefix may contain spaces, so it takes the rest of the query
+                Some("prefix") => {
+                    let value = unquote(rest["prefix:".len()..].trim());
+                    rest = "";
+                    match value.is_empty() {
+                        true => return Err(QueryError::MissingValue("prefix".to_owned())),
+                        false => Filter::Prefix(value.to_owned()),
+                    }
+                }
+                Some(operator) => {
+                    let (value, _, next) = read_value(&rest[operator.len() + 1..]);
+                    rest = next;
+                    if value.is_empty() {
+                        return Err(QueryError::MissingValue(operator.to_owned()));
+                    }
+
+                    let value = value.to_owned();
+                    match operator {
+                        "intitle" => Filter::InTitle(value),
+                        "incategory" => Filter::InCategory(value),
+                        "linksto" => Filter::LinksTo(value),
+                        _ => Filter::Namespace(value),
+                    }
+                }
+                None => {
+                    let (value, quoted, next) = read_value(rest);
+                    rest = next;
+                    match (value.trim().is_empty(), quoted) {
+                        (true, _) => {
+                            rest = rest.trim_start();
+                            continue;
+                        }
+                        (false, true) => Filter::Phrase(value.to_owned()),
+                        (false, false) => Filter::Word(value.to_owned()),
+                    }
+                }
+            };
+
+            clauses.push(Clause { negated, filter });
+            rest = rest.trim_start();
+        }
+
+        if !clauses.is_empty() && clauses.iter().all(|clause| clause.negated) {
+            return Err(QueryError::OnlyExclusions);
+        }
+        Ok(Query { clauses })
+    }
+
+    /// Finds the operator a part of a query starts with, like `intitle` in
+    /// `intitle:rust`.
+    fn operator(text: &str) -> Option<&'static str> {
+        let (name, _) = text.split_once(':')?;
+        ["intitle", "incategory", "linksto", "prefix", "ns"]
+            .into_iter()
+            .find(|operator| name.eq_ignore_ascii_case(operator))
+    }
+
+    /// Whether the query is made of nothing but words, which are better
+    /// served by searching for titles starting with them.
+    pub fn is_plain(&self) -> bool {
+        self.clauses
+            .iter()
+            .all(|clause| !clause.negated && matches!(clause.filter, Filter::Word(_)))
+    }
+
+    /// Finds up to `limit` titles matching all clauses, best first, that
+    /// `is_after` returns true for. Titles are ranked like by [`Index::rank`],
+    /// counting the characters of the text they have to match.
+    pub fn execute<'a>(
+        &self,
+        index: &'a Index,
+        site_info: &SiteInfo,
+        links: Option<&LinkIndex>,
+        is_after: impl Fn(f64, usize) -> bool,
+        limit: usize,
+    ) -> Result<RankedResults<'a>, QueryError> {
+        let matchers = self
+            .clauses
+            .iter()
+            .map(|clause| {
+                let matcher = Matcher::resolve(&clause.filter, index, site_info, links)?;
+                Ok((clause.negated, matcher))
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+
+        // The narrowest of the filters that list their pages is where the
+        // titles to check come from, all titles if there is none
+        let candidates: Vec<usize> = match self.clauses.iter().find_map(|clause| match clause {
+            Clause {
+                negated: false,
+                filter: Filter::Prefix(prefix),
+            } => Some(prefix),
+            _ => None,
+        }) {
+            Some(prefix) => index.positions_starting_with(prefix).collect(),
+            None => match matchers
+                .iter()
+                .filter_map(|(negated, matcher)| match (negated, matcher) {
+                    (false, Matcher::Pages(pages)) => Some(pages),
+                    _ => None,
+                })
+                .min_by_key(|pages| pages.len())
+            {
+                Some(pages) => pages.iter().map(|&page| page as usize).collect(),
+                None => index.positions_starting_with("").collect(),
+            },
+        };
+
+        // The longest text that matching titles contain, which rules out most
+        // ASCII titles before their search keys are computed
+        let needle = matchers
+            .iter()
+            .filter_map(|(negated, matcher)| match (negated, matcher) {
+                (false, Matcher::Words { words, .. }) => words.iter().max_by_key(|word| word.len()),
+                (false, Matcher::Contains(text) | Matcher::StartsWith(text)) => Some(text),
+                _ => None,
+            })
+            .max_by_key(|needle| needle.len())
+            .filter(|needle| !needle.is_empty());
+
+        let entries = index.entries();
+        let matching: Vec<usize> = candidates
+            .into_par_iter()
+            .filter(|&position| {
+                let title = &entries[position].page_name;
+                // The search key of ASCII text is its lowercase form, so it
+                // contains the needle if the title does, ignoring case
+                if needle.is_some_and(|needle| {
+                    title.is_ascii() && !contains_ignoring_ascii_case(title, needle)
+                }) {
+                    return false;
+                }
+
+                let key = search_key_string(title);
+                matchers.iter().all(|(negated, matcher)| {
+                    matcher.matches(position, title, &key, site_info) != *negated
+                })
+            })
+            .collect();
+
+        let query_len = matchers
+            .iter()
+            .map(|(negated, matcher)| match (negated, matcher) {
+                (false, Matcher::Words { words, .. }) => {
+                    words.iter().map(|word| word.chars().count()).sum()
+                }
+                (false, Matcher::Contains(text) | Matcher::StartsWith(text)) => {
+                    text.chars().count()
+                }
+                _ => 0,
+            })
+            .sum();
+        Ok(index.rank(matching.into_iter(), query_len, is_after, limit))
+    }
+}
+
+impl<'a> Matcher<'a> {
+    fn resolve(
+        filter: &Filter,
+        index: &Index,
+        site_info: &SiteInfo,
+        links: Option<&'a LinkIndex>,
+    ) -> Result<Matcher<'a>, QueryError> {
+        let key = search_key_string;
+        let words = |text: &str| title_words(&key(text)).map(str::to_owned).collect();
+        let links =
+            |operator: &str| links.ok_or_else(|| QueryError::MissingLinks(operator.to_owned()));
+
+        Ok(match filter {
+            Filter::Word(word) => Matcher::Words {
+                words: words(word),
+                exact: false,
+            },
+            Filter::Phrase(phrase) => Matcher::Words {
+                words: words(phrase),
+                exact: true,
+            },
+            Filter::InTitle(text) => Matcher::Contains(key(text)),
+            Filter::Prefix(prefix) => Matcher::StartsWith(key(prefix)),
+            Filter::InCategory(category) => Matcher::Pages(
+                links("incategory")?.category_members(&category_name(category, site_info)),
+            ),
+            Filter::LinksTo(title) => {
+                let links = links("linksto")?;
+                match index.find_position(&site_info.normalize_title(title)) {
+                    Some(position) => Matcher::Pages(links.backlinks(position)),
+                    None => Matcher::Pages(&[]),
+                }
+            }
+            Filter::Namespace(name) => Matcher::Namespace(Self::namespace_key(name, site_info)?),
+        })
+    }
+
+    /// Finds a namespace by its number or name, where the main namespace is
+    /// called `main`.
+    fn namespace_key(name: &str, site_info: &SiteInfo) -> Result<i32, QueryError> {
+        let name = name.replace('_', " ");
+        if let Ok(key) = name.parse() {
+            if site_info.namespaces.iter().any(|ns| ns.key == key) {
+                return Ok(key);
+            }
+        }
+        if name.eq_ignore_ascii_case("main") {
+            return Ok(0);
+        }
+
+        site_info
+            .namespaces
+            .iter()
+            .find(|ns| ns.key != 0 && ns.name.eq_ignore_ascii_case(&name))
+            .map(|ns| ns.key)
+            .ok_or(QueryError::UnknownNamespace(name))
+    }
+
+    fn matches(&self, position: usize, title: &str, key: &str, site_info: &SiteInfo) -> bool {
+        match self {
+            Matcher::Words { words, exact } => {
+                let (last, init) = match words.split_last() {
+                    Some(split) => split,
+                    None => return true,
+                };
+                // Most titles don't contain the words at all, which is much
+                // cheaper to rule out than splitting them into words
+                if !words.iter().all(|word| key.contains(word.as_str())) {
+                    return false;
+                }
+
+                let title_words: Vec<&str> = title_words(key).collect();
+                title_words.windows(words.len()).any(|window| {
+                    window[..init.len()] == init[..]
+                        && match exact {
+                            true => window[init.len()] == last,
+                            false => window[init.len()].starts_with(last.as_str()),
+                        }
+                })
+            }
+            Matcher::Contains(text) => key.contains(text.as_str()),
+            Matcher::StartsWith(prefix) => key.starts_with(prefix.as_str()),
+            Matcher::Pages(pages) => pages.binary_search(&(position as u32)).is_ok(),
+            Matcher::Namespace(namespace) => {
+                site_info.namespace_of(title).map_or(0, |ns| ns.key) == *namespace
+            }
+        }
+    }
+}
+
+/// Reads a value up to the next whitespace, or up to the closing quote if
+/// it starts with one that is closed. Returns the value, whether it was
+/// quoted, and the rest of the query.
+fn read_value(text: &str) -> (&str, bool, &str) {
+    if let Some(quoted) = text.strip_prefix('"') {
+        if let Some(end) = quoted.find('"') {
+            return (&quoted[..end], true, &quoted[end + 1..]);
+        }
+    }
+
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (&text[..end], false, &text[end..])
+}
+
+/// Whether `text` contains `needle`, which must not be empty, ignoring the
+/// case of ASCII letters.
+fn contains_ignoring_ascii_case(text: &str, needle: &str) -> bool {
+    text.as_bytes()
+        .windows(needle.len())
+        .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+/// Splits a search key into words at everything but letters and digits.
+fn title_words(key: &str) -> impl Iterator<Item = &str> {
+    key.split(|chr: char| !chr.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs, process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::wiki::{
+        index::IndexEntry,
+        siteinfo::{CaseSensitivity, Namespace},
+    };
+
+    const TITLES: [&str; 9] = [
+        "Rust (programming language)",
+        "Rust Belt",
+        "Programming Rust",
+        "Zürich",
+        "Hauptstraße",
+        "Strasse Map",
+        "Talk:Rust",
+        "Category:Languages",
+        "12\" single",
+    ];
+
+    fn index() -> Index {
+        let entries = TITLES
+            .iter()
+            .enumerate()
+            .map(|(position, title)| IndexEntry {
+                offset: 0,
+                end_offset: None,
+                page_id: position as u64 + 1,
+                page_name: title.to_string(),
+            })
+            .collect();
+        Index::from_entries(entries)
+    }
+
+    fn site_info() -> SiteInfo {
+        let namespace = |key, name: &str| Namespace {
+            key,
+            name: name.to_owned(),
+            case: CaseSensitivity::FirstLetter,
+        };
+        SiteInfo {
+            site_name: "Wikipedia".to_owned(),
+            db_name: "enwiki".to_owned(),
+            base: "https://en.wikipedia.org/wiki/Main_Page".to_owned(),
+            generator: "MediaWiki".to_owned(),
+            case: CaseSensitivity::FirstLetter,
+            namespaces: vec![
+                namespace(0, ""),
+                namespace(1, "Talk"),
+                namespace(14, "Category"),
+            ],
+            language: None,
+        }
+    }
+
+    /// Links where `Rust (programming language)` and `Programming Rust`
+    /// are in `Category:Languages` and link to `Rust Belt`.
+    fn links(index: &Index) -> LinkIndex {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let file = FILES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("wiki-rs-query-{}-{}.links", process::id(), file));
+        fs::write(&path, "1\t2\tLanguages\n3\t2\tLanguages\n2\t\t\n").unwrap();
+        let links = LinkIndex::from_file(&path, index).unwrap();
+        fs::remove_file(&path).unwrap();
+        links
+    }
+
+    fn search(query: &str) -> Vec<String> {
+        let index = index();
+        let links = links(&index);
+        let query = Query::parse(query).expect("query parses");
+        let mut titles: Vec<String> = query
+            .execute(&index, &site_info(), Some(&links), |_, _| true, 100)
+            .unwrap()
+            .results
+            .iter()
+            .map(|result| result.entry.page_name.clone())
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn parse_reads_every_operator() {
+        let query = Query::parse(
+            "rust \"belt road\" -old intitle:lang INCATEGORY:\"Programming languages\" \
+             linksto:Rust ns:talk prefix:Rust (pro",
+        )
+        .unwrap();
+        let clauses: Vec<String> = query
+            .clauses
+            .iter()
+            .map(|clause| {
+                format!(
+                    "{}{:?}",
+                    if clause.negated { "-" } else { "" },
+                    clause.filter
+                )
+            })
+            .collect();
+        assert_eq!(
+            clauses,
+            [
+                "Word(\"rust\")",
+                "Phrase(\"belt road\")",
+                "-Word(\"old\")",
+                "InTitle(\"lang\")",
+                "InCategory(\"Programming languages\")",
+                "LinksTo(\"Rust\")",
+                "Namespace(\"talk\")",
+                "Prefix(\"Rust (pro\")",
+            ]
+        );
+        assert!(!query.is_plain());
+        assert!(Query::parse("rust belt").unwrap().is_plain());
+    }
+
+    #[test]
+    fn parse_takes_unclosed_quotes_literally() {
+        let query = Query::parse("12\" single").unwrap();
+        let clauses: Vec<String> = query
+            .clauses
+            .iter()
+            .map(|clause| format!("{:?}", clause.filter))
+            .collect();
+        assert_eq!(clauses, ["Word(\"12\\\"\")", "Word(\"single\")"]);
+
+        let query = Query::parse("\"rust belt").unwrap();
+        assert!(matches!(&query.clauses[0].filter, Filter::Word(word) if word == "\"rust"));
+        assert_eq!(search("\"rust belt"), ["Rust Belt"]);
+    }
+
+    #[test]
+    fn parse_rejects_empty_values() {
+        for query in [
+            "intitle:",
+            "intitle:\"\" rust",
+            "ns: rust",
+            "prefix:",
+            "prefix:\"\"",
+        ] {
+            assert!(
+                matches!(Query::parse(query), Err(QueryError::MissingValue(_))),
+                "{:?}",
+                query
+            );
+        }
+        for query in ["-", "rust - belt"] {
+            assert!(
+                matches!(Query::parse(query), Err(QueryError::MissingExclusion)),
+                "{:?}",
+                query
+            );
+        }
+        assert!(matches!(
+            Query::parse("-rust -belt"),
+            Err(QueryError::OnlyExclusions)
+        ));
+        assert!(Query::parse("\"\" rust").unwrap().is_plain());
+    }
+
+    #[test]
+    fn words_match_word_prefixes() {
+        assert_eq!(
+            search("rust prog"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+        assert_eq!(search("ust"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn phrases_match_consecutive_words() {
+        assert_eq!(search("\"programming rust\""), ["Programming Rust"]);
+        assert_eq!(search("\"rust prog\""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn exclusions_remove_matches() {
+        assert_eq!(
+            search("rust -belt -talk"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+    }
+
+    #[test]
+    fn filters_match_titles() {
+        assert_eq!(
+            search("intitle:gram"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+        assert_eq!(search("prefix:rust b"), ["Rust Belt"]);
+        assert_eq!(search("rust ns:talk"), ["Talk:Rust"]);
+        assert_eq!(search("rust ns:1"), ["Talk:Rust"]);
+        assert_eq!(
+            search("prog ns:main"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+        assert!(matches!(
+            Query::parse("ns:nowhere").unwrap().execute(
+                &index(),
+                &site_info(),
+                None,
+                |_, _| true,
+                10
+            ),
+            Err(QueryError::UnknownNamespace(_))
+        ));
+    }
+
+    #[test]
+    fn filters_match_links() {
+        assert_eq!(
+            search("incategory:languages"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+        assert_eq!(
+            search("incategory:Category:Languages -belt"),
+            ["Programming Rust", "Rust (programming language)"]
+        );
+        assert_eq!(search("linksto:rust_Belt -language"), ["Programming Rust"]);
+        assert_eq!(search("linksto:Nowhere"), Vec::<String>::new());
+        assert!(matches!(
+            Query::parse("linksto:Rust").unwrap().execute(
+                &index(),
+                &site_info(),
+                None,
+                |_, _| true,
+                10
+            ),
+            Err(QueryError::MissingLinks(_))
+        ));
+    }
+
+    #[test]
+    fn prefilter_keeps_folded_matches() {
+        // ASCII queries match titles with accents, and queries with
+        // accents or ß match ASCII titles
+        assert_eq!(search("zurich"), ["Zürich"]);
+        assert_eq!(search("intitle:ZURICH"), ["Zürich"]);
+        assert_eq!(search("intitle:strasse"), ["Hauptstraße", "Strasse Map"]);
+        assert_eq!(search("intitle:straße"), ["Hauptstraße", "Strasse Map"]);
+        assert_eq!(search("\"STRASSE map\""), ["Strasse Map"]);
+        assert_eq!(search("prefix:Zü"), ["Zürich"]);
+    }
+}
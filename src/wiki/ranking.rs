@@ -0,0 +1,514 @@
+//! Importance of articles, used to rank search results. It is computed
+//! offline from the link graph of a dump into a ranks file, which can be
+//! combined with a file of pageviews when the dump is loaded.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+use thiserror::Error;
+
+use super::siteinfo::SiteInfo;
+
+/// Probability of following a link rather than jumping to a random page.
+const DAMPING: f64 = 0.85;
+
+/// PageRank is iterated until the ranks of all pages together change by
+/// less than `PAGERANK_TOLERANCE`, but at most `MAX_PAGERANK_ITERATIONS`
+/// times.
+const PAGERANK_TOLERANCE: f64 = 1e-6;
+const MAX_PAGERANK_ITERATIONS: usize = 100;
+
+#[derive(Error, Debug)]
+pub enum RankingError {
+    #[error("invalid line {0} in ranks file: {1}")]
+    InvalidRank(usize, String),
+
+    #[error("invalid line {0} in pageviews file: {1}")]
+    InvalidPageviews(usize, String),
+}
+
+/// Link statistics of an article.
+#[derive(Debug, Clone, Copy)]
+pub struct Rank {
+    pub page_id: u64,
+    /// Number of articles linking to it
+    pub links: u32,
+    /// Number of redirects to it
+    pub redirects: u32,
+    /// PageRank relative to the average, so 1 is an average article
+    pub pagerank: f32,
+}
+
+/// Ranks of the articles of a dump, looked up by page id.
+#[derive(Default)]
+pub struct Ranks {
+    /// Sorted by page id
+    ranks: Vec<Rank>,
+}
+
+/// The links of an article, as positions of the pages they point to.
+pub struct PageLinks {
+    pub page: u32,
+    /// Page that the article redirects to instead of having content
+    pub redirect: Option<u32>,
+    pub links: Vec<u32>,
+}
+
+impl Rank {
+    fn parse(line: &str) -> Option<Rank> {
+        let mut fields = line.split(':');
+        let rank = Rank {
+            page_id: fields.next()?.parse().ok()?,
+            links: fields.next()?.parse().ok()?,
+            redirects: fields.next()?.parse().ok()?,
+            pagerank: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(rank)
+    }
+}
+
+impl Ranks {
+    /// Reads a ranks file with lines of `page_id:links:redirects:pagerank`.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Ranks> {
+        let mut ranks = Vec::new();
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            match Rank::parse(&line) {
+                Some(rank) => ranks.push(rank),
+                None => bail!(RankingError::InvalidRank(number + 1, line)),
+            }
+        }
+
+        ranks.sort_unstable_by_key(|rank| rank.page_id);
+        Ok(Ranks { ranks })
+    }
+
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for rank in &self.ranks {
+            writeln!(
+                writer,
+                "{}:{}:{}:{}",
+                rank.page_id, rank.links, rank.redirects, rank.pagerank
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn get(&self, page_id: u64) -> Option<&Rank> {
+        self.ranks
+            .binary_search_by_key(&page_id, |rank| rank.page_id)
+            .ok()
+            .map(|idx| &self.ranks[idx])
+    }
+
+    /// Computes the ranks of `page_count` pages from their links. Links to
+    /// redirects count for the page they redirect to, and redirects pass
+    /// their PageRank on to it. Only pages in `articles` are ranked.
+    pub fn compute(page_count: usize, page_links: &[PageLinks], articles: &[(u32, u64)]) -> Ranks {
+        let mut redirect_of: Vec<Option<u32>> = vec![None; page_count];
+        for page in page_links {
+            redirect_of[page.page as usize] = page.redirect;
+        }
+        let resolve = |page: u32| redirect_of[page as usize].unwrap_or(page);
+
+        let mut links = vec![0u32; page_count];
+        let mut redirects = vec![0u32; page_count];
+        // Outgoing edges of every page, counting each target only once
+        let mut edges: Vec<Vec<u32>> = vec![Vec::new(); page_count];
+        for page in page_links {
+            match page.redirect {
+                Some(target) => {
+                    let target = resolve(target);
+                    if target != page.page {
+                        redirects[target as usize] += 1;
+                        edges[page.page as usize] = vec![target];
+                    }
+                }
+                None => {
+                    let mut targets: Vec<u32> = page
+                        .links
+                        .iter()
+                        .map(|&target| resolve(target))
+                        .filter(|&target| target != page.page)
+                        .collect();
+                    targets.sort_unstable();
+                    targets.dedup();
+                    for &target in &targets {
+                        links[target as usize] += 1;
+                    }
+                    edges[page.page as usize] = targets;
+                }
+            }
+        }
+
+        let pagerank = Self::pagerank(&edges);
+
+        let mut ranks: Vec<Rank> = articles
+            .iter()
+            .map(|&(page, page_id)| Rank {
+                page_id,
+                links: links[page as usize],
+                redirects: redirects[page as usize],
+                pagerank: (pagerank[page as usize] * page_count as f64) as f32,
+            })
+            .collect();
+        ranks.sort_unstable_by_key(|rank| rank.page_id);
+        Ranks { ranks }
+    }
+
+    /// Computes the PageRank of every page by power iteration until it
+    /// converges. The rank of pages without links is spread evenly over all
+    /// pages.
+    fn pagerank(edges: &[Vec<u32>]) -> Vec<f64> {
+        let count = edges.len();
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let mut ranks = vec![1.0 / count as f64; count];
+        for _ in 0..MAX_PAGERANK_ITERATIONS {
+            let mut next = vec![0.0; count];
+            let mut dangling = 0.0;
+            for (page, targets) in edges.iter().enumerate() {
+                if targets.is_empty() {
+                    dangling += ranks[page];
+                    continue;
+                }
+
+                let share = ranks[page] / targets.len() as f64;
+                for &target in targets {
+                    next[target as usize] += share;
+                }
+            }
+
+            let base = (1.0 - DAMPING + DAMPING * dangling) / count as f64;
+            for rank in &mut next {
+                *rank = base + DAMPING * *rank;
+            }
+            let change: f64 = ranks
+                .iter()
+                .zip(&next)
+                .map(|(rank, next)| (rank - next).abs())
+                .sum();
+            ranks = next;
+            if change < PAGERANK_TOLERANCE {
+                break;
+            }
+        }
+
+        ranks
+    }
+}
+
+/// Names of a wiki in the project column of pageview files: `de.wikipedia`,
+/// and `de` and `de.m` for its desktop and mobile site as in the pageview
+/// dumps of Wikimedia, where other projects have a suffix like `de.d` for
+/// `de.wiktionary`.
+pub fn pageview_projects(site_info: &SiteInfo) -> Vec<String> {
+    let language = site_info.language();
+    let project = site_info.project();
+    let suffix = match project {
+        "wikibooks" => ".b",
+        "wiktionary" => ".d",
+        "wikinews" => ".n",
+        "wikiquote" => ".q",
+        "wikisource" => ".s",
+        "wikiversity" => ".v",
+        "wikivoyage" => ".voy",
+        _ => "",
+    };
+    vec![
+        format!("{}.{}", language, project),
+        format!("{}{}", language, suffix),
+        format!("{}.m{}", language, suffix),
+    ]
+}
+
+/// Reads a pageviews file into the views of each title, see
+/// [`read_pageviews`].
+pub fn read_pageviews_file(
+    path: impl AsRef<Path>,
+    projects: &[String],
+) -> anyhow::Result<HashMap<String, u64>> {
+    read_pageviews(BufReader::new(File::open(path)?), projects)
+}
+
+/// Reads pageviews into the views of each title. Lines are either
+/// `<title> <views>` or, as in the pageview dumps of Wikimedia,
+/// `<project> <title> <views> ...`, which only count if the project is one
+/// of `projects`, see [`pageview_projects`]. Views of the same title are
+/// summed.
+pub fn read_pageviews(
+    reader: impl BufRead,
+    projects: &[String],
+) -> anyhow::Result<HashMap<String, u64>> {
+    let mut views: HashMap<String, u64> = HashMap::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (title, count) = match fields[..] {
+            [] => continue,
+            [title, count] => (title, count),
+            [project, title, count, ..] => match projects.iter().any(|known| known == project) {
+                true => (title, count),
+                false => continue,
+            },
+            _ => bail!(RankingError::InvalidPageviews(number + 1, line)),
+        };
+        let count: u64 = match count.parse() {
+            Ok(count) => count,
+            Err(_) => bail!(RankingError::InvalidPageviews(number + 1, line)),
+        };
+
+        *views.entry(title.replace('_', " ")).or_default() += count;
+    }
+
+    Ok(views)
+}
+
+/// Finds the targets of the internal links in wikitext, like `Target` in
+/// `[[Target|label]]`, without their section anchors.
+pub fn extract_links(wikitext: &str) -> Vec<&str> {
+    let mut links = Vec::new();
+    let mut rest = wikitext;
+
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let end = rest.find(['|', ']', '\n']).unwrap_or(rest.len());
+        let target = rest[..end].split('#').next().unwrap_or_default().trim();
+        if !target.is_empty() {
+            links.push(target);
+        }
+        rest = &rest[end..];
+    }
+
+    links
+}
+
+/// Combines the signals of how important an article is into a single
+/// number, which is 0 for an article nothing is known about. Each signal is
+/// counted logarithmically, so that no single one dominates.
+pub fn importance(rank: Option<&Rank>, views: Option<u64>) -> f32 {
+    let mut importance = 0.0;
+    if let Some(rank) = rank {
+        importance += (rank.links as f32).ln_1p();
+        importance += 2.0 * (rank.redirects as f32).ln_1p();
+        importance += rank.pagerank.max(0.0).ln_1p();
+    }
+    if let Some(views) = views {
+        importance += (views as f32).ln_1p();
+    }
+    importance
+}
+
+/// Scores a title matching a query by its importance and by how much of it
+/// the query covers, from the whole title down to a short prefix of it.
+pub fn score(query_len: usize, title_len: usize, importance: f32) -> f64 {
+    let quality = match title_len {
+        0 => 1.0,
+        _ => (query_len.max(1) as f64 / title_len as f64).min(1.0),
+    };
+    quality * (1.0 + importance as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wiki::siteinfo::CaseSensitivity;
+
+    fn page(page: u32, redirect: Option<u32>, links: &[u32]) -> PageLinks {
+        PageLinks {
+            page,
+            redirect,
+            links: links.to_vec(),
+        }
+    }
+
+    fn site_info(db_name: &str) -> SiteInfo {
+        SiteInfo {
+            site_name: "Wikipedia".to_owned(),
+            db_name: db_name.to_owned(),
+            base: "http://localhost/wiki/Main_Page".to_owned(),
+            generator: "MediaWiki 1.40".to_owned(),
+            case: CaseSensitivity::FirstLetter,
+            namespaces: Vec::new(),
+            language: None,
+        }
+    }
+
+    #[test]
+    fn pagerank_converges() {
+        // 0 and 1 link to each other, and 2 links to 0. Solving the PageRank
+        // equations for them gives these ranks
+        let ranks = Ranks::pagerank(&[vec![1], vec![0], vec![0]]);
+        let expected = [0.135 / 0.2775, 0.05 + 0.85 * 0.135 / 0.2775, 0.05];
+        for (rank, expected) in ranks.iter().zip(expected) {
+            assert!((rank - expected).abs() < 1e-5, "{:?}", ranks);
+        }
+        assert!((ranks.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pagerank_spreads_pages_without_links() {
+        let ranks = Ranks::pagerank(&[vec![], vec![], vec![], vec![]]);
+        for rank in &ranks {
+            assert!((rank - 0.25).abs() < 1e-9, "{:?}", ranks);
+        }
+        assert!(Ranks::pagerank(&[]).is_empty());
+    }
+
+    #[test]
+    fn redirects_count_for_their_targets() {
+        // 1 and 3 redirect to 0, 4 redirects to the redirect 1 and 5 to
+        // itself. 2 links to 0 twice and to it through 1
+        let pages = [
+            page(0, None, &[2]),
+            page(1, Some(0), &[]),
+            page(2, None, &[0, 0, 1]),
+            page(3, Some(0), &[]),
+            page(4, Some(1), &[]),
+            page(5, Some(5), &[]),
+        ];
+        let ranks = Ranks::compute(6, &pages, &[(0, 100), (2, 102), (5, 105)]);
+
+        let first = ranks.get(100).unwrap();
+        assert_eq!((first.links, first.redirects), (1, 3));
+        let second = ranks.get(102).unwrap();
+        assert_eq!((second.links, second.redirects), (1, 0));
+        assert_eq!(ranks.get(105).unwrap().redirects, 0);
+        // Redirects are not ranked themselves
+        assert!(ranks.get(101).is_none());
+        // The redirects pass their rank on, so the first page ranks highest
+        assert!(first.pagerank > second.pagerank);
+        assert!(second.pagerank > ranks.get(105).unwrap().pagerank);
+    }
+
+    #[test]
+    fn pageview_lines_are_parsed() {
+        let projects = pageview_projects(&site_info("enwiki"));
+        let views = read_pageviews(
+            "Rust_(programming_language) 10\n\
+             \n\
+             en Rust_(programming_language) 5 0\n\
+             en.m Rust_(programming_language) 7 0\n\
+             en.wikipedia Rust 1 desktop\n"
+                .as_bytes(),
+            &projects,
+        )
+        .unwrap();
+
+        assert_eq!(views.len(), 2);
+        assert_eq!(views["Rust (programming language)"], 22);
+        assert_eq!(views["Rust"], 1);
+
+        for line in ["Rust", "Rust many", "en Rust -1 0"] {
+            let err = read_pageviews(line.as_bytes(), &projects).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref(),
+                    Some(RankingError::InvalidPageviews(1, _))
+                ),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn pageviews_of_other_wikis_are_skipped() {
+        let lines = "de Rust 1 0\n\
+                     en Rust 2 0\n\
+                     en.d Rust 4 0\n\
+                     en.m.d Rust 8 0\n\
+                     en.wiktionary Rust 16 0\n\
+                     en.m.b Rust 32 0\n\
+                     commons.m Rust 64 0\n";
+        let views = |db_name| {
+            read_pageviews(lines.as_bytes(), &pageview_projects(&site_info(db_name)))
+                .unwrap()
+                .get("Rust")
+                .copied()
+        };
+
+        assert_eq!(views("enwiki"), Some(2));
+        assert_eq!(views("dewiki"), Some(1));
+        assert_eq!(views("enwiktionary"), Some(4 + 8 + 16));
+        assert_eq!(views("frwiki"), None);
+    }
+
+    #[test]
+    fn links_are_extracted() {
+        let wikitext = "See [[Rust (language)|Rust]] and [[Cargo#Usage]], \
+                        [[ Crates ]], [[:Category:Languages]], [[]] and [[#Top]].\n\
+                        [[Broken\nlink]] [[Unclosed";
+        assert_eq!(
+            extract_links(wikitext),
+            [
+                "Rust (language)",
+                "Cargo",
+                "Crates",
+                ":Category:Languages",
+                "Broken",
+                "Unclosed"
+            ]
+        );
+    }
+
+    #[test]
+    fn importance_grows_with_every_signal() {
+        let rank = |links, redirects, pagerank| Rank {
+            page_id: 1,
+            links,
+            redirects,
+            pagerank,
+        };
+
+        assert_eq!(importance(None, None), 0.0);
+        let base = importance(Some(&rank(10, 1, 1.0)), None);
+        assert!(base > 0.0);
+        assert!(importance(Some(&rank(100, 1, 1.0)), None) > base);
+        assert!(importance(Some(&rank(10, 2, 1.0)), None) > base);
+        assert!(importance(Some(&rank(10, 1, 2.0)), None) > base);
+        assert!(importance(Some(&rank(10, 1, 1.0)), Some(1000)) > base);
+        // A negative PageRank from a broken file doesn't make it a NaN
+        assert!(importance(Some(&rank(0, 0, -1.0)), None) == 0.0);
+    }
+
+    #[test]
+    fn score_prefers_covered_and_important_titles() {
+        assert_eq!(score(4, 4, 0.0), 1.0);
+        assert_eq!(score(2, 4, 0.0), 0.5);
+        assert_eq!(score(8, 4, 0.0), 1.0);
+        assert_eq!(score(0, 4, 0.0), 0.25);
+        assert_eq!(score(0, 0, 0.0), 1.0);
+        assert_eq!(score(2, 4, 1.0), 1.0);
+        assert!(score(2, 4, 3.0) > score(4, 4, 0.0));
+    }
+
+    #[test]
+    fn ranks_survive_round_trip() {
+        let ranks = Ranks::compute(
+            2,
+            &[page(0, None, &[1]), page(1, None, &[0])],
+            &[(0, 7), (1, 3)],
+        );
+        let path = std::env::temp_dir().join(format!("wiki-rs-ranks-{}.txt", std::process::id()));
+        ranks.write_to_file(&path).unwrap();
+        let read = Ranks::from_file(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let read = read.unwrap();
+        for page_id in [3, 7] {
+            let (rank, read) = (ranks.get(page_id).unwrap(), read.get(page_id).unwrap());
+            assert_eq!((read.links, read.redirects), (rank.links, rank.redirects));
+            assert_eq!(read.pagerank, rank.pagerank);
+        }
+    }
+}
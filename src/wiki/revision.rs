@@ -0,0 +1,300 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use quick_xml::{
+    escape::unescape,
+    events::{BytesStart, Event},
+    reader::Reader,
+};
+
+use super::article::ArticleError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Contributor {
+    User {
+        id: u64,
+        name: String,
+    },
+    Ip(String),
+    /// The contributor was hidden by revision deletion.
+    Deleted,
+}
+
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub id: u64,
+    pub parent_id: Option<u64>,
+    pub timestamp: DateTime<Utc>,
+    pub contributor: Contributor,
+    /// `None` when the revision has no comment or it was deleted.
+    pub comment: Option<String>,
+    pub minor: bool,
+    pub model: String,
+    pub format: String,
+    pub sha1: String,
+}
+
+impl Contributor {
+    pub fn display_name(&self) -> &str {
+        match self {
+            Contributor::User { name, .. } => name,
+            Contributor::Ip(ip) => ip,
+            Contributor::Deleted => "(username removed)",
+        }
+    }
+
+    fn parse(reader: &mut Reader<&[u8]>) -> anyhow::Result<Contributor> {
+        let mut id = None;
+        let mut name = None;
+        let mut ip = None;
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => match start.local_name().as_ref() {
+                    b"username" => name = Some(read_string(reader, &start)?),
+                    b"id" => id = Some(read_string(reader, &start)?.parse()?),
+                    b"ip" => ip = Some(read_string(reader, &start)?),
+                    _ => {
+                        reader.read_to_end(start.name())?;
+                    }
+                },
+                Event::End(end) if end.local_name().as_ref() == b"contributor" => break,
+                Event::Eof => bail!(ArticleError::MissingProperty("contributor".to_owned())),
+                _ => {}
+            }
+        }
+
+        Ok(match (name, id, ip) {
+            (Some(name), Some(id), _) => Contributor::User { id, name },
+            (_, _, Some(ip)) => Contributor::Ip(ip),
+            _ => Contributor::Deleted,
+        })
+    }
+}
+
+impl Revision {
+    /// Parses the `<revision>` the reader is positioned in, returning its
+    /// metadata and, if `with_text` is set, its wikitext. Otherwise the text
+    /// is skipped without being copied, which matters for history dumps
+    /// where pages have thousands of revisions.
+    pub(super) fn parse(
+        reader: &mut Reader<&[u8]>,
+        with_text: bool,
+    ) -> anyhow::Result<(Revision, Option<String>)> {
+        let mut id = None;
+        let mut parent_id = None;
+        let mut timestamp = None;
+        let mut contributor = None;
+        let mut comment = None;
+        let mut minor = false;
+        let mut model = None;
+        let mut format = None;
+        let mut sha1 = None;
+        let mut text = None;
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => match start.local_name().as_ref() {
+                    b"id" => id = Some(read_string(reader, &start)?.parse()?),
+                    b"parentid" => parent_id = Some(read_string(reader, &start)?.parse()?),
+                    b"timestamp" => timestamp = Some(read_string(reader, &start)?.parse()?),
+                    b"contributor" => contributor = Some(Contributor::parse(reader)?),
+                    b"comment" => comment = Some(read_string(reader, &start)?),
+                    b"model" => model = Some(read_string(reader, &start)?),
+                    b"format" => format = Some(read_string(reader, &start)?),
+                    b"sha1" => sha1 = Some(read_string(reader, &start)?),
+                    b"text" if with_text => text = Some(read_string(reader, &start)?),
+                    b"minor" => {
+                        minor = true;
+                        reader.read_to_end(start.name())?;
+                    }
+                    _ => {
+                        reader.read_to_end(start.name())?;
+                    }
+                },
+                Event::Empty(empty) => match empty.local_name().as_ref() {
+                    b"contributor" => contributor = Some(Contributor::Deleted),
+                    b"minor" => minor = true,
+                    b"text" if with_text => text = Some(String::new()),
+                    b"sha1" => sha1 = Some(String::new()),
+                    _ => {}
+                },
+                Event::End(end) if end.local_name().as_ref() == b"revision" => break,
+                Event::Eof => bail!(ArticleError::MissingProperty("revision".to_owned())),
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| ArticleError::MissingProperty(name.to_owned());
+        let revision = Revision {
+            id: id.ok_or_else(|| missing("revision id"))?,
+            parent_id,
+            timestamp: timestamp.ok_or_else(|| missing("timestamp"))?,
+            contributor: contributor.ok_or_else(|| missing("contributor"))?,
+            comment,
+            minor,
+            model: model.unwrap_or_else(|| "wikitext".to_owned()),
+            format: format.unwrap_or_else(|| "text/x-wiki".to_owned()),
+            sha1: sha1.unwrap_or_default(),
+        };
+        if with_text && text.is_none() {
+            bail!(missing("text"));
+        }
+
+        Ok((revision, text))
+    }
+}
+
+/// Reads the text content of the element that was just started and
+/// resolves its XML entities.
+fn read_string(reader: &mut Reader<&[u8]>, start: &BytesStart) -> anyhow::Result<String> {
+    let raw = reader.read_text(start.name())?;
+    Ok(unescape(&raw)?.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the `<revision>` in `xml`, with its text.
+    fn parse(xml: &str) -> anyhow::Result<(Revision, Option<String>)> {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) if start.local_name().as_ref() == b"revision" => break,
+                Event::Eof => panic!("no revision in {}", xml),
+                _ => {}
+            }
+        }
+        Revision::parse(&mut reader, true)
+    }
+
+    fn revision(contributor: &str, rest: &str) -> Revision {
+        let xml = format!(
+            "<revision><id>2</id><parentid>1</parentid><timestamp>2021-01-23T15:15:01Z</timestamp>{}{}<text>Text</text></revision>",
+            contributor, rest
+        );
+        parse(&xml).unwrap().0
+    }
+
+    #[test]
+    fn users_are_read() {
+        let revision = revision(
+            "<contributor><username>A &amp; B</username><id>42</id></contributor>",
+            "<comment>Fixed &lt;ref&gt;</comment>",
+        );
+        assert_eq!(revision.id, 2);
+        assert_eq!(revision.parent_id, Some(1));
+        assert_eq!(revision.timestamp.to_rfc3339(), "2021-01-23T15:15:01+00:00");
+        assert_eq!(
+            revision.contributor,
+            Contributor::User {
+                id: 42,
+                name: "A & B".to_owned()
+            }
+        );
+        assert_eq!(revision.contributor.display_name(), "A & B");
+        assert_eq!(revision.comment.as_deref(), Some("Fixed <ref>"));
+        assert!(!revision.minor);
+    }
+
+    #[test]
+    fn ip_contributors_are_read() {
+        let revision = revision("<contributor><ip>2001:db8::1</ip></contributor>", "");
+        assert_eq!(
+            revision.contributor,
+            Contributor::Ip("2001:db8::1".to_owned())
+        );
+        assert_eq!(revision.contributor.display_name(), "2001:db8::1");
+    }
+
+    #[test]
+    fn deleted_contributors_are_read() {
+        for contributor in [
+            r#"<contributor deleted="deleted" />"#,
+            r#"<contributor deleted="deleted"></contributor>"#,
+            // A name without an id is as good as none
+            "<contributor><username>Someone</username></contributor>",
+        ] {
+            let revision = revision(contributor, "");
+            assert_eq!(
+                revision.contributor,
+                Contributor::Deleted,
+                "{}",
+                contributor
+            );
+            assert_eq!(revision.contributor.display_name(), "(username removed)");
+        }
+    }
+
+    #[test]
+    fn minor_edits_are_read() {
+        let contributor = "<contributor><ip>127.0.0.1</ip></contributor>";
+        assert!(revision(contributor, "<minor />").minor);
+        assert!(revision(contributor, "<minor></minor>").minor);
+        assert!(!revision(contributor, "").minor);
+    }
+
+    #[test]
+    fn deleted_comments_and_text_are_empty() {
+        let (revision, text) = parse(
+            r#"<revision>
+                <id>2</id>
+                <timestamp>2021-01-23T15:15:01Z</timestamp>
+                <contributor deleted="deleted" />
+                <comment deleted="deleted" />
+                <model>wikitext</model>
+                <format>text/x-wiki</format>
+                <text bytes="1234" deleted="deleted" />
+                <sha1 />
+              </revision>"#,
+        )
+        .unwrap();
+        assert_eq!(revision.comment, None);
+        assert_eq!(revision.sha1, "");
+        assert_eq!(text.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn model_and_format_have_defaults() {
+        let contributor = "<contributor><ip>127.0.0.1</ip></contributor>";
+        let defaults = revision(contributor, "<sha1>abc</sha1>");
+        assert_eq!(defaults.model, "wikitext");
+        assert_eq!(defaults.format, "text/x-wiki");
+        assert_eq!(defaults.sha1, "abc");
+
+        let css = revision(contributor, "<model>css</model><format>text/css</format>");
+        assert_eq!(css.model, "css");
+        assert_eq!(css.format, "text/css");
+    }
+
+    #[test]
+    fn incomplete_revisions_are_rejected() {
+        for (xml, missing) in [
+            (
+                "<revision><timestamp>2021-01-23T15:15:01Z</timestamp><contributor deleted=\"deleted\" /><text /></revision>",
+                "revision id",
+            ),
+            (
+                "<revision><id>1</id><contributor deleted=\"deleted\" /><text /></revision>",
+                "timestamp",
+            ),
+            (
+                "<revision><id>1</id><timestamp>2021-01-23T15:15:01Z</timestamp><text /></revision>",
+                "contributor",
+            ),
+            (
+                "<revision><id>1</id><timestamp>2021-01-23T15:15:01Z</timestamp><contributor deleted=\"deleted\" /></revision>",
+                "text",
+            ),
+            ("<revision><id>1</id>", "revision"),
+        ] {
+            let err = parse(xml).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(ArticleError::MissingProperty(name)) if name == missing),
+                "{}: {:?}",
+                xml,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! The pages that revisions belong to, which are found by scanning a dump
+//! offline into a revisions file, so that a revision can be opened by its id
+//! alone.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+use thiserror::Error;
+
+use super::index::Index;
+
+#[derive(Error, Debug)]
+pub enum RevisionsError {
+    #[error("invalid line {0} in revisions file: {1}")]
+    InvalidLine(usize, String),
+
+    #[error("revisions without a title need a revisions file for the dump, see `wiki-rs build-index --revisions`")]
+    MissingRevisionsFile,
+}
+
+/// Revisions by their ids, with the positions in the index of the pages
+/// they belong to.
+pub struct RevisionIndex {
+    /// Revision ids, sorted
+    revisions: Vec<u64>,
+    /// Position in the index of the page of each of the revisions
+    pages: Vec<u32>,
+}
+
+impl RevisionIndex {
+    /// Reads a revisions file with lines of `revision_id:page_id`. Pages
+    /// missing from `index` are left out.
+    pub fn from_file(path: impl AsRef<Path>, index: &Index) -> anyhow::Result<RevisionIndex> {
+        // Positions of the entries ordered by page id
+        let mut by_page_id: Vec<(u64, u32)> = index
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.page_id, position as u32))
+            .collect();
+        by_page_id.sort_unstable();
+        let position_of = |page_id: u64| {
+            by_page_id
+                .binary_search_by_key(&page_id, |&(page_id, _)| page_id)
+                .ok()
+                .map(|idx| by_page_id[idx].1)
+        };
+
+        let mut revisions = Vec::new();
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let parsed = line
+                .split_once(':')
+                .and_then(|(revision, page)| Some((revision.parse().ok()?, page.parse().ok()?)));
+            let (revision, page_id): (u64, u64) = match parsed {
+                Some(parsed) => parsed,
+                None => bail!(RevisionsError::InvalidLine(number + 1, line)),
+            };
+            if let Some(page) = position_of(page_id) {
+                revisions.push((revision, page));
+            }
+        }
+
+        revisions.sort_unstable();
+        Ok(RevisionIndex {
+            pages: revisions.iter().map(|&(_, page)| page).collect(),
+            revisions: revisions
+                .into_iter()
+                .map(|(revision, _)| revision)
+                .collect(),
+        })
+    }
+
+    /// Writes the revisions of pages, given as pairs of revision and page
+    /// ids, in the format that [`RevisionIndex::from_file`] reads.
+    pub fn write_to_file(path: impl AsRef<Path>, revisions: &[(u64, u64)]) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (revision, page_id) in revisions {
+            writeln!(writer, "{}:{}", revision, page_id)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Position in the index of the page a revision belongs to.
+    pub fn page_of(&self, revision_id: u64) -> Option<usize> {
+        self.revisions
+            .binary_search(&revision_id)
+            .ok()
+            .map(|idx| self.pages[idx] as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::wiki::index::IndexEntry;
+
+    /// Writes revisions to a file of their own and reads them back.
+    fn round_trip(revisions: &[(u64, u64)], index: &Index) -> anyhow::Result<RevisionIndex> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "wiki-rs-revisions-{}-{}.txt",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        RevisionIndex::write_to_file(&path, revisions)?;
+        let result = RevisionIndex::from_file(&path, index);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    fn index() -> Index {
+        let entry = |offset, page_id, page_name: &str| IndexEntry {
+            offset,
+            end_offset: None,
+            page_id,
+            page_name: page_name.to_owned(),
+        };
+        Index::from_entries(vec![
+            entry(10, 12, "Anarchism"),
+            entry(10, 25, "Autism"),
+            entry(20, 39, "Albedo"),
+        ])
+    }
+
+    #[test]
+    fn revisions_survive_round_trip() {
+        let revisions = round_trip(
+            &[(1001, 39), (900, 12), (901, 12), (1200, 25), (77, 404)],
+            &index(),
+        )
+        .unwrap();
+
+        assert_eq!(revisions.page_of(900), Some(0));
+        assert_eq!(revisions.page_of(901), Some(0));
+        assert_eq!(revisions.page_of(1200), Some(1));
+        assert_eq!(revisions.page_of(1001), Some(2));
+        // Pages missing from the index are left out
+        assert_eq!(revisions.page_of(77), None);
+        assert_eq!(revisions.page_of(902), None);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        let path = std::env::temp_dir().join(format!(
+            "wiki-rs-revisions-invalid-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "900:12\n901\n").unwrap();
+        let err = RevisionIndex::from_file(&path, &index()).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RevisionsError::InvalidLine(2, line)) if line == "901"
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::bail;
+use minidom::Element;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+use url::{Host, Url};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    FirstLetter,
+    CaseSensitive,
+}
+
+#[derive(Debug, Clone)]
+pub struct Namespace {
+    pub key: i32,
+    pub name: String,
+    pub case: CaseSensitivity,
+}
+
+#[derive(Debug, Clone)]
+pub struct SiteInfo {
+    pub site_name: String,
+    pub db_name: String,
+    pub base: String,
+    pub generator: String,
+    pub case: CaseSensitivity,
+    pub namespaces: Vec<Namespace>,
+    /// Language code set in the config, which takes precedence over the
+    /// one derived from the base URL.
+    pub language: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum SiteInfoError {
+    #[error("dump does not start with a siteinfo block")]
+    MissingSiteInfo,
+
+    #[error("missing property on siteinfo")]
+    MissingProperty(String),
+}
+
+impl CaseSensitivity {
+    fn parse(value: &str) -> CaseSensitivity {
+        match value {
+            "case-sensitive" => CaseSensitivity::CaseSensitive,
+            _ => CaseSensitivity::FirstLetter,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaseSensitivity::FirstLetter => "first-letter",
+            CaseSensitivity::CaseSensitive => "case-sensitive",
+        }
+    }
+}
+
+impl SiteInfo {
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        let start = xml.find("<siteinfo>");
+        let end = xml.find("</siteinfo>");
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end + "</siteinfo>".len()),
+            _ => bail!(SiteInfoError::MissingSiteInfo),
+        };
+
+        let reconstructed_xml = xml[start..end].replacen("<siteinfo>", "<siteinfo xmlns=\"\">", 1);
+        let root: Element = reconstructed_xml.parse()?;
+
+        let child_text = |name: &str| match root.get_child(name, "") {
+            Some(child) => anyhow::Ok(child.text()),
+            None => bail!(SiteInfoError::MissingProperty(name.to_owned())),
+        };
+
+        let site_name = child_text("sitename")?;
+        let db_name = child_text("dbname")?;
+        let base = child_text("base")?;
+        let generator = child_text("generator")?;
+        let case = CaseSensitivity::parse(&child_text("case")?);
+
+        let namespaces = match root.get_child("namespaces", "") {
+            Some(namespaces) => namespaces
+                .children()
+                .map(Self::parse_namespace)
+                .collect::<anyhow::Result<Vec<Namespace>>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            site_name,
+            db_name,
+            base,
+            generator,
+            case,
+            namespaces,
+            language: None,
+        })
+    }
+
+    fn parse_namespace(element: &Element) -> anyhow::Result<Namespace> {
+        let key = match element.attr("key") {
+            Some(key) => key.parse()?,
+            None => bail!(SiteInfoError::MissingProperty("namespace key".to_owned())),
+        };
+        let case = CaseSensitivity::parse(element.attr("case").unwrap_or_default());
+
+        Ok(Namespace {
+            key,
+            name: element.text(),
+            case,
+        })
+    }
+
+    /// Language code of the wiki, taken from the subdomain of the base URL
+    /// (`https://de.wikipedia.org/...` is `de`) unless it is configured.
+    /// Base URLs without such a subdomain, like `http://localhost/` or
+    /// `https://www.mediawiki.org/`, fall back to the database name
+    /// (`dewiki` is `de`).
+    pub fn language(&self) -> String {
+        if let Some(language) = &self.language {
+            return language.clone();
+        }
+
+        let url = Url::parse(&self.base).ok();
+        let subdomain = match url.as_ref().and_then(|url| url.host()) {
+            Some(Host::Domain(domain)) if domain.split('.').count() >= 3 => {
+                domain.split('.').next()
+            }
+            _ => None,
+        };
+        let is_language = |code: &&str| {
+            !code.is_empty()
+                && *code != "www"
+                && code
+                    .chars()
+                    .all(|chr| chr.is_ascii_lowercase() || chr == '-')
+        };
+
+        let db_suffix = match self.project() {
+            "wikipedia" => "wiki",
+            project => project,
+        };
+        match subdomain.filter(is_language) {
+            Some(subdomain) => subdomain.to_owned(),
+            None => self
+                .db_name
+                .strip_suffix(db_suffix)
+                .unwrap_or(&self.db_name)
+                .to_owned(),
+        }
+    }
+
+    /// Wikimedia project of the wiki, like `wikipedia` or `wiktionary`,
+    /// taken from the end of the database name (`dewiktionary` is
+    /// `wiktionary`, while `dewiki` is `wikipedia`).
+    pub fn project(&self) -> &'static str {
+        const PROJECTS: &[&str] = &[
+            "wikibooks",
+            "wikinews",
+            "wikiquote",
+            "wikisource",
+            "wikiversity",
+            "wikivoyage",
+            "wiktionary",
+        ];
+        PROJECTS
+            .iter()
+            .copied()
+            .find(|project| self.db_name.ends_with(project))
+            .unwrap_or("wikipedia")
+    }
+
+    /// Finds the namespace a title belongs to by its `Prefix:`. Titles
+    /// without a known prefix are in the main namespace.
+    pub fn namespace_of(&self, title: &str) -> Option<&Namespace> {
+        let main_namespace = self.namespaces.iter().find(|ns| ns.key == 0);
+
+        let prefix = match title.find(':') {
+            Some(idx) => &title[..idx],
+            None => return main_namespace,
+        };
+
+        self.namespaces
+            .iter()
+            .find(|ns| ns.key != 0 && ns.name.eq_ignore_ascii_case(prefix))
+            .or(main_namespace)
+    }
+
+    /// Converts a title from a URL into the form used in the dump, which is
+    /// in NFC like all MediaWiki titles, applying the case rules of the
+    /// namespace it is in.
+    pub fn normalize_title(&self, title: &str) -> String {
+        let title = title.nfc().collect::<String>().replace('_', " ");
+        let title = title.trim();
+
+        let namespace = self.namespace_of(title);
+        let (prefix, name) = match namespace {
+            Some(ns) if ns.key != 0 => title.split_at(ns.name.len() + 1),
+            _ => ("", title),
+        };
+        let case = namespace.map(|ns| ns.case).unwrap_or(self.case);
+
+        match case {
+            CaseSensitivity::CaseSensitive => title.to_owned(),
+            CaseSensitivity::FirstLetter => {
+                let mut chars = name.chars();
+                match chars.next() {
+                    Some(first) => format!("{}{}{}", prefix, first.to_uppercase(), chars.as_str()),
+                    None => title.to_owned(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site_info(case: &str, base: &str) -> SiteInfo {
+        SiteInfo::parse(&format!(
+            r#"<mediawiki><siteinfo>
+                <sitename>Wikipedia</sitename>
+                <dbname>dewiki</dbname>
+                <base>{}</base>
+                <generator>MediaWiki 1.40</generator>
+                <case>{}</case>
+                <namespaces>
+                    <namespace key="0" case="{}" />
+                    <namespace key="1" case="first-letter">Diskussion</namespace>
+                    <namespace key="14" case="case-sensitive">Kategorie</namespace>
+                </namespaces>
+            </siteinfo><page>"#,
+            base, case, case
+        ))
+        .expect("siteinfo parses")
+    }
+
+    #[test]
+    fn parse_reads_siteinfo() {
+        let site_info = site_info("first-letter", "https://de.wikipedia.org/wiki/Wikipedia");
+        assert_eq!(site_info.site_name, "Wikipedia");
+        assert_eq!(site_info.db_name, "dewiki");
+        assert_eq!(site_info.generator, "MediaWiki 1.40");
+        assert_eq!(site_info.case, CaseSensitivity::FirstLetter);
+        let namespaces: Vec<(i32, &str)> = site_info
+            .namespaces
+            .iter()
+            .map(|ns| (ns.key, ns.name.as_str()))
+            .collect();
+        assert_eq!(namespaces, [(0, ""), (1, "Diskussion"), (14, "Kategorie")]);
+
+        assert!(SiteInfo::parse("<mediawiki><page>").is_err());
+        assert!(SiteInfo::parse("<siteinfo><sitename>A</sitename></siteinfo>").is_err());
+    }
+
+    #[test]
+    fn first_letter_wikis_capitalize_titles() {
+        let site_info = site_info("first-letter", "https://de.wikipedia.org/wiki/Wikipedia");
+        assert_eq!(site_info.normalize_title("berlin_Mitte "), "Berlin Mitte");
+        assert_eq!(site_info.normalize_title("über"), "Über");
+        assert_eq!(site_info.normalize_title("ßtraße"), "SStraße");
+        // Titles from URLs may come decomposed, while dumps are in NFC
+        assert_eq!(site_info.normalize_title("u\u{0308}ber"), "Über");
+        assert_eq!(
+            site_info.normalize_title("Diskussion:berlin"),
+            "Diskussion:Berlin"
+        );
+        assert_eq!(site_info.normalize_title(""), "");
+    }
+
+    #[test]
+    fn case_sensitive_wikis_keep_titles() {
+        let site_info = site_info("case-sensitive", "https://en.wiktionary.org/wiki/Main");
+        assert_eq!(site_info.case, CaseSensitivity::CaseSensitive);
+        assert_eq!(site_info.normalize_title("über_alles"), "über alles");
+        // Namespaces have their own case rules
+        assert_eq!(
+            site_info.normalize_title("Diskussion:über"),
+            "Diskussion:Über"
+        );
+        assert_eq!(
+            site_info.normalize_title("Kategorie:über"),
+            "Kategorie:über"
+        );
+    }
+
+    #[test]
+    fn namespace_of_finds_prefix() {
+        let site_info = site_info("first-letter", "https://de.wikipedia.org/wiki/Wikipedia");
+        let key = |title| site_info.namespace_of(title).map(|ns| ns.key);
+        assert_eq!(key("Kategorie:Berlin"), Some(14));
+        assert_eq!(key("kategorie:Berlin"), Some(14));
+        assert_eq!(key("Berlin"), Some(0));
+        assert_eq!(key("Star Trek: Picard"), Some(0));
+    }
+
+    #[test]
+    fn language_comes_from_subdomain() {
+        let language = |base| site_info("first-letter", base).language();
+        assert_eq!(language("https://de.wikipedia.org/wiki/Wikipedia"), "de");
+        assert_eq!(
+            language("https://zh-min-nan.wikipedia.org/wiki/Thâu-ia̍h"),
+            "zh-min-nan"
+        );
+        assert_eq!(language("https://en.m.wikipedia.org/wiki/Main_Page"), "en");
+    }
+
+    #[test]
+    fn language_falls_back_to_db_name() {
+        let language = |base| site_info("first-letter", base).language();
+        for base in [
+            "http://localhost:8080/wiki/Main_Page",
+            "http://127.0.0.1/wiki/Main_Page",
+            "https://wikipedia.org/wiki/Main_Page",
+            "https://www.mediawiki.org/wiki/MediaWiki",
+            "https://WIKI01.example.org/",
+            "Main Page",
+        ] {
+            assert_eq!(language(base), "de", "{}", base);
+        }
+
+        let mut site_info = site_info("first-letter", "http://localhost/");
+        site_info.db_name = "dewiktionary".to_owned();
+        assert_eq!(site_info.language(), "de");
+        site_info.language = Some("gsw".to_owned());
+        assert_eq!(site_info.language(), "gsw");
+    }
+
+    #[test]
+    fn project_comes_from_db_name() {
+        let mut site_info = site_info("first-letter", "http://localhost/");
+        assert_eq!(site_info.project(), "wikipedia");
+        site_info.db_name = "enwiktionary".to_owned();
+        assert_eq!(site_info.project(), "wiktionary");
+        site_info.db_name = "zh_min_nanwikisource".to_owned();
+        assert_eq!(site_info.project(), "wikisource");
+    }
+}
@@ -0,0 +1,359 @@
+//! Summaries of articles for search results: their short description, the
+//! start of their lead and the section matching the words of a query. The
+//! text is taken from the wikitext by dropping the markup that isn't prose,
+//! like templates, tables and references.
+
+use super::{article::Article, normalize::search_key};
+
+/// Words shown in a snippet of an article's text.
+const SNIPPET_WORDS: usize = 30;
+/// Words shown before the first match in a snippet around matches.
+const CONTEXT_WORDS: usize = 8;
+
+pub struct Summary {
+    /// Title of the page the article redirects to, in which case it has no
+    /// text to summarize
+    pub redirect: Option<String>,
+    /// Description from the `{{Short description}}` template
+    pub short_description: Option<String>,
+    /// Start of the text before the first heading
+    pub lead: String,
+    /// First part of the article containing all the words looked for
+    pub text_match: Option<TextMatch>,
+}
+
+pub struct TextMatch {
+    /// Heading of the section, `None` for the lead
+    pub heading: Option<String>,
+    /// Text of the section around the first of the words
+    pub snippet: String,
+}
+
+/// A part of an article, from a heading up to the next one.
+struct Section<'a> {
+    heading: Option<String>,
+    wikitext: &'a str,
+}
+
+impl Summary {
+    /// Summarizes an article, looking for the first section that contains a
+    /// word starting with each of `terms` like titles do in a search.
+    pub fn of(article: &Article, terms: &[&str]) -> Summary {
+        if let Some(redirect) = &article.redirect {
+            return Summary {
+                redirect: Some(redirect.clone()),
+                short_description: None,
+                lead: String::new(),
+                text_match: None,
+            };
+        }
+
+        let sections = sections(&article.body);
+        let lead = match sections.first() {
+            Some(section) if section.heading.is_none() => {
+                let text = plain_text(section.wikitext);
+                let words: Vec<&str> = text.split_whitespace().collect();
+                snippet(&words, 0)
+            }
+            _ => String::new(),
+        };
+
+        let terms: Vec<String> = terms
+            .iter()
+            .map(|term| search_key(term).collect())
+            .filter(|term: &String| !term.is_empty())
+            .collect();
+        let text_match = match terms.is_empty() {
+            true => None,
+            false => sections
+                .into_iter()
+                .find_map(|section| find_terms(section, &terms)),
+        };
+
+        Summary {
+            redirect: None,
+            short_description: short_description(&article.body),
+            lead,
+            text_match,
+        }
+    }
+}
+
+/// Converts a heading into the anchor that links to its section, the way
+/// MediaWiki does.
+pub fn section_anchor(heading: &str) -> String {
+    heading.trim().replace(' ', "_")
+}
+
+/// Finds the argument of the `{{Short description|...}}` template.
+fn short_description(wikitext: &str) -> Option<String> {
+    let mut rest = wikitext;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let end = rest.find("}}")?;
+        let (name, description) = match rest[..end].split_once('|') {
+            Some(template) => template,
+            None => continue,
+        };
+
+        if name.trim().eq_ignore_ascii_case("short description") {
+            let description = plain_text(description);
+            let description = description.trim();
+            // `{{Short description|none}}` marks articles that don't need one
+            return match description.eq_ignore_ascii_case("none") || description.is_empty() {
+                true => None,
+                false => Some(description.to_owned()),
+            };
+        }
+    }
+
+    None
+}
+
+/// Splits wikitext at the lines with headings like `== Heading ==`.
+fn sections(wikitext: &str) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut heading = None;
+    let mut start = 0;
+
+    let mut line_start = 0;
+    for line in wikitext.split_inclusive('\n') {
+        if let Some(text) = parse_heading(line) {
+            sections.push(Section {
+                heading: heading.take(),
+                wikitext: &wikitext[start..line_start],
+            });
+            heading = Some(text);
+            start = line_start + line.len();
+        }
+        line_start += line.len();
+    }
+    sections.push(Section {
+        heading,
+        wikitext: &wikitext[start..],
+    });
+
+    sections
+}
+
+/// Reads the text of a heading, `None` if the line isn't one. A line of only
+/// `=` has no text between the two runs of them, so it isn't a heading.
+fn parse_heading(line: &str) -> Option<String> {
+    let line = line.trim();
+    let level = line.len() - line.trim_start_matches('=').len();
+    if level < 2 || line.len() < 2 * level || line.len() - line.trim_end_matches('=').len() < level
+    {
+        return None;
+    }
+
+    let text = plain_text(line[level..line.len() - level].trim());
+    match text.trim() {
+        "" => None,
+        text => Some(text.to_owned()),
+    }
+}
+
+/// Finds the first word of a section that starts with a term, if every
+/// term starts some word in it.
+fn find_terms(section: Section, terms: &[String]) -> Option<TextMatch> {
+    let text = plain_text(section.wikitext);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let keys: Vec<String> = words
+        .iter()
+        .map(|word| search_key(word.trim_matches(|chr: char| !chr.is_alphanumeric())).collect())
+        .collect();
+
+    let mut first = None;
+    for term in terms {
+        let position = keys.iter().position(|key| key.starts_with(term.as_str()))?;
+        first = Some(first.map_or(position, |first: usize| first.min(position)));
+    }
+
+    let first = first?;
+    Some(TextMatch {
+        heading: section.heading,
+        snippet: snippet(&words, first.saturating_sub(CONTEXT_WORDS)),
+    })
+}
+
+/// Joins the words of a snippet starting at `start`, marking where words
+/// were left out.
+fn snippet(words: &[&str], start: usize) -> String {
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "… ");
+    }
+    if end < words.len() {
+        snippet.push_str(" …");
+    }
+    snippet
+}
+
+/// Strips the markup from wikitext, keeping the text of links and dropping
+/// templates, tables, references, files, categories and comments.
+fn plain_text(wikitext: &str) -> String {
+    let mut text = String::new();
+    let mut rest = wikitext;
+
+    while let Some(chr) = rest.chars().next() {
+        if rest.starts_with("<!--") {
+            rest = after(rest, "-->");
+        } else if rest.starts_with("{{") {
+            rest = after_nested(rest, "{{", "}}");
+        } else if rest.starts_with("{|") && (text.is_empty() || text.ends_with('\n')) {
+            rest = after_nested(rest, "{|", "|}");
+        } else if rest.starts_with("[[") {
+            let end = rest.len() - after_nested(rest, "[[", "]]").len();
+            let inner = rest[2..end].trim_end_matches(']');
+            // Links with a prefix are files, categories and links to other
+            // wikis rather than prose
+            let target = inner.split('|').next().unwrap_or_default();
+            if !target.contains(':') || target.starts_with(':') {
+                let label = inner.rsplit('|').next().unwrap_or_default();
+                text.push_str(&plain_text(label.trim_start_matches(':')));
+            }
+            rest = &rest[end..];
+        } else if rest.starts_with('[')
+            && (rest[1..].starts_with("http") || rest[1..].starts_with("//"))
+        {
+            let end = rest.find(']').map_or(rest.len(), |end| end + 1);
+            if let Some((_, label)) = rest[1..end].trim_end_matches(']').split_once(' ') {
+                text.push_str(&plain_text(label));
+            }
+            rest = &rest[end..];
+        } else if rest.starts_with("<ref") {
+            let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            rest = match rest[..tag_end].ends_with("/>") {
+                true => &rest[tag_end..],
+                false => after(rest, "</ref>"),
+            };
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|chr: char| chr == '/' || chr.is_ascii_alphabetic())
+        {
+            // Other tags are dropped, but not the text inside them
+            match rest.find('>') {
+                Some(end) => rest = &rest[end + 1..],
+                None => {
+                    text.push(chr);
+                    rest = &rest[1..];
+                }
+            }
+        } else if rest.starts_with("''") {
+            rest = rest.trim_start_matches('\'');
+        } else if rest.starts_with("__")
+            && rest[2..].find("__").is_some_and(|end| {
+                end > 0 && rest[2..2 + end].chars().all(|chr| chr.is_ascii_uppercase())
+            })
+        {
+            // Magic words like __TOC__
+            rest = after(&rest[2..], "__");
+        } else {
+            text.push(chr);
+            rest = &rest[chr.len_utf8()..];
+        }
+    }
+
+    text
+}
+
+/// Skips past the first `end`, or to the end of the text if there is none.
+fn after<'a>(text: &'a str, end: &str) -> &'a str {
+    match text.find(end) {
+        Some(idx) => &text[idx + end.len()..],
+        None => "",
+    }
+}
+
+/// Skips past the `close` matching the `open` that `text` starts with.
+fn after_nested<'a>(text: &'a str, open: &str, close: &str) -> &'a str {
+    let mut depth = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with(open) {
+            depth += 1;
+            rest = &rest[open.len()..];
+        } else if rest.starts_with(close) {
+            depth -= 1;
+            rest = &rest[close.len()..];
+            if depth == 0 {
+                return rest;
+            }
+        } else {
+            let chr = rest.chars().next().unwrap_or_default();
+            rest = &rest[chr.len_utf8()..];
+        }
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_heading_reads_text() {
+        assert_eq!(parse_heading("== a ==\n"), Some("a".to_owned()));
+        assert_eq!(parse_heading("===[[Link|b]]==="), Some("b".to_owned()));
+    }
+
+    #[test]
+    fn parse_heading_rejects_other_lines() {
+        for line in ["==", "===", "=====", "=a=", "== ==", "==a=", "text"] {
+            assert_eq!(parse_heading(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn sections_split_at_headings() {
+        let sections = sections("Lead\n== One ==\nFirst\n==\n=== Two ===\nSecond");
+        let headings: Vec<_> = sections.iter().map(|s| s.heading.as_deref()).collect();
+        assert_eq!(headings, [None, Some("One"), Some("Two")]);
+        assert_eq!(sections[0].wikitext, "Lead\n");
+        assert_eq!(sections[1].wikitext, "First\n==\n");
+        assert_eq!(sections[2].wikitext, "Second");
+    }
+
+    #[test]
+    fn short_description_is_plain_text() {
+        let wikitext = "{{Infobox}}{{Short description|A [[test]] page}}";
+        assert_eq!(short_description(wikitext).as_deref(), Some("A test page"));
+        assert_eq!(short_description("{{short description|none}}"), None);
+        assert_eq!(short_description("No templates"), None);
+    }
+
+    #[test]
+    fn plain_text_drops_markup() {
+        let wikitext = "'''Bold''' [[Target|label]]<ref name=\"a\">Ref</ref> \
+                        [[File:A.png|thumb]]{{cite}}<!-- comment -->[https://a.org site]";
+        assert_eq!(plain_text(wikitext), "Bold label site");
+    }
+
+    #[test]
+    fn find_terms_needs_every_term() {
+        let keys = |terms: &[&str]| -> Vec<String> {
+            terms
+                .iter()
+                .map(|term| search_key(term).collect())
+                .collect()
+        };
+        let section = || Section {
+            heading: Some("History".to_owned()),
+            wikitext: "It was first written in Ürdingen.",
+        };
+
+        let text_match = find_terms(section(), &keys(&["urd", "writ"])).expect("terms are found");
+        assert_eq!(text_match.heading.as_deref(), Some("History"));
+        assert_eq!(text_match.snippet, "It was first written in Ürdingen.");
+        assert!(find_terms(section(), &keys(&["writ", "missing"])).is_none());
+    }
+
+    #[test]
+    fn snippet_marks_left_out_words() {
+        let words: Vec<String> = (0..40).map(|n| n.to_string()).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let snippet = snippet(&words, 5);
+        assert!(snippet.starts_with("… 5 6"));
+        assert!(snippet.ends_with("33 34 …"));
+    }
+}